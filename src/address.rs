@@ -0,0 +1,77 @@
+use crate::hash::sha256d;
+use crate::network::Network;
+use crate::transaction::Script;
+use bech32::{segwit, Fe32, Hrp};
+
+/// Encodes the address paying to a standard output script, or `None` for
+/// scripts without one such as `OP_RETURN` outputs.
+pub fn from_script(script: &Script, network: Network) -> Option<String> {
+    let payload = script.payload()?;
+    if script.is_p2pkh() {
+        Some(base58check(p2pkh_prefix(network), payload))
+    } else if script.is_p2sh() {
+        Some(base58check(p2sh_prefix(network), payload))
+    } else {
+        let version = Fe32::try_from(script.as_bytes()[0].saturating_sub(0x50)).ok()?;
+        segwit::encode(hrp(network), version, payload).ok()
+    }
+}
+
+/// Decodes an address of any network to the output script it pays to.
+pub fn to_script(address: &str) -> Option<Script> {
+    if let Ok((_, version, program)) = segwit::decode(address) {
+        let version = version.to_u8();
+        let mut script = Script(vec![if version == 0 { 0x00 } else { 0x50 + version }]);
+        script.push_slice(&program);
+        return Some(script);
+    }
+
+    let data = bs58::decode(address).into_vec().ok()?;
+    if data.len() != 25 || sha256d(&data[..21])[..4] != data[21..] {
+        return None;
+    }
+    let (prefix, hash) = (data[0], &data[1..21]);
+    let mut script = Script::new();
+    if prefix == 0x00 || prefix == 0x6f {
+        script.0.extend_from_slice(&[0x76, 0xa9]);
+        script.push_slice(hash);
+        script.0.extend_from_slice(&[0x88, 0xac]);
+    } else if prefix == 0x05 || prefix == 0xc4 {
+        script.0.push(0xa9);
+        script.push_slice(hash);
+        script.0.push(0x87);
+    } else {
+        return None;
+    }
+    Some(script)
+}
+
+fn base58check(prefix: u8, payload: &[u8]) -> String {
+    let mut data = vec![prefix];
+    data.extend_from_slice(payload);
+    let checksum = sha256d(&data);
+    data.extend_from_slice(&checksum[..4]);
+    bs58::encode(data).into_string()
+}
+
+fn hrp(network: Network) -> Hrp {
+    match network {
+        Network::Bitcoin => bech32::hrp::BC,
+        Network::Testnet | Network::Testnet4 | Network::Signet => bech32::hrp::TB,
+        Network::Regtest => bech32::hrp::BCRT,
+    }
+}
+
+fn p2pkh_prefix(network: Network) -> u8 {
+    match network {
+        Network::Bitcoin => 0x00,
+        _ => 0x6f,
+    }
+}
+
+fn p2sh_prefix(network: Network) -> u8 {
+    match network {
+        Network::Bitcoin => 0x05,
+        _ => 0xc4,
+    }
+}
@@ -0,0 +1,418 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const SAT_PER_BTC: u64 = 100_000_000;
+
+/// An amount of bitcoin, stored as an integer number of satoshis.
+///
+/// Bitcoin Core represents amounts as BTC values with 8 decimal places. In JSON
+/// they are (de)serialized as such, while [`Amount::from_btc_str`] parses the
+/// decimal text exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(u64);
+
+/// Errors returned when parsing an [`Amount`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseAmountError {
+    /// The value is negative.
+    Negative,
+    /// The value exceeds the 21 million BTC supply.
+    TooBig,
+    /// The value has more than 8 decimal places.
+    TooPrecise,
+    /// The input is not a number.
+    InvalidFormat,
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseAmountError::Negative => write!(f, "amount is negative"),
+            ParseAmountError::TooBig => write!(f, "amount exceeds the maximum supply"),
+            ParseAmountError::TooPrecise => write!(f, "amount has more than 8 decimal places"),
+            ParseAmountError::InvalidFormat => write!(f, "invalid amount format"),
+        }
+    }
+}
+
+impl std::error::Error for ParseAmountError {}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const ONE_SAT: Amount = Amount(1);
+    pub const ONE_BTC: Amount = Amount(SAT_PER_BTC);
+    /// The maximum amount that can ever exist, 21 million BTC.
+    pub const MAX_MONEY: Amount = Amount(21_000_000 * SAT_PER_BTC);
+
+    pub const fn from_sat(sat: u64) -> Amount {
+        Amount(sat)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Converts a BTC float, as found in Bitcoin Core's JSON, to an amount.
+    ///
+    /// Every value with at most 8 decimals below [`Amount::MAX_MONEY`] maps to a
+    /// distinct `f64`, so rounding to the nearest satoshi recovers it exactly.
+    pub fn from_btc(btc: f64) -> Result<Amount, ParseAmountError> {
+        if !btc.is_finite() {
+            return Err(ParseAmountError::InvalidFormat);
+        }
+        if btc < 0.0 {
+            return Err(ParseAmountError::Negative);
+        }
+
+        let sat = (btc * SAT_PER_BTC as f64).round();
+        if sat > Amount::MAX_MONEY.0 as f64 {
+            return Err(ParseAmountError::TooBig);
+        }
+        Ok(Amount(sat as u64))
+    }
+
+    /// Converts the amount to a BTC float for use in JSON-RPC requests.
+    pub fn to_btc(self) -> f64 {
+        self.0 as f64 / SAT_PER_BTC as f64
+    }
+
+    /// Parses a decimal BTC value such as `"0.1"` or `"0.00010000"` exactly.
+    pub fn from_btc_str(s: &str) -> Result<Amount, ParseAmountError> {
+        let s = s.trim();
+        if s.starts_with('-') {
+            return Err(ParseAmountError::Negative);
+        }
+
+        let (whole, fraction) = match s.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (s, ""),
+        };
+        if whole.is_empty() && fraction.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(ParseAmountError::InvalidFormat);
+        }
+        if fraction.len() > 8 {
+            return Err(ParseAmountError::TooPrecise);
+        }
+
+        let whole: u64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| ParseAmountError::TooBig)?
+        };
+        let fraction: u64 = format!("{:0<8}", fraction).parse().unwrap_or_default();
+
+        let sat = whole
+            .checked_mul(SAT_PER_BTC)
+            .and_then(|sat| sat.checked_add(fraction))
+            .ok_or(ParseAmountError::TooBig)?;
+        if sat > Amount::MAX_MONEY.0 {
+            return Err(ParseAmountError::TooBig);
+        }
+        Ok(Amount(sat))
+    }
+
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, rhs: u64) -> Option<Amount> {
+        self.0.checked_mul(rhs).map(Amount)
+    }
+
+    pub fn checked_div(self, rhs: u64) -> Option<Amount> {
+        self.0.checked_div(rhs).map(Amount)
+    }
+
+    /// Sums the amounts, returning `None` on overflow.
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Amount> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, Amount::checked_add)
+    }
+
+    /// Formats the amount in satoshis, e.g. `10000 sat`.
+    pub fn display_sat(self) -> String {
+        format!("{} sat", self.0)
+    }
+}
+
+/// Formats the amount in BTC with 8 decimals, e.g. `0.00010000 BTC`.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:08} BTC",
+            self.0 / SAT_PER_BTC,
+            self.0 % SAT_PER_BTC
+        )
+    }
+}
+
+/// Parses `"<btc>"`, `"<btc> BTC"` or `"<sat> sat"`.
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(sat) = s.strip_suffix("sat").or_else(|| s.strip_suffix("sats")) {
+            let sat: u64 = sat
+                .trim()
+                .parse()
+                .map_err(|_| ParseAmountError::InvalidFormat)?;
+            if sat > Amount::MAX_MONEY.0 {
+                return Err(ParseAmountError::TooBig);
+            }
+            return Ok(Amount(sat));
+        }
+
+        Amount::from_btc_str(s.strip_suffix("BTC").unwrap_or(s))
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_btc())
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let btc = f64::deserialize(deserializer)?;
+        Amount::from_btc(btc).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A fee rate, stored in satoshis per 1000 virtual bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    pub const ZERO: FeeRate = FeeRate(0);
+
+    pub const fn from_sat_per_kvb(sat_per_kvb: u64) -> FeeRate {
+        FeeRate(sat_per_kvb)
+    }
+
+    /// The rate of `sat_per_vb`, `None` if it overflows.
+    pub const fn from_sat_per_vb(sat_per_vb: u64) -> Option<FeeRate> {
+        match sat_per_vb.checked_mul(1000) {
+            Some(sat_per_kvb) => Some(FeeRate(sat_per_kvb)),
+            None => None,
+        }
+    }
+
+    pub const fn to_sat_per_kvb(self) -> u64 {
+        self.0
+    }
+
+    /// The rate in sat/vB, as taken by the `fee_rate` RPC options.
+    pub fn to_sat_per_vb(self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+
+    /// The fee for a transaction of `vsize` virtual bytes, rounded up, `None`
+    /// if it overflows.
+    pub fn fee_for_vsize(self, vsize: u64) -> Option<Amount> {
+        self.0
+            .checked_mul(vsize)
+            .map(|fee| Amount(fee.div_ceil(1000)))
+    }
+
+    /// The fee rate paid by `fee` for `vsize` virtual bytes.
+    pub fn from_fee(fee: Amount, vsize: u64) -> Option<FeeRate> {
+        fee.0.checked_mul(1000)?.checked_div(vsize).map(FeeRate)
+    }
+}
+
+/// Formats the rate in sat/vB, e.g. `2.5 sat/vB`.
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat/vB", self.to_sat_per_vb())
+    }
+}
+
+/// Serializes the rate as a number of sat/vB.
+impl Serialize for FeeRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_sat_per_vb())
+    }
+}
+
+impl<'de> Deserialize<'de> for FeeRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sat_per_vb = f64::deserialize(deserializer)?;
+        if !sat_per_vb.is_finite() || sat_per_vb < 0.0 {
+            return Err(serde::de::Error::custom(format!(
+                "invalid fee rate {}",
+                sat_per_vb
+            )));
+        }
+        Ok(FeeRate((sat_per_vb * 1000.0).round() as u64))
+    }
+}
+
+/// Parses a rate in sat/vB with up to three decimals, e.g. `2.5`.
+impl FromStr for FeeRate {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_suffix("sat/vB").unwrap_or(s).trim();
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(ParseAmountError::InvalidFormat);
+        }
+        if fraction.len() > 3 {
+            return Err(ParseAmountError::TooPrecise);
+        }
+
+        let whole: u64 = whole.parse().map_err(|_| ParseAmountError::TooBig)?;
+        let fraction: u64 = format!("{:0<3}", fraction).parse().unwrap_or_default();
+        whole
+            .checked_mul(1000)
+            .and_then(|rate| rate.checked_add(fraction))
+            .map(FeeRate)
+            .ok_or(ParseAmountError::TooBig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn btc_strings_convert_exactly() {
+        assert_eq!(Amount::from_btc_str("1"), Ok(Amount::ONE_BTC));
+        assert_eq!(
+            Amount::from_btc_str("0.1"),
+            Ok(Amount::from_sat(10_000_000))
+        );
+        assert_eq!(
+            Amount::from_btc_str("0.00010000"),
+            Ok(Amount::from_sat(10_000))
+        );
+        assert_eq!(Amount::from_btc_str(".00000001"), Ok(Amount::ONE_SAT));
+        assert_eq!(
+            Amount::from_btc_str("20999999.99999999"),
+            Ok(Amount::from_sat(2_099_999_999_999_999))
+        );
+        assert_eq!(Amount::from_btc_str("21000000"), Ok(Amount::MAX_MONEY));
+        assert_eq!("0.5 BTC".parse(), Ok(Amount::from_sat(50_000_000)));
+        assert_eq!("1500 sat".parse(), Ok(Amount::from_sat(1500)));
+        assert_eq!("1500 sats".parse(), Ok(Amount::from_sat(1500)));
+    }
+
+    #[test]
+    fn invalid_btc_strings() {
+        assert_eq!(
+            Amount::from_btc_str("0.000000001"),
+            Err(ParseAmountError::TooPrecise)
+        );
+        assert_eq!(
+            Amount::from_btc_str("-0.1"),
+            Err(ParseAmountError::Negative)
+        );
+        assert_eq!(
+            Amount::from_btc_str("21000000.00000001"),
+            Err(ParseAmountError::TooBig)
+        );
+        assert_eq!(
+            Amount::from_btc_str("184467440737.09551616"),
+            Err(ParseAmountError::TooBig)
+        );
+        assert_eq!(
+            Amount::from_btc_str("99999999999999999999"),
+            Err(ParseAmountError::TooBig)
+        );
+        assert_eq!(
+            Amount::from_btc_str(""),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            Amount::from_btc_str("."),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            Amount::from_btc_str("1e-8"),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            "2100000000000001 sat".parse::<Amount>(),
+            Err(ParseAmountError::TooBig)
+        );
+    }
+
+    #[test]
+    fn btc_floats() {
+        assert_eq!(Amount::from_btc(0.1), Ok(Amount::from_sat(10_000_000)));
+        assert_eq!(Amount::from_btc(-0.1), Err(ParseAmountError::Negative));
+        assert_eq!(
+            Amount::from_btc(21_000_001.0),
+            Err(ParseAmountError::TooBig)
+        );
+        assert_eq!(
+            Amount::from_btc(f64::NAN),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(
+            Amount::from_btc(f64::INFINITY),
+            Err(ParseAmountError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn amounts_round_trip_through_json() {
+        for sat in [
+            0,
+            1,
+            10_000,
+            12_345_678,
+            2_099_999_999_999_999,
+            Amount::MAX_MONEY.to_sat(),
+        ] {
+            let amount = Amount::from_sat(sat);
+            let json = serde_json::to_string(&amount).unwrap();
+            assert_eq!(serde_json::from_str::<Amount>(&json).unwrap(), amount);
+        }
+        assert_eq!(
+            serde_json::to_string(&Amount::from_sat(10_000)).unwrap(),
+            "0.0001"
+        );
+        assert!(serde_json::from_str::<Amount>("-0.1").is_err());
+        assert!(serde_json::from_str::<Amount>("21000001").is_err());
+    }
+
+    #[test]
+    fn fee_rates() {
+        assert_eq!("2.5".parse(), Ok(FeeRate::from_sat_per_kvb(2500)));
+        assert_eq!("1 sat/vB".parse(), Ok(FeeRate::from_sat_per_kvb(1000)));
+        assert_eq!(
+            "2.5001".parse::<FeeRate>(),
+            Err(ParseAmountError::TooPrecise)
+        );
+        assert_eq!(
+            "-1".parse::<FeeRate>(),
+            Err(ParseAmountError::InvalidFormat)
+        );
+        assert_eq!(FeeRate::from_sat_per_vb(u64::MAX), None);
+
+        let rate = FeeRate::from_sat_per_kvb(2500);
+        assert_eq!(rate.fee_for_vsize(141), Some(Amount::from_sat(353)));
+        assert_eq!(FeeRate::from_sat_per_kvb(u64::MAX).fee_for_vsize(2), None);
+        let json = serde_json::to_string(&rate).unwrap();
+        assert_eq!(json, "2.5");
+        assert_eq!(serde_json::from_str::<FeeRate>(&json).unwrap(), rate);
+        assert!(serde_json::from_str::<FeeRate>("-1.0").is_err());
+    }
+}
@@ -0,0 +1,598 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use psbt_guide::{
+    Algorithm, Amount, ChangeType, Error, EstimateMode, FeeRate, FeeSplit, FundingOptions, Input,
+    JoinOrder, ListUnspentOptions, Network, OutPoint, Recipient, ScriptType, SigningPolicy,
+};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Build, sign, combine and broadcast PSBTs through Bitcoin Core.
+///
+/// Arguments taking a PSBT or transaction accept the base64/hex value itself,
+/// a path to a file containing it, or `-` to read it from stdin.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Wallet used for wallet RPCs, overriding RPC_WALLET.
+    #[arg(long, global = true)]
+    pub wallet: Option<String>,
+
+    /// Write the result to this file instead of stdout.
+    #[arg(short, long, global = true)]
+    pub output: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the wallet's unspent outputs.
+    ListUtxos {
+        #[command(flatten)]
+        query: UnspentArgs,
+    },
+    /// Create a funded PSBT, letting the wallet add inputs as needed.
+    ///
+    /// With --select the inputs are chosen here instead, at --fee-rate, and
+    /// the wallet only adds change.
+    Create {
+        /// An outpoint to spend, as `txid:vout`. May be repeated.
+        #[arg(long = "input", value_parser = parse_input)]
+        inputs: Vec<Input>,
+        /// A recipient as `address=amount`, e.g. `bcrt1q...=0.0001`. May be repeated.
+        #[arg(long = "to", value_parser = parse_recipient, required_unless_present = "data")]
+        recipients: Vec<Recipient>,
+        /// Hex-encoded data for an OP_RETURN output.
+        #[arg(long, value_parser = parse_data)]
+        data: Option<Recipient>,
+        /// Choose the inputs with this coin selection algorithm.
+        #[arg(long, value_enum, conflicts_with_all = ["inputs", "no_add_inputs"], requires = "fee_rate")]
+        select: Option<SelectArg>,
+        /// Only select outputs with at least this many confirmations.
+        #[arg(long, default_value_t = 1, requires = "select")]
+        min_conf: u32,
+        #[command(flatten)]
+        funding: FundingArgs,
+    },
+    /// Join PSBTs from several participants into one.
+    ///
+    /// A directory argument adds every file in it; `-` reads one PSBT per
+    /// line from stdin.
+    Join {
+        #[arg(required = true, num_args = 1..)]
+        psbts: Vec<String>,
+        /// Join locally instead of with the node's joinpsbts.
+        #[arg(long)]
+        offline: bool,
+        /// Order of the joined inputs and outputs, with --offline.
+        #[arg(long, value_enum, default_value = "random", requires = "offline")]
+        order: JoinOrderArg,
+    },
+    /// Sign the wallet's inputs of a PSBT.
+    ///
+    /// With --max-fee the PSBT is checked first, and not signed unless it only
+    /// spends the expected inputs of the wallet, contains every expected
+    /// output and costs the wallet at most the expected payments plus the
+    /// maximum fee.
+    #[command(alias = "sign")]
+    Process {
+        psbt: String,
+        #[command(flatten)]
+        verify: VerifyArgs,
+    },
+    /// Combine signed copies of the same PSBT.
+    Combine {
+        #[arg(required = true, num_args = 1..)]
+        psbts: Vec<String>,
+        /// Combine locally instead of with the node's combinepsbt.
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Finalize a PSBT and extract the network transaction.
+    Finalize {
+        psbt: String,
+        /// Finalize locally instead of with the node's finalizepsbt.
+        #[arg(long)]
+        offline: bool,
+    },
+    /// Extract the network transaction from a finalized PSBT without a node,
+    /// printing its txid, wtxid, size and fee.
+    Extract { psbt: String },
+    /// Broadcast a raw transaction.
+    Broadcast { hex: String },
+    /// Decode a PSBT with the node's decodepsbt.
+    Decode { psbt: String },
+    /// Describe what the wallet brings to a coinjoin: its spendable outputs
+    /// and fresh mix and change addresses, as JSON for the `coinjoin` command.
+    Participant {
+        #[command(flatten)]
+        query: UnspentArgs,
+        /// Type of the mix and change addresses.
+        #[arg(long, value_enum)]
+        address_type: Option<ChangeTypeArg>,
+    },
+    /// Build an equal-denomination coinjoin from the participants' JSON,
+    /// printing each participant's PSBT on its own line, ready for
+    /// `join --offline -`.
+    ///
+    /// The fee allocation is reported so each participant can sign with
+    /// `process --max-fee` set to their share.
+    Coinjoin {
+        #[arg(required = true, num_args = 2..)]
+        participants: Vec<String>,
+        /// Fee rate in sat/vB.
+        #[arg(long)]
+        fee_rate: FeeRate,
+        /// Amount of each mix output, instead of the largest standard
+        /// denomination everyone can afford.
+        #[arg(long)]
+        denomination: Option<Amount>,
+        /// How the fee is divided: by the size of each participant's inputs
+        /// and outputs, or equally.
+        #[arg(long, value_enum, default_value = "weight")]
+        fee_split: FeeSplitArg,
+    },
+    /// Coordinate a coinjoin round over HTTP: wait for the participants to
+    /// register, join their contributions, collect their signatures, then
+    /// finalize and broadcast with the node and wait for a confirmation.
+    Coordinate {
+        /// How many participants the round waits for.
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(2..),
+            required_unless_present = "resume"
+        )]
+        participants: Option<u32>,
+        /// Fee rate in sat/vB.
+        #[arg(long, required_unless_present = "resume")]
+        fee_rate: Option<FeeRate>,
+        /// Amount of each mix output, instead of the largest standard
+        /// denomination everyone can afford.
+        #[arg(long)]
+        denomination: Option<Amount>,
+        #[arg(long, value_enum, default_value = "weight")]
+        fee_split: FeeSplitArg,
+        /// Order of the joined inputs and outputs.
+        #[arg(long, value_enum, default_value = "random")]
+        order: JoinOrderArg,
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8339")]
+        listen: String,
+        /// Seconds participants have to sign before the round is rebuilt
+        /// without those who did not.
+        #[arg(long)]
+        signing_timeout: Option<u64>,
+        /// File of outputs banned from rounds, one txid:vout per line. The
+        /// outputs of participants who do not sign are added to it.
+        #[arg(long)]
+        ban_list: Option<PathBuf>,
+        /// Directory to save the round and its PSBTs in after every step.
+        #[arg(long)]
+        state: Option<PathBuf>,
+        /// Resume the round saved in this directory from the last stage it
+        /// reached, instead of starting a new one.
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "participants",
+                "fee_rate",
+                "denomination",
+                "signing_timeout",
+                "ban_list",
+                "state"
+            ]
+        )]
+        resume: Option<PathBuf>,
+    },
+    /// Take part in a coordinator's round with the wallet's spendable
+    /// outputs, signing the joined PSBT only if it pays this wallet as
+    /// agreed.
+    Mix {
+        /// URL of the coordinator.
+        #[arg(long, default_value = "http://127.0.0.1:8339")]
+        coordinator: String,
+        #[command(flatten)]
+        query: UnspentArgs,
+        /// Type of the mix and change addresses.
+        #[arg(long, value_enum)]
+        address_type: Option<ChangeTypeArg>,
+        /// The most this wallet pays towards the fee. Assignments asking for
+        /// more are not signed.
+        #[arg(long, required_unless_present = "max_fee_rate", conflicts_with = "max_fee_rate")]
+        max_fee: Option<Amount>,
+        /// The most this wallet pays towards the fee, in sat/vB of its inputs
+        /// and outputs and the transaction overhead.
+        #[arg(long)]
+        max_fee_rate: Option<FeeRate>,
+        /// Seconds between polls of the coordinator.
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// Show what a PSBT contains: inputs, outputs, fee, locktime and what is
+    /// still missing.
+    Inspect {
+        psbt: String,
+        /// Complete the summary with the node's analyzepsbt and, with a
+        /// wallet, which outputs belong to it.
+        #[arg(long)]
+        node: bool,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+        /// Network used to encode addresses without --node, defaulting to
+        /// RPC_NETWORK.
+        #[arg(long, conflicts_with = "node")]
+        network: Option<Network>,
+    },
+}
+
+/// Options controlling how the wallet funds a new PSBT.
+#[derive(Debug, Args)]
+pub struct FundingArgs {
+    /// Fee rate in sat/vB.
+    #[arg(long, conflicts_with_all = ["conf_target", "estimate_mode"])]
+    fee_rate: Option<FeeRate>,
+    /// Confirmation target in blocks for fee estimation.
+    #[arg(long)]
+    conf_target: Option<u32>,
+    /// Fee estimation mode used with --conf-target.
+    #[arg(long, value_enum)]
+    estimate_mode: Option<EstimateModeArg>,
+    /// Only spend the given inputs; fail instead of adding more.
+    #[arg(long)]
+    no_add_inputs: bool,
+    /// Send change to this address.
+    #[arg(long, conflicts_with = "change_type")]
+    change_address: Option<String>,
+    /// Output type of the change output.
+    #[arg(long, value_enum)]
+    change_type: Option<ChangeTypeArg>,
+    /// Position of the change output.
+    #[arg(long)]
+    change_position: Option<u32>,
+    /// Also spend watch-only outputs.
+    #[arg(long)]
+    include_watching: bool,
+    /// Lock the selected outputs so other PSBTs do not spend them.
+    #[arg(long)]
+    lock_unspents: bool,
+    /// Deduct the fee from the recipient at this position. May be repeated.
+    #[arg(long = "subtract-fee-from")]
+    subtract_fee_from: Vec<usize>,
+    /// Signal BIP125 replaceability, or not with `false`.
+    #[arg(long)]
+    replaceable: Option<bool>,
+    /// Raw locktime of the transaction.
+    #[arg(long)]
+    locktime: Option<u32>,
+}
+
+/// Which unspent outputs to list.
+#[derive(Debug, Args)]
+pub struct UnspentArgs {
+    /// Minimum confirmations.
+    #[arg(long)]
+    min_conf: Option<u32>,
+    /// Maximum confirmations.
+    #[arg(long)]
+    max_conf: Option<u32>,
+    /// Only outputs to this address. May be repeated.
+    #[arg(long = "address")]
+    addresses: Vec<String>,
+    /// Whether the node lists unsafe outputs, which it does by default.
+    #[arg(long)]
+    include_unsafe: Option<bool>,
+    /// Minimum amount of each output.
+    #[arg(long)]
+    min_amount: Option<Amount>,
+    /// Maximum amount of each output.
+    #[arg(long)]
+    max_amount: Option<Amount>,
+    /// Maximum number of outputs the node lists.
+    #[arg(long)]
+    max_count: Option<u32>,
+    /// Stop once the listed outputs add up to this amount.
+    #[arg(long)]
+    min_sum: Option<Amount>,
+    /// Only outputs with this label.
+    #[arg(long)]
+    label: Option<String>,
+    /// Only outputs of this descriptor or one derived from it.
+    #[arg(long = "desc")]
+    descriptor: Option<String>,
+    /// Only outputs of this script type. May be repeated.
+    #[arg(long = "script-type", value_enum)]
+    script_types: Vec<ScriptTypeArg>,
+    /// Only outputs that are safe to spend.
+    #[arg(long)]
+    safe: bool,
+    /// Only outputs the wallet can spend.
+    #[arg(long)]
+    spendable: bool,
+}
+
+impl UnspentArgs {
+    pub fn options(&self) -> ListUnspentOptions {
+        ListUnspentOptions {
+            min_conf: self.min_conf,
+            max_conf: self.max_conf,
+            addresses: self.addresses.clone(),
+            include_unsafe: self.include_unsafe,
+            minimum_amount: self.min_amount,
+            maximum_amount: self.max_amount,
+            maximum_count: self.max_count,
+            minimum_sum_amount: self.min_sum,
+            label: self.label.clone(),
+            descriptor: self.descriptor.clone(),
+            script_types: self.script_types.iter().map(|&t| t.into()).collect(),
+            safe: self.safe.then_some(true),
+            spendable: self.spendable.then_some(true),
+        }
+    }
+}
+
+/// What a PSBT must match before the wallet signs it.
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// An input of the wallet the PSBT may spend, as `txid:vout`. May be repeated.
+    #[arg(long = "expect-input", requires = "max_fee")]
+    inputs: Vec<OutPoint>,
+    /// An output the PSBT must contain, as `address=amount`. May be repeated.
+    #[arg(long = "expect-output", value_parser = parse_recipient, requires = "max_fee")]
+    outputs: Vec<Recipient>,
+    /// The most the wallet may contribute to the fee.
+    #[arg(long)]
+    max_fee: Option<Amount>,
+}
+
+impl VerifyArgs {
+    /// The signing policy, if verification was asked for.
+    pub fn policy(&self) -> Option<SigningPolicy> {
+        let policy = SigningPolicy {
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            max_fee: self.max_fee?,
+        };
+        Some(policy)
+    }
+}
+
+impl FundingArgs {
+    pub fn options(&self) -> FundingOptions {
+        let mut options =
+            FundingOptions::new().subtract_fee_from_outputs(self.subtract_fee_from.clone());
+        if let Some(fee_rate) = self.fee_rate {
+            options = options.fee_rate(fee_rate);
+        }
+        if let Some(conf_target) = self.conf_target {
+            options = options.conf_target(conf_target);
+        }
+        if let Some(mode) = self.estimate_mode {
+            options = options.estimate_mode(mode.into());
+        }
+        if self.no_add_inputs {
+            options = options.add_inputs(false);
+        }
+        if let Some(address) = &self.change_address {
+            options = options.change_address(address.as_str());
+        }
+        if let Some(change_type) = self.change_type {
+            options = options.change_type(change_type.into());
+        }
+        if let Some(position) = self.change_position {
+            options = options.change_position(position);
+        }
+        if self.include_watching {
+            options = options.include_watching(true);
+        }
+        if self.lock_unspents {
+            options = options.lock_unspents(true);
+        }
+        if let Some(replaceable) = self.replaceable {
+            options = options.replaceable(replaceable);
+        }
+        if let Some(locktime) = self.locktime {
+            options = options.locktime(locktime);
+        }
+        options
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum JoinOrderArg {
+    Random,
+    Bip69,
+    Preserve,
+}
+
+impl From<JoinOrderArg> for JoinOrder {
+    fn from(order: JoinOrderArg) -> Self {
+        match order {
+            JoinOrderArg::Random => JoinOrder::Random,
+            JoinOrderArg::Bip69 => JoinOrder::Bip69,
+            JoinOrderArg::Preserve => JoinOrder::Preserve,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FeeSplitArg {
+    Weight,
+    Equal,
+}
+
+impl From<FeeSplitArg> for FeeSplit {
+    fn from(split: FeeSplitArg) -> Self {
+        match split {
+            FeeSplitArg::Weight => FeeSplit::Weight,
+            FeeSplitArg::Equal => FeeSplit::Equal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SelectArg {
+    /// Try every algorithm and keep the least wasteful selection.
+    Auto,
+    /// Branch-and-Bound, which avoids change.
+    Bnb,
+    Knapsack,
+    /// Single random draw.
+    Srd,
+    LargestFirst,
+}
+
+impl From<SelectArg> for Algorithm {
+    fn from(select: SelectArg) -> Self {
+        match select {
+            SelectArg::Auto => Algorithm::Auto,
+            SelectArg::Bnb => Algorithm::BranchAndBound,
+            SelectArg::Knapsack => Algorithm::Knapsack,
+            SelectArg::Srd => Algorithm::SingleRandomDraw,
+            SelectArg::LargestFirst => Algorithm::LargestFirst,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ScriptTypeArg {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    Nonstandard,
+}
+
+impl From<ScriptTypeArg> for ScriptType {
+    fn from(script_type: ScriptTypeArg) -> Self {
+        match script_type {
+            ScriptTypeArg::P2pkh => ScriptType::P2pkh,
+            ScriptTypeArg::P2sh => ScriptType::P2sh,
+            ScriptTypeArg::P2wpkh => ScriptType::P2wpkh,
+            ScriptTypeArg::P2wsh => ScriptType::P2wsh,
+            ScriptTypeArg::P2tr => ScriptType::P2tr,
+            ScriptTypeArg::Nonstandard => ScriptType::Nonstandard,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum EstimateModeArg {
+    Unset,
+    Economical,
+    Conservative,
+}
+
+impl From<EstimateModeArg> for EstimateMode {
+    fn from(mode: EstimateModeArg) -> Self {
+        match mode {
+            EstimateModeArg::Unset => EstimateMode::Unset,
+            EstimateModeArg::Economical => EstimateMode::Economical,
+            EstimateModeArg::Conservative => EstimateMode::Conservative,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ChangeTypeArg {
+    Legacy,
+    P2shSegwit,
+    Bech32,
+    Bech32m,
+}
+
+impl From<ChangeTypeArg> for ChangeType {
+    fn from(change_type: ChangeTypeArg) -> Self {
+        match change_type {
+            ChangeTypeArg::Legacy => ChangeType::Legacy,
+            ChangeTypeArg::P2shSegwit => ChangeType::P2shSegwit,
+            ChangeTypeArg::Bech32 => ChangeType::Bech32,
+            ChangeTypeArg::Bech32m => ChangeType::Bech32m,
+        }
+    }
+}
+
+/// Resolves an argument to its contents: `-` reads stdin, an existing path
+/// reads the file, anything else is used as given.
+pub fn read_arg(arg: &str) -> Result<String, Error> {
+    let contents = if arg == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        contents
+    } else if Path::new(arg).is_file() {
+        fs::read_to_string(arg)?
+    } else {
+        arg.to_string()
+    };
+
+    Ok(contents.trim().to_string())
+}
+
+/// Resolves several arguments like [`read_arg`], expanding a directory to the
+/// files it contains (in name order) and `-` to one value per line of stdin.
+pub fn read_args(args: &[String]) -> Result<Vec<String>, Error> {
+    let mut values = Vec::new();
+    for arg in args {
+        if Path::new(arg).is_dir() {
+            let mut paths = fs::read_dir(arg)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.retain(|path| path.is_file() && !is_hidden(path));
+            paths.sort();
+            for path in paths {
+                values.push(fs::read_to_string(path)?.trim().to_string());
+            }
+        } else if arg == "-" {
+            let contents = read_arg(arg)?;
+            values.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(String::from),
+            );
+        } else {
+            values.push(read_arg(arg)?);
+        }
+    }
+
+    Ok(values)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// Writes the result to `path`, or to stdout when no path is given.
+pub fn write_output(path: Option<&Path>, contents: &str) -> Result<(), Error> {
+    match path {
+        Some(path) => fs::write(path, format!("{contents}\n"))?,
+        None => writeln!(io::stdout(), "{contents}")?,
+    }
+    Ok(())
+}
+
+fn parse_input(s: &str) -> Result<Input, String> {
+    let (txid, vout) = s.split_once(':').ok_or("expected txid:vout")?;
+    let vout = vout.parse().map_err(|_| format!("invalid vout: {vout}"))?;
+    Ok(Input {
+        txid: txid.to_string(),
+        vout,
+    })
+}
+
+fn parse_recipient(s: &str) -> Result<Recipient, String> {
+    let (address, amount) = s.split_once('=').ok_or("expected address=amount")?;
+    let amount = amount.parse::<Amount>().map_err(|e| e.to_string())?;
+    Ok(Recipient::address(address, amount))
+}
+
+fn parse_data(s: &str) -> Result<Recipient, String> {
+    let data = hex::decode(s).map_err(|e| e.to_string())?;
+    Ok(Recipient::data(data))
+}
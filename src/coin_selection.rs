@@ -0,0 +1,716 @@
+//! Choosing which of the wallet's unspent outputs fund a new PSBT.
+//!
+//! The algorithms follow Bitcoin Core's wallet: Branch-and-Bound looks for an
+//! exact match that needs no change, knapsack and single random draw fall
+//! back to creating change, and largest-first spends the fewest outputs.
+//! Selections are compared by their waste: the fee paid now for the inputs
+//! over what they would cost at the long-term fee rate, plus the cost of the
+//! change output or the excess given up to the fee.
+
+use crate::address;
+use crate::amount::{Amount, FeeRate};
+use crate::error::Error;
+use crate::transaction::{Script, ScriptType};
+use crate::types::{Input, Recipient, UnspentTxOutputs};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::fmt;
+
+/// Version, input and output counts and locktime.
+pub(crate) const TX_OVERHEAD_VSIZE: u64 = 11;
+/// A P2WPKH output, the default change type.
+const CHANGE_OUTPUT_VSIZE: u64 = 31;
+/// Spending a P2WPKH output.
+const CHANGE_SPEND_VSIZE: u64 = 68;
+/// The dust limit of a P2WPKH output at the default relay fee.
+pub(crate) const DUST: u64 = 294;
+/// The change knapsack aims for when no exact match exists.
+const MIN_CHANGE: u64 = 1_000_000;
+/// The least change single random draw leaves.
+const CHANGE_LOWER: u64 = 50_000;
+/// How many branches Branch-and-Bound explores before giving up.
+const BNB_TOTAL_TRIES: usize = 100_000;
+/// How many random subsets knapsack tries.
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+/// A coin selection algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Run every algorithm and keep the selection with the least waste.
+    #[default]
+    Auto,
+    /// Search for a selection that needs no change.
+    BranchAndBound,
+    /// Bitcoin Core's approximate subset sum, aiming for an exact match or
+    /// a comfortable change.
+    Knapsack,
+    /// Spend outputs in random order until the target is reached.
+    SingleRandomDraw,
+    /// Spend the largest outputs first.
+    LargestFirst,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Algorithm::Auto => "auto",
+            Algorithm::BranchAndBound => "branch-and-bound",
+            Algorithm::Knapsack => "knapsack",
+            Algorithm::SingleRandomDraw => "single random draw",
+            Algorithm::LargestFirst => "largest-first",
+        })
+    }
+}
+
+/// What a selection must pay for.
+#[derive(Debug, Clone)]
+pub struct CoinSelectionParams {
+    /// The sum paid to the recipients.
+    pub target: Amount,
+    pub fee_rate: FeeRate,
+    /// The rate the inputs are expected to cost later, used to tell whether
+    /// spending more inputs now is wasteful.
+    pub long_term_fee_rate: FeeRate,
+    /// The size of the transaction without its inputs and change.
+    pub base_vsize: u64,
+    /// Only spend outputs with at least this many confirmations.
+    pub min_confirmations: u32,
+}
+
+impl CoinSelectionParams {
+    /// Parameters for paying `target` to a single output.
+    pub fn new(target: Amount, fee_rate: FeeRate) -> Self {
+        CoinSelectionParams {
+            target,
+            fee_rate,
+            long_term_fee_rate: FeeRate::from_sat_per_kvb(10_000),
+            base_vsize: TX_OVERHEAD_VSIZE + CHANGE_OUTPUT_VSIZE,
+            min_confirmations: 1,
+        }
+    }
+
+    /// Parameters for paying `recipients`, sizing their outputs from their
+    /// addresses.
+    pub fn for_recipients(recipients: &[Recipient], fee_rate: FeeRate) -> Result<Self, Error> {
+        let mut target = Amount::ZERO;
+        let mut base_vsize = TX_OVERHEAD_VSIZE;
+        for recipient in recipients {
+            let (amount, script_len) = match recipient {
+                Recipient::Address { address, amount } => {
+                    let script = address::to_script(address).ok_or_else(|| {
+                        Error::InvalidRequest(format!("invalid address: {}", address))
+                    })?;
+                    (*amount, script.0.len())
+                }
+                Recipient::Data(data) => (Amount::ZERO, data.len() + 3),
+            };
+            target = target
+                .checked_add(amount)
+                .ok_or_else(|| Error::InvalidRequest("amounts overflow".to_string()))?;
+            base_vsize += output_vsize(script_len);
+        }
+
+        Ok(CoinSelectionParams {
+            target,
+            base_vsize,
+            ..CoinSelectionParams::new(target, fee_rate)
+        })
+    }
+
+    pub fn long_term_fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.long_term_fee_rate = fee_rate;
+        self
+    }
+
+    pub fn min_confirmations(mut self, min_confirmations: u32) -> Self {
+        self.min_confirmations = min_confirmations;
+        self
+    }
+
+    /// What the inputs must bring in after paying for themselves.
+    fn selection_target(&self) -> Result<u64, Error> {
+        Ok(self.target.to_sat() + fee_for_vsize(self.fee_rate, self.base_vsize)?)
+    }
+
+    fn change_fee(&self) -> Result<u64, Error> {
+        fee_for_vsize(self.fee_rate, CHANGE_OUTPUT_VSIZE)
+    }
+
+    /// Creating a change output now and spending it later.
+    fn cost_of_change(&self) -> Result<u64, Error> {
+        Ok(self.change_fee()? + fee_for_vsize(self.long_term_fee_rate, CHANGE_SPEND_VSIZE)?)
+    }
+}
+
+/// The outputs chosen to fund a PSBT.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub algorithm: Algorithm,
+    pub utxos: Vec<UnspentTxOutputs>,
+    /// The fee of the whole transaction, including any excess given up
+    /// because change would have been dust.
+    pub fee: Amount,
+    /// The change left after the fee, if worth an output.
+    pub change: Option<Amount>,
+    /// The waste metric in satoshis; lower is better, and it is negative when
+    /// fees are below the long-term rate.
+    pub waste: i64,
+}
+
+impl Selection {
+    /// The selected outputs as inputs for [`create_psbt`](crate::create_psbt).
+    pub fn inputs(&self) -> Vec<Input> {
+        self.utxos
+            .iter()
+            .map(|utxo| Input::new(utxo.txid.clone(), utxo.vout))
+            .collect()
+    }
+
+    pub fn total(&self) -> Amount {
+        Amount::checked_sum(self.utxos.iter().map(|utxo| utxo.amount)).unwrap_or(Amount::MAX_MONEY)
+    }
+}
+
+/// An output that may be spent, with what it brings in at the target rate.
+struct Candidate<'a> {
+    utxo: &'a UnspentTxOutputs,
+    effective_value: u64,
+    fee: u64,
+    long_term_fee: u64,
+}
+
+impl Candidate<'_> {
+    fn waste(&self) -> i64 {
+        self.fee as i64 - self.long_term_fee as i64
+    }
+}
+
+/// The virtual size of an input spending `script`, if its type tells it.
+///
+/// P2SH outputs are assumed to wrap P2WPKH, as wallets create them.
+pub(crate) fn input_vsize(script: &Script) -> Option<u64> {
+    match script.script_type() {
+        ScriptType::P2pkh => Some(148),
+        ScriptType::P2sh => Some(91),
+        ScriptType::P2wpkh => Some(68),
+        ScriptType::P2tr => Some(58),
+        _ => None,
+    }
+}
+
+/// The fee for `vsize` virtual bytes at `fee_rate`, which fails for rates too
+/// high to compute it.
+pub(crate) fn fee_for_vsize(fee_rate: FeeRate, vsize: u64) -> Result<u64, Error> {
+    fee_rate
+        .fee_for_vsize(vsize)
+        .map(Amount::to_sat)
+        .ok_or_else(|| Error::InvalidRequest(format!("fee rate {} is too high", fee_rate)))
+}
+
+/// The virtual size of an output with a script of `script_len` bytes.
+pub(crate) fn output_vsize(script_len: usize) -> u64 {
+    9 + script_len as u64
+}
+
+/// Selects outputs from `utxos` paying for `params` with `algorithm`.
+///
+/// Only spendable outputs with enough confirmations and a known input size
+/// are considered, and outputs costing more to spend than they are worth
+/// are left out.
+pub fn select_coins(
+    utxos: &[UnspentTxOutputs],
+    params: &CoinSelectionParams,
+    algorithm: Algorithm,
+) -> Result<Selection, Error> {
+    let mut candidates = Vec::new();
+    for utxo in utxos {
+        if !utxo.spendable || utxo.confirmations < params.min_confirmations {
+            continue;
+        }
+        let Ok(script) = hex::decode(&utxo.script_pub_key) else {
+            continue;
+        };
+        let Some(vsize) = input_vsize(&Script::from(script)) else {
+            continue;
+        };
+        let fee = fee_for_vsize(params.fee_rate, vsize)?;
+        let Some(effective_value) = utxo.amount.to_sat().checked_sub(fee).filter(|&v| v > 0) else {
+            continue;
+        };
+        candidates.push(Candidate {
+            utxo,
+            effective_value,
+            fee,
+            long_term_fee: fee_for_vsize(params.long_term_fee_rate, vsize)?,
+        });
+    }
+    // Largest first, which Branch-and-Bound and knapsack rely on.
+    candidates.sort_by_key(|candidate| Reverse(candidate.effective_value));
+
+    let target = params.selection_target()?;
+    let available: u64 = candidates.iter().map(|c| c.effective_value).sum();
+    if available < target {
+        return Err(Error::InsufficientFunds {
+            needed: Amount::from_sat(target),
+            available: Amount::from_sat(available),
+        });
+    }
+
+    let algorithms = match algorithm {
+        Algorithm::Auto => vec![
+            Algorithm::BranchAndBound,
+            Algorithm::Knapsack,
+            Algorithm::SingleRandomDraw,
+            Algorithm::LargestFirst,
+        ],
+        algorithm => vec![algorithm],
+    };
+
+    let mut best: Option<Selection> = None;
+    for algorithm in algorithms {
+        let selected = match algorithm {
+            Algorithm::BranchAndBound => {
+                branch_and_bound(&candidates, target, params.cost_of_change()?)
+            }
+            Algorithm::Knapsack => knapsack(&candidates, target),
+            Algorithm::SingleRandomDraw => {
+                single_random_draw(&candidates, target + params.change_fee()? + CHANGE_LOWER)
+            }
+            Algorithm::LargestFirst => largest_first(&candidates, target),
+            Algorithm::Auto => unreachable!(),
+        };
+        let Some(selected) = selected else {
+            continue;
+        };
+        let selection = finish(&candidates, &selected, params, algorithm)?;
+        // Ties go to the earlier algorithm, preferring changeless solutions.
+        if best
+            .as_ref()
+            .is_none_or(|best| selection.waste < best.waste)
+        {
+            best = Some(selection);
+        }
+    }
+
+    best.ok_or_else(|| {
+        Error::InvalidRequest(format!("{} found no selection of the outputs", algorithm))
+    })
+}
+
+/// Decides on change and computes the fee and waste of a selection.
+fn finish(
+    candidates: &[Candidate],
+    selected: &[usize],
+    params: &CoinSelectionParams,
+    algorithm: Algorithm,
+) -> Result<Selection, Error> {
+    let target = params.selection_target()?;
+    let value: u64 = selected
+        .iter()
+        .map(|&i| candidates[i].effective_value)
+        .sum();
+    let inputs_fee: u64 = selected.iter().map(|&i| candidates[i].fee).sum();
+    let inputs_waste: i64 = selected.iter().map(|&i| candidates[i].waste()).sum();
+    let base_fee = fee_for_vsize(params.fee_rate, params.base_vsize)?;
+    let change_fee = params.change_fee()?;
+
+    let excess = value - target;
+    let change = excess
+        .checked_sub(change_fee)
+        .filter(|&change| change >= DUST);
+    let (fee, waste) = match change {
+        Some(_) => (
+            base_fee + inputs_fee + change_fee,
+            inputs_waste + params.cost_of_change()? as i64,
+        ),
+        None => (base_fee + inputs_fee + excess, inputs_waste + excess as i64),
+    };
+
+    Ok(Selection {
+        algorithm,
+        utxos: selected
+            .iter()
+            .map(|&i| candidates[i].utxo.clone())
+            .collect(),
+        fee: Amount::from_sat(fee),
+        change: change.map(Amount::from_sat),
+        waste,
+    })
+}
+
+/// Depth-first search for the changeless selection with the least waste,
+/// whose value lies between `target` and `target + cost_of_change`.
+///
+/// `candidates` must be sorted by descending effective value.
+fn branch_and_bound(
+    candidates: &[Candidate],
+    target: u64,
+    cost_of_change: u64,
+) -> Option<Vec<usize>> {
+    let mut available: u64 = candidates.iter().map(|c| c.effective_value).sum();
+    let fee_rate_high = candidates.first().is_some_and(|c| c.fee > c.long_term_fee);
+
+    let mut selection: Vec<usize> = Vec::new();
+    let mut value = 0;
+    let mut waste = 0;
+    let mut best: Option<Vec<usize>> = None;
+    let mut best_waste = i64::MAX;
+
+    let mut index = 0;
+    for _ in 0..BNB_TOTAL_TRIES {
+        let mut backtrack = false;
+        if value + available < target
+            || value > target + cost_of_change
+            // Adding inputs only adds waste when fees are above the
+            // long-term rate.
+            || (waste > best_waste && fee_rate_high)
+        {
+            backtrack = true;
+        } else if value >= target {
+            let total_waste = waste + (value - target) as i64;
+            if total_waste <= best_waste {
+                best = Some(selection.clone());
+                best_waste = total_waste;
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = selection.last() else {
+                break;
+            };
+            // Give back the outputs skipped since the last included one,
+            // then try the branch that omits it.
+            index -= 1;
+            while index > last {
+                available += candidates[index].effective_value;
+                index -= 1;
+            }
+            value -= candidates[index].effective_value;
+            waste -= candidates[index].waste();
+            selection.pop();
+        } else {
+            let candidate = &candidates[index];
+            available -= candidate.effective_value;
+            // Omitting an output equivalent to the previous one, which was
+            // omitted too, explores the same selections again.
+            let equivalent_omitted = index > 0
+                && selection.last() != Some(&(index - 1))
+                && candidate.effective_value == candidates[index - 1].effective_value
+                && candidate.fee == candidates[index - 1].fee;
+            if selection.is_empty() || !equivalent_omitted {
+                selection.push(index);
+                value += candidate.effective_value;
+                waste += candidate.waste();
+            }
+        }
+        index += 1;
+    }
+
+    best
+}
+
+/// Bitcoin Core's knapsack solver: an exact match if one output or all the
+/// smaller ones make it, otherwise the best random subset or the smallest
+/// output covering the target alone.
+fn knapsack(candidates: &[Candidate], target: u64) -> Option<Vec<usize>> {
+    let mut rng = rand::thread_rng();
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.shuffle(&mut rng);
+
+    let mut lowest_larger: Option<usize> = None;
+    let mut applicable = Vec::new();
+    let mut total_lower = 0;
+    for index in order {
+        let value = candidates[index].effective_value;
+        if value == target {
+            return Some(vec![index]);
+        } else if value < target + MIN_CHANGE {
+            applicable.push(index);
+            total_lower += value;
+        } else if lowest_larger.is_none_or(|larger| value < candidates[larger].effective_value) {
+            lowest_larger = Some(index);
+        }
+    }
+
+    if total_lower == target {
+        return Some(applicable);
+    }
+    if total_lower < target {
+        return lowest_larger.map(|index| vec![index]);
+    }
+
+    applicable.sort_by_key(|&index| Reverse(candidates[index].effective_value));
+    let values: Vec<u64> = applicable
+        .iter()
+        .map(|&i| candidates[i].effective_value)
+        .collect();
+    let (mut best, mut best_value) =
+        approximate_best_subset(&mut rng, &values, total_lower, target);
+    if best_value != target && total_lower >= target + MIN_CHANGE {
+        (best, best_value) =
+            approximate_best_subset(&mut rng, &values, total_lower, target + MIN_CHANGE);
+    }
+
+    // Prefer the single larger output over a subset that misses both the
+    // target and a comfortable change.
+    if let Some(larger) = lowest_larger {
+        if (best_value != target && best_value < target + MIN_CHANGE)
+            || candidates[larger].effective_value <= best_value
+        {
+            return Some(vec![larger]);
+        }
+    }
+
+    Some(
+        applicable
+            .into_iter()
+            .zip(best)
+            .filter_map(|(index, included)| included.then_some(index))
+            .collect(),
+    )
+}
+
+/// Tries random subsets of `values`, returning the smallest sum reaching
+/// `target` and which values make it.
+fn approximate_best_subset(
+    rng: &mut impl Rng,
+    values: &[u64],
+    total_lower: u64,
+    target: u64,
+) -> (Vec<bool>, u64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total_lower;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = 0;
+        let mut reached = false;
+        // The first pass includes each value at random, the second adds the
+        // rest until the target is reached.
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for (index, &value) in values.iter().enumerate() {
+                let include = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[index]
+                };
+                if !include {
+                    continue;
+                }
+                total += value;
+                included[index] = true;
+                if total >= target {
+                    reached = true;
+                    if total < best_value {
+                        best_value = total;
+                        best = included.clone();
+                    }
+                    total -= value;
+                    included[index] = false;
+                }
+            }
+        }
+    }
+
+    (best, best_value)
+}
+
+/// Spends outputs in random order until their value reaches `target`.
+fn single_random_draw(candidates: &[Candidate], target: u64) -> Option<Vec<usize>> {
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.shuffle(&mut rand::thread_rng());
+
+    let mut value = 0;
+    let mut selected = Vec::new();
+    for index in order {
+        selected.push(index);
+        value += candidates[index].effective_value;
+        if value >= target {
+            return Some(selected);
+        }
+    }
+    None
+}
+
+/// Spends the largest outputs until their value reaches `target`.
+///
+/// `candidates` must be sorted by descending effective value.
+fn largest_first(candidates: &[Candidate], target: u64) -> Option<Vec<usize>> {
+    let mut value = 0;
+    for (index, candidate) in candidates.iter().enumerate() {
+        value += candidate.effective_value;
+        if value >= target {
+            return Some((0..=index).collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P2WPKH: &str = "00141111111111111111111111111111111111111111";
+
+    fn utxo(vout: u32, amount: u64) -> UnspentTxOutputs {
+        UnspentTxOutputs {
+            txid: "ab".repeat(32),
+            vout,
+            address: None,
+            label: None,
+            script_pub_key: P2WPKH.to_string(),
+            amount: Amount::from_sat(amount),
+            confirmations: 6,
+            spendable: true,
+            solvable: true,
+            desc: None,
+            parent_descs: Vec::new(),
+            safe: true,
+        }
+    }
+
+    /// P2WPKH outputs worth `effective_values` at 10 sat/vB, where each input
+    /// costs 680 sat now and in the long term.
+    fn utxos(effective_values: &[u64]) -> Vec<UnspentTxOutputs> {
+        effective_values
+            .iter()
+            .zip(0..)
+            .map(|(&value, vout)| utxo(vout, value + 680))
+            .collect()
+    }
+
+    /// Paying `target` at 10 sat/vB, which with the 42 vB base costs
+    /// 420 sat, so the inputs must bring in `target + 420`.
+    fn params(target: u64) -> CoinSelectionParams {
+        CoinSelectionParams::new(
+            Amount::from_sat(target),
+            FeeRate::from_sat_per_vb(10).unwrap(),
+        )
+    }
+
+    fn vouts(selection: &Selection) -> Vec<u32> {
+        let mut vouts: Vec<u32> = selection.utxos.iter().map(|utxo| utxo.vout).collect();
+        vouts.sort();
+        vouts
+    }
+
+    #[test]
+    fn branch_and_bound_finds_an_exact_match() {
+        let utxos = utxos(&[100_000, 200_000, 400_000, 800_000]);
+        let selection = select_coins(&utxos, &params(299_580), Algorithm::BranchAndBound).unwrap();
+        assert_eq!(vouts(&selection), [0, 1]);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, Amount::from_sat(420 + 2 * 680));
+        assert_eq!(selection.waste, 0);
+    }
+
+    #[test]
+    fn branch_and_bound_gives_up_excess_below_the_cost_of_change() {
+        // Change would cost 310 sat now and 680 sat to spend, so 500 sat of
+        // excess goes to the fee and counts as waste.
+        let utxos = utxos(&[150_000, 151_000]);
+        let selection = select_coins(&utxos, &params(300_080), Algorithm::BranchAndBound).unwrap();
+        assert_eq!(vouts(&selection), [0, 1]);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, Amount::from_sat(420 + 2 * 680 + 500));
+        assert_eq!(selection.waste, 500);
+    }
+
+    #[test]
+    fn branch_and_bound_without_a_match_fails() {
+        let utxos = utxos(&[100_000, 100_000]);
+        assert!(matches!(
+            select_coins(&utxos, &params(149_580), Algorithm::BranchAndBound),
+            Err(Error::InvalidRequest(message))
+                if message == "branch-and-bound found no selection of the outputs"
+        ));
+    }
+
+    #[test]
+    fn knapsack_prefers_a_single_exact_match() {
+        let utxos = utxos(&[100_000, 300_000, 500_000]);
+        let selection = select_coins(&utxos, &params(299_580), Algorithm::Knapsack).unwrap();
+        assert_eq!(vouts(&selection), [1]);
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, Amount::from_sat(420 + 680));
+        assert_eq!(selection.waste, 0);
+    }
+
+    #[test]
+    fn knapsack_falls_back_to_the_smallest_larger_output() {
+        let utxos = utxos(&[50_000, 5_000_000, 8_000_000]);
+        let selection = select_coins(&utxos, &params(299_580), Algorithm::Knapsack).unwrap();
+        assert_eq!(vouts(&selection), [1]);
+        assert_eq!(
+            selection.change,
+            Some(Amount::from_sat(5_000_000 - 300_000 - 310))
+        );
+        assert_eq!(selection.fee, Amount::from_sat(420 + 680 + 310));
+        assert_eq!(selection.waste, 310 + 680);
+    }
+
+    #[test]
+    fn largest_first_pays_for_change_at_a_high_fee_rate() {
+        // At 20 sat/vB an input costs 1360 sat, 680 sat more than in the
+        // long term, and change costs 620 sat now plus 680 sat later.
+        let utxos = vec![utxo(0, 501_360), utxo(1, 1_001_360)];
+        let params = CoinSelectionParams::new(
+            Amount::from_sat(1_200_000),
+            FeeRate::from_sat_per_vb(20).unwrap(),
+        );
+        let selection = select_coins(&utxos, &params, Algorithm::LargestFirst).unwrap();
+        assert_eq!(vouts(&selection), [0, 1]);
+        assert_eq!(selection.change, Some(Amount::from_sat(298_540)));
+        assert_eq!(selection.fee, Amount::from_sat(840 + 2 * 1360 + 620));
+        assert_eq!(selection.waste, 2 * 680 + 620 + 680);
+    }
+
+    #[test]
+    fn auto_keeps_the_changeless_selection() {
+        let utxos = utxos(&[100_000, 200_000, 400_000, 800_000]);
+        let selection = select_coins(&utxos, &params(299_580), Algorithm::Auto).unwrap();
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+        assert_eq!(vouts(&selection), [0, 1]);
+        assert_eq!(selection.waste, 0);
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let utxos = utxos(&[100_000, 50_000]);
+        assert!(matches!(
+            select_coins(&utxos, &params(200_000), Algorithm::Auto),
+            Err(Error::InsufficientFunds { needed, available })
+                if needed == Amount::from_sat(200_420) && available == Amount::from_sat(150_000)
+        ));
+    }
+
+    #[test]
+    fn unusable_outputs_are_left_out() {
+        let mut unconfirmed = utxo(1, 1_000_000);
+        unconfirmed.confirmations = 0;
+        let mut unspendable = utxo(2, 1_000_000);
+        unspendable.spendable = false;
+        let mut unknown_script = utxo(3, 1_000_000);
+        unknown_script.script_pub_key = "6a".to_string();
+        let uneconomic = utxo(4, 680);
+        let utxos = vec![
+            utxo(0, 100_680),
+            unconfirmed,
+            unspendable,
+            unknown_script,
+            uneconomic,
+        ];
+        assert!(matches!(
+            select_coins(&utxos, &params(200_000), Algorithm::Auto),
+            Err(Error::InsufficientFunds { available, .. }) if available == Amount::from_sat(100_000)
+        ));
+    }
+}
@@ -0,0 +1,822 @@
+//! Building equal-denomination coinjoins.
+//!
+//! Every participant brings unspent outputs and two fresh addresses. The
+//! builder picks a denomination all of them can afford and gives each
+//! participant a PSBT paying that amount to their mix address, with the rest
+//! less their share of the fee going to their change address. Joined, the
+//! mix outputs all have the same value and script type, so they cannot be
+//! told apart.
+
+use crate::address;
+use crate::amount::{Amount, FeeRate};
+use crate::coin_selection::{fee_for_vsize, input_vsize, output_vsize, DUST, TX_OVERHEAD_VSIZE};
+use crate::error::Error;
+use crate::psbt::{self, input_sighashes, PartiallySignedTransaction};
+use crate::transaction::{OutPoint, Script, ScriptType, Transaction, TxIn, TxOut, Txid};
+use crate::types::UnspentTxOutputs;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// What a participant brings to a coinjoin.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Participant {
+    /// The outputs the participant spends, all of them.
+    pub utxos: Vec<UnspentTxOutputs>,
+    /// A fresh address receiving the mixed output.
+    pub mix_address: String,
+    /// A fresh address receiving the change.
+    pub change_address: String,
+}
+
+/// Builds the contributions of an equal-denomination coinjoin.
+#[derive(Debug, Clone)]
+pub struct CoinjoinBuilder {
+    participants: Vec<Participant>,
+    fee_rate: FeeRate,
+    denomination: Option<Amount>,
+}
+
+/// A participant's part of a coinjoin, ready for joining.
+#[derive(Debug, Clone)]
+pub struct Contribution {
+    pub psbt: PartiallySignedTransaction,
+    /// The outputs spent by the PSBT's inputs, in order.
+    pub spent: Vec<TxOut>,
+    /// The position of the participant's change output, if any.
+    pub change_index: Option<usize>,
+}
+
+impl Contribution {
+    /// A contribution made elsewhere, e.g. by `walletcreatefundedpsbt`, with
+    /// its change output at `change_index`. Every input needs its UTXO.
+    pub fn new(
+        psbt: PartiallySignedTransaction,
+        change_index: Option<usize>,
+    ) -> Result<Self, Error> {
+        let spent = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(&psbt.inputs)
+            .enumerate()
+            .map(|(index, (txin, input))| {
+                input
+                    .spent_output(&txin.previous_output)
+                    .cloned()
+                    .ok_or(Error::Psbt(psbt::Error::MissingUtxo(index)))
+            })
+            .collect::<Result<_, _>>()?;
+        if let Some(index) = change_index.filter(|&i| i >= psbt.unsigned_tx.output.len()) {
+            return Err(Error::InvalidRequest(format!(
+                "change output {} does not exist",
+                index
+            )));
+        }
+
+        Ok(Contribution {
+            psbt,
+            spent,
+            change_index,
+        })
+    }
+
+    /// What the participant pays towards the fee: its inputs less its
+    /// outputs.
+    pub fn fee(&self) -> Amount {
+        let spent: u64 = self.spent.iter().map(|txout| txout.value.to_sat()).sum();
+        let paid: u64 = self
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.value.to_sat())
+            .sum();
+        Amount::from_sat(spent.saturating_sub(paid))
+    }
+
+    pub fn change(&self) -> Option<Amount> {
+        self.change_index
+            .map(|index| self.psbt.unsigned_tx.output[index].value)
+    }
+
+    /// The estimated size of the participant's inputs, once signed, and
+    /// outputs, without the transaction overhead.
+    pub fn vsize(&self) -> Result<u64, Error> {
+        let mut vsize = 0;
+        for (txin, txout) in self.psbt.unsigned_tx.input.iter().zip(&self.spent) {
+            vsize += input_vsize(&txout.script_pubkey).ok_or_else(|| {
+                Error::InvalidRequest(format!(
+                    "cannot estimate the size of spending {}",
+                    txin.previous_output
+                ))
+            })?;
+        }
+        for txout in &self.psbt.unsigned_tx.output {
+            vsize += output_vsize(txout.script_pubkey.len());
+        }
+        Ok(vsize)
+    }
+}
+
+/// The contributions of a coinjoin, in the order the participants were
+/// added.
+#[derive(Debug, Clone)]
+pub struct Coinjoin {
+    pub denomination: Amount,
+    pub contributions: Vec<Contribution>,
+}
+
+impl CoinjoinBuilder {
+    pub fn new(fee_rate: FeeRate) -> Self {
+        CoinjoinBuilder {
+            participants: Vec::new(),
+            fee_rate,
+            denomination: None,
+        }
+    }
+
+    pub fn participant(mut self, participant: Participant) -> Self {
+        self.participants.push(participant);
+        self
+    }
+
+    /// Mixes this amount instead of the largest standard denomination every
+    /// participant can afford.
+    pub fn denomination(mut self, denomination: Amount) -> Self {
+        self.denomination = Some(denomination);
+        self
+    }
+
+    /// Computes the denomination and each participant's PSBT.
+    pub fn build(&self) -> Result<Coinjoin, Error> {
+        if self.participants.len() < 2 {
+            return Err(Error::InvalidRequest(
+                "a coinjoin needs at least two participants".to_string(),
+            ));
+        }
+
+        let mut spent = HashSet::new();
+        let mut mix_type = None;
+        let mut plans = Vec::with_capacity(self.participants.len());
+        for (index, participant) in self.participants.iter().enumerate() {
+            let plan = Plan::new(index, participant, self.participants.len(), self.fee_rate)?;
+            for (outpoint, _) in &plan.inputs {
+                if !spent.insert(*outpoint) {
+                    return Err(Error::InvalidRequest(format!(
+                        "input {} is brought by more than one participant",
+                        outpoint
+                    )));
+                }
+            }
+            // Mix outputs of different types would tell their owners apart.
+            let script_type = plan.mix_script.script_type();
+            if mix_type.is_some_and(|mix_type| mix_type != script_type) {
+                return Err(Error::InvalidRequest(format!(
+                    "mix address of participant {} is not {} like the others",
+                    index,
+                    mix_type.unwrap_or(script_type)
+                )));
+            }
+            mix_type = Some(script_type);
+            plans.push(plan);
+        }
+
+        let affordable = plans
+            .iter()
+            .map(|plan| plan.affordable(self.fee_rate))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .min()
+            .unwrap_or(0);
+        let denomination = match self.denomination {
+            Some(denomination) => denomination.to_sat(),
+            None => standard_denomination(affordable),
+        };
+        if denomination < DUST || denomination > affordable {
+            return Err(Error::InsufficientFunds {
+                needed: Amount::from_sat(denomination.max(DUST)),
+                available: Amount::from_sat(affordable),
+            });
+        }
+
+        let contributions = plans
+            .iter()
+            .map(|plan| plan.contribution(denomination, self.fee_rate))
+            .collect::<Result<_, _>>()?;
+        Ok(Coinjoin {
+            denomination: Amount::from_sat(denomination),
+            contributions,
+        })
+    }
+}
+
+/// A participant's inputs and output scripts, with the sizes they add.
+struct Plan {
+    inputs: Vec<(OutPoint, TxOut)>,
+    mix_script: Script,
+    change_script: Script,
+    /// The participant's inputs, mix output and share of the transaction
+    /// overhead.
+    vsize: u64,
+}
+
+impl Plan {
+    fn new(
+        index: usize,
+        participant: &Participant,
+        participants: usize,
+        fee_rate: FeeRate,
+    ) -> Result<Self, Error> {
+        let invalid =
+            |msg: String| Error::InvalidRequest(format!("participant {}: {}", index, msg));
+        if participant.utxos.is_empty() {
+            return Err(invalid("no inputs".to_string()));
+        }
+        if participant.mix_address == participant.change_address {
+            return Err(invalid("mix and change addresses are the same".to_string()));
+        }
+        let mix_script = address::to_script(&participant.mix_address)
+            .ok_or_else(|| invalid(format!("invalid address {}", participant.mix_address)))?;
+        let change_script = address::to_script(&participant.change_address)
+            .ok_or_else(|| invalid(format!("invalid address {}", participant.change_address)))?;
+
+        let mut vsize =
+            TX_OVERHEAD_VSIZE.div_ceil(participants as u64) + output_vsize(mix_script.len());
+        let mut inputs = Vec::with_capacity(participant.utxos.len());
+        for utxo in &participant.utxos {
+            let txid: Txid = utxo
+                .txid
+                .parse()
+                .map_err(|_| invalid(format!("invalid txid {}", utxo.txid)))?;
+            let script = hex::decode(&utxo.script_pub_key)
+                .map(Script::from)
+                .map_err(|_| invalid(format!("invalid script of {}:{}", utxo.txid, utxo.vout)))?;
+            vsize += input_vsize(&script).ok_or_else(|| {
+                invalid(format!(
+                    "cannot estimate the size of spending {}:{}",
+                    utxo.txid, utxo.vout
+                ))
+            })?;
+            let txout = TxOut {
+                value: utxo.amount,
+                script_pubkey: script,
+            };
+            inputs.push((OutPoint::new(txid, utxo.vout), txout));
+        }
+
+        let total: u64 = inputs.iter().map(|(_, txout)| txout.value.to_sat()).sum();
+        if fee_for_vsize(fee_rate, vsize)? >= total {
+            return Err(invalid("inputs do not cover their own fee".to_string()));
+        }
+
+        Ok(Plan {
+            inputs,
+            mix_script,
+            change_script,
+            vsize,
+        })
+    }
+
+    fn total(&self) -> u64 {
+        self.inputs
+            .iter()
+            .map(|(_, txout)| txout.value.to_sat())
+            .sum()
+    }
+
+    /// The largest mix output the participant can pay without change.
+    fn affordable(&self, fee_rate: FeeRate) -> Result<u64, Error> {
+        Ok(self
+            .total()
+            .saturating_sub(fee_for_vsize(fee_rate, self.vsize)?))
+    }
+
+    fn contribution(&self, denomination: u64, fee_rate: FeeRate) -> Result<Contribution, Error> {
+        let fee_with_change =
+            fee_for_vsize(fee_rate, self.vsize + output_vsize(self.change_script.len()))?;
+        let change = (self.total() - denomination)
+            .checked_sub(fee_with_change)
+            .filter(|&change| change >= DUST);
+
+        let mut output = vec![TxOut {
+            value: Amount::from_sat(denomination),
+            script_pubkey: self.mix_script.clone(),
+        }];
+        if let Some(change) = change {
+            output.push(TxOut {
+                value: Amount::from_sat(change),
+                script_pubkey: self.change_script.clone(),
+            });
+        }
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: self
+                .inputs
+                .iter()
+                .map(|(outpoint, _)| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: Script::new(),
+                    sequence: 0xffff_ffff,
+                    witness: Vec::new(),
+                })
+                .collect(),
+            output,
+        };
+
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx)?;
+        for (input, (_, txout)) in psbt.inputs.iter_mut().zip(&self.inputs) {
+            // Legacy inputs need the whole previous transaction, which the
+            // wallet adds when it processes the PSBT.
+            if txout.script_pubkey.script_type() != ScriptType::P2pkh {
+                input.witness_utxo = Some(txout.clone());
+            }
+        }
+
+        Ok(Contribution {
+            psbt,
+            spent: self.inputs.iter().map(|(_, txout)| txout.clone()).collect(),
+            change_index: change.map(|_| 1),
+        })
+    }
+}
+
+/// The largest amount of the form 1, 2 or 5 times a power of ten satoshis
+/// not above `max`, so rounds with similar funds mix the same amounts.
+fn standard_denomination(max: u64) -> u64 {
+    let mut best = 0;
+    let mut power = 1u64;
+    while power <= max {
+        for step in [1, 2, 5] {
+            if let Some(denomination) = power.checked_mul(step).filter(|&d| d <= max) {
+                best = best.max(denomination);
+            }
+        }
+        match power.checked_mul(10) {
+            Some(next) => power = next,
+            None => break,
+        }
+    }
+    best
+}
+
+/// How the fee of a joined transaction is divided between participants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSplit {
+    /// Each participant pays for the size of their inputs and outputs, and
+    /// the transaction overhead is shared equally.
+    #[default]
+    Weight,
+    /// Every participant pays the same.
+    Equal,
+}
+
+impl fmt::Display for FeeSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FeeSplit::Weight => "weight",
+            FeeSplit::Equal => "equal",
+        })
+    }
+}
+
+/// What one participant pays towards the fee.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeShare {
+    /// The size of the participant's inputs and outputs.
+    pub vsize: u64,
+    pub fee: Amount,
+    /// The participant's change after paying the fee.
+    pub change: Option<Amount>,
+}
+
+/// How the fee of a joined transaction was divided, for every participant to
+/// check before signing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeAllocation {
+    pub split: FeeSplit,
+    pub fee_rate: FeeRate,
+    /// The estimated size of the joined transaction once signed.
+    pub vsize: u64,
+    /// The total fee, above the target when a participant without change
+    /// pays more than their share.
+    pub fee: Amount,
+    /// The shares, in the order of the contributions.
+    pub shares: Vec<FeeShare>,
+}
+
+impl fmt::Display for FeeAllocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "fee: {} for {} vB at {}, {} split",
+            self.fee.display_sat(),
+            self.vsize,
+            self.fee_rate,
+            self.split
+        )?;
+        for (index, share) in self.shares.iter().enumerate() {
+            write!(
+                f,
+                "  participant {}: {} vB, pays {}",
+                index,
+                share.vsize,
+                share.fee.display_sat()
+            )?;
+            match share.change {
+                Some(change) => writeln!(f, ", change {}", change)?,
+                None => writeln!(f, ", no change")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Divides the fee of the transaction joining `contributions` at `fee_rate`
+/// and adjusts each participant's change output to pay their share.
+///
+/// A participant without change keeps paying what their contribution leaves
+/// to the fee, which must cover their share. The contributions must not be
+/// signed yet.
+pub fn allocate_fees(
+    contributions: &mut [Contribution],
+    fee_rate: FeeRate,
+    split: FeeSplit,
+) -> Result<FeeAllocation, Error> {
+    if contributions.is_empty() {
+        return Err(Error::InvalidRequest(
+            "there are no contributions to allocate fees to".to_string(),
+        ));
+    }
+    if let Some(index) = contributions.iter().position(|contribution| {
+        contribution
+            .psbt
+            .inputs
+            .iter()
+            .any(|input| input_sighashes(input).next().is_some())
+    }) {
+        return Err(Error::InvalidRequest(format!(
+            "contribution {} is already signed",
+            index
+        )));
+    }
+
+    let vsizes = contributions
+        .iter()
+        .map(Contribution::vsize)
+        .collect::<Result<Vec<_>, _>>()?;
+    let vsize = TX_OVERHEAD_VSIZE + vsizes.iter().sum::<u64>();
+    let target = fee_for_vsize(fee_rate, vsize)?;
+
+    let count = contributions.len() as u64;
+    let weights: Vec<u64> = match split {
+        // Scaled by the participant count, so the overhead divides evenly.
+        FeeSplit::Weight => vsizes
+            .iter()
+            .map(|vsize| vsize * count + TX_OVERHEAD_VSIZE)
+            .collect(),
+        FeeSplit::Equal => vec![1; contributions.len()],
+    };
+    let fees = split_proportionally(target, &weights);
+
+    let mut shares = Vec::with_capacity(contributions.len());
+    for (index, (contribution, fee)) in contributions.iter_mut().zip(fees).enumerate() {
+        let paid = contribution.fee().to_sat();
+        let share = match contribution.change_index {
+            Some(change_index) => {
+                let txout = &mut contribution.psbt.unsigned_tx.output[change_index];
+                let change = (txout.value.to_sat() + paid)
+                    .checked_sub(fee)
+                    .filter(|&change| change >= DUST)
+                    .ok_or_else(|| {
+                        Error::InvalidRequest(format!(
+                            "participant {} cannot pay {} towards the fee from their change",
+                            index,
+                            Amount::from_sat(fee)
+                        ))
+                    })?;
+                txout.value = Amount::from_sat(change);
+                FeeShare {
+                    vsize: vsizes[index],
+                    fee: Amount::from_sat(fee),
+                    change: Some(txout.value),
+                }
+            }
+            None if paid >= fee => FeeShare {
+                vsize: vsizes[index],
+                fee: Amount::from_sat(paid),
+                change: None,
+            },
+            None => {
+                return Err(Error::InvalidRequest(format!(
+                    "participant {} pays {} towards the fee, less than their share of {}, \
+                     and has no change to pay the rest",
+                    index,
+                    Amount::from_sat(paid),
+                    Amount::from_sat(fee)
+                )))
+            }
+        };
+        shares.push(share);
+    }
+
+    Ok(FeeAllocation {
+        split,
+        fee_rate,
+        vsize,
+        fee: Amount::from_sat(shares.iter().map(|share| share.fee.to_sat()).sum()),
+        shares,
+    })
+}
+
+/// Divides `total` in proportion to `weights`, giving the satoshis lost to
+/// rounding to the largest remainders so the parts add up to `total`.
+fn split_proportionally(total: u64, weights: &[u64]) -> Vec<u64> {
+    let sum: u128 = weights.iter().map(|&weight| weight as u128).sum();
+    let mut parts = Vec::with_capacity(weights.len());
+    let mut remainders = Vec::with_capacity(weights.len());
+    for (index, &weight) in weights.iter().enumerate() {
+        let exact = total as u128 * weight as u128;
+        parts.push((exact / sum) as u64);
+        remainders.push((exact % sum, index));
+    }
+
+    let left = total - parts.iter().sum::<u64>();
+    // Largest remainder first, then the earlier participant.
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for &(_, index) in remainders.iter().take(left as usize) {
+        parts[index] += 1;
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+
+    /// A participant spending P2WPKH outputs worth `amounts`, with mix and
+    /// change addresses derived from `id`.
+    fn participant(id: u8, amounts: &[u64]) -> Participant {
+        let address = |byte: u8| {
+            let script = Script([[0x00, 0x14].as_slice(), &[byte; 20]].concat());
+            address::from_script(&script, Network::Regtest).unwrap()
+        };
+        Participant {
+            utxos: amounts
+                .iter()
+                .enumerate()
+                .map(|(vout, &amount)| UnspentTxOutputs {
+                    txid: hex::encode([id; 32]),
+                    vout: vout as u32,
+                    address: None,
+                    label: None,
+                    script_pub_key: format!("0014{}", hex::encode([id; 20])),
+                    amount: Amount::from_sat(amount),
+                    confirmations: 6,
+                    spendable: true,
+                    solvable: true,
+                    desc: None,
+                    parent_descs: Vec::new(),
+                    safe: true,
+                })
+                .collect(),
+            mix_address: address(id + 100),
+            change_address: address(id + 200),
+        }
+    }
+
+    fn builder() -> CoinjoinBuilder {
+        CoinjoinBuilder::new(FeeRate::from_sat_per_vb(10).unwrap())
+            .participant(participant(1, &[1_500_000]))
+            .participant(participant(2, &[700_000, 600_000]))
+    }
+
+    fn values(contribution: &Contribution) -> Vec<u64> {
+        contribution
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.value.to_sat())
+            .collect()
+    }
+
+    #[test]
+    fn standard_denominations() {
+        assert_eq!(standard_denomination(0), 0);
+        assert_eq!(standard_denomination(1), 1);
+        assert_eq!(standard_denomination(4), 2);
+        assert_eq!(standard_denomination(9), 5);
+        assert_eq!(standard_denomination(250), 200);
+        assert_eq!(standard_denomination(2_000_000), 2_000_000);
+        assert_eq!(standard_denomination(1_298_270), 1_000_000);
+        assert_eq!(standard_denomination(u64::MAX), 10_000_000_000_000_000_000);
+    }
+
+    #[test]
+    fn build_picks_a_denomination_everyone_can_afford() {
+        // Each participant pays for their inputs, mix output and half the
+        // overhead: 105 vB for the first, who can afford 1,498,950 sat, and
+        // 173 vB for the second, who can afford 1,298,270 sat.
+        let coinjoin = builder().build().unwrap();
+        assert_eq!(coinjoin.denomination, Amount::from_sat(1_000_000));
+        assert_eq!(coinjoin.contributions.len(), 2);
+
+        // Change pays for its own output too.
+        let first = &coinjoin.contributions[0];
+        assert_eq!(values(first), [1_000_000, 498_640]);
+        assert_eq!(first.change_index, Some(1));
+        assert_eq!(first.change(), Some(Amount::from_sat(498_640)));
+        assert_eq!(first.fee(), Amount::from_sat(1_360));
+        assert_eq!(first.vsize().unwrap(), 68 + 31 + 31);
+
+        let second = &coinjoin.contributions[1];
+        assert_eq!(values(second), [1_000_000, 297_960]);
+        assert_eq!(second.fee(), Amount::from_sat(2_040));
+        assert_eq!(second.psbt.inputs.len(), 2);
+        assert_eq!(second.spent.len(), 2);
+        assert!(second
+            .psbt
+            .inputs
+            .iter()
+            .all(|input| input.witness_utxo.is_some()));
+
+        // The mix outputs cannot be told apart by value or script type.
+        let mix: Vec<&TxOut> = coinjoin
+            .contributions
+            .iter()
+            .map(|contribution| &contribution.psbt.unsigned_tx.output[0])
+            .collect();
+        assert_eq!(mix[0].value, mix[1].value);
+        assert_eq!(
+            mix[0].script_pubkey.script_type(),
+            mix[1].script_pubkey.script_type()
+        );
+    }
+
+    #[test]
+    fn change_too_small_to_pay_for_is_left_to_the_fee() {
+        let coinjoin = builder()
+            .denomination(Amount::from_sat(1_298_270))
+            .build()
+            .unwrap();
+        assert_eq!(values(&coinjoin.contributions[0]), [1_298_270, 200_370]);
+        assert_eq!(values(&coinjoin.contributions[1]), [1_298_270]);
+        assert_eq!(coinjoin.contributions[1].change_index, None);
+        assert_eq!(coinjoin.contributions[1].fee(), Amount::from_sat(1_730));
+    }
+
+    #[test]
+    fn unaffordable_denominations_are_rejected() {
+        match builder().denomination(Amount::from_sat(1_298_271)).build() {
+            Err(Error::InsufficientFunds { needed, available }) => {
+                assert_eq!(needed, Amount::from_sat(1_298_271));
+                assert_eq!(available, Amount::from_sat(1_298_270));
+            }
+            other => panic!("expected insufficient funds, got {:?}", other),
+        }
+
+        // A participant who can barely pay their fee limits everyone to dust.
+        let poor = builder().participant(participant(3, &[1_200]));
+        assert!(matches!(poor.build(), Err(Error::InsufficientFunds { .. })));
+
+        // One who cannot even pay their fee is rejected outright.
+        let broke = builder().participant(participant(3, &[1_000]));
+        match broke.build() {
+            Err(Error::InvalidRequest(message)) => {
+                assert_eq!(message, "participant 2: inputs do not cover their own fee")
+            }
+            other => panic!("expected an invalid request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_participants_are_rejected() {
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        let single = CoinjoinBuilder::new(fee_rate).participant(participant(1, &[1_500_000]));
+        assert!(matches!(single.build(), Err(Error::InvalidRequest(_))));
+
+        let twice = builder().participant(participant(1, &[1_500_000]));
+        assert!(matches!(twice.build(), Err(Error::InvalidRequest(_))));
+
+        let mut taproot = participant(3, &[1_500_000]);
+        let script = Script([[0x51, 0x20].as_slice(), &[3; 32]].concat());
+        taproot.mix_address = address::from_script(&script, Network::Regtest).unwrap();
+        assert!(matches!(
+            builder().participant(taproot).build(),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn split_proportionally_adds_up() {
+        assert_eq!(split_proportionally(10, &[1, 1, 1]), [4, 3, 3]);
+        assert_eq!(split_proportionally(100, &[1, 2]), [33, 67]);
+        assert_eq!(split_proportionally(0, &[5, 7]), [0, 0]);
+        assert_eq!(split_proportionally(7, &[1]), [7]);
+
+        let weights = [271, 407, 1_000, 3];
+        for total in [0, 1, 2_373, 3_390, 999_999] {
+            let parts = split_proportionally(total, &weights);
+            assert_eq!(parts.iter().sum::<u64>(), total);
+        }
+    }
+
+    /// Allocates the fee of the builder's contributions at `sat_per_vb`,
+    /// checking the shares add up to the fee of the joined transaction and
+    /// each contribution now pays its share.
+    fn allocate(sat_per_vb: u64, split: FeeSplit) -> FeeAllocation {
+        let fee_rate = FeeRate::from_sat_per_vb(sat_per_vb).unwrap();
+        let mut contributions = builder().build().unwrap().contributions;
+        let allocation = allocate_fees(&mut contributions, fee_rate, split).unwrap();
+
+        // 11 vB of overhead, 130 vB for the first and 198 vB for the second.
+        assert_eq!(allocation.vsize, 339);
+        assert_eq!(allocation.fee, Amount::from_sat(339 * sat_per_vb));
+        let shares: u64 = allocation
+            .shares
+            .iter()
+            .map(|share| share.fee.to_sat())
+            .sum();
+        assert_eq!(shares, 339 * sat_per_vb);
+        for (contribution, share) in contributions.iter().zip(&allocation.shares) {
+            assert_eq!(contribution.fee(), share.fee);
+            assert_eq!(contribution.change(), share.change);
+        }
+        allocation
+    }
+
+    fn fees(allocation: &FeeAllocation) -> Vec<u64> {
+        allocation
+            .shares
+            .iter()
+            .map(|share| share.fee.to_sat())
+            .collect()
+    }
+
+    #[test]
+    fn weight_split_pays_for_size() {
+        // Weighted 271 to 407: the size of each participant's inputs and
+        // outputs, twice, plus the overhead.
+        let even = allocate(10, FeeSplit::Weight);
+        assert_eq!(fees(&even), [1_355, 2_035]);
+        assert_eq!(even.shares[0].vsize, 130);
+        assert_eq!(even.shares[1].vsize, 198);
+        assert_eq!(even.shares[0].change, Some(Amount::from_sat(498_645)));
+
+        // 2,373 sat does not divide evenly; the satoshi left over goes to the
+        // first of the equal remainders.
+        assert_eq!(fees(&allocate(7, FeeSplit::Weight)), [949, 1_424]);
+    }
+
+    #[test]
+    fn equal_split_pays_the_same() {
+        assert_eq!(fees(&allocate(10, FeeSplit::Equal)), [1_695, 1_695]);
+        assert_eq!(fees(&allocate(7, FeeSplit::Equal)), [1_187, 1_186]);
+    }
+
+    #[test]
+    fn shares_that_cannot_be_paid_are_rejected() {
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        let without_change = || {
+            builder()
+                .denomination(Amount::from_sat(1_298_270))
+                .build()
+                .unwrap()
+                .contributions
+        };
+
+        // Without change, the second participant keeps paying 1,730 sat, more
+        // than their share of 1,725 sat, so the fee ends up above the target.
+        let allocation = allocate_fees(&mut without_change(), fee_rate, FeeSplit::Weight).unwrap();
+        assert_eq!(fees(&allocation), [1_355, 1_730]);
+        assert_eq!(allocation.shares[1].change, None);
+        assert_eq!(allocation.fee, Amount::from_sat(3_085));
+
+        // At twice the rate their share is 3,450 sat.
+        assert!(matches!(
+            allocate_fees(
+                &mut without_change(),
+                FeeRate::from_sat_per_vb(20).unwrap(),
+                FeeSplit::Weight
+            ),
+            Err(Error::InvalidRequest(_))
+        ));
+
+        let mut signed = builder().build().unwrap().contributions;
+        signed[1].psbt.inputs[0]
+            .partial_sigs
+            .insert(vec![2; 33], vec![0x30, 0x01]);
+        assert!(matches!(
+            allocate_fees(&mut signed, fee_rate, FeeSplit::Weight),
+            Err(Error::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            allocate_fees(&mut [], fee_rate, FeeSplit::Weight),
+            Err(Error::InvalidRequest(_))
+        ));
+    }
+}
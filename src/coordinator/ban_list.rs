@@ -0,0 +1,97 @@
+//! Outputs barred from rounds because their owner did not sign.
+
+use crate::error::Error;
+use crate::transaction::OutPoint;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// A file listing banned outputs, one `txid:vout` per line.
+///
+/// Outputs are compared parsed, so a txid matches in either case.
+#[derive(Debug, Clone)]
+pub struct BanList {
+    path: PathBuf,
+    outpoints: BTreeSet<OutPoint>,
+}
+
+impl BanList {
+    /// Reads the list at `path`, which is empty if the file does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let outpoints = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.parse()
+                    .map_err(|e| Error::Config(format!("ban list {}: {}", path.display(), e)))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(BanList { path, outpoints })
+    }
+
+    pub fn contains(&self, outpoint: &OutPoint) -> bool {
+        self.outpoints.contains(outpoint)
+    }
+
+    /// Bans `outpoints` and writes the list.
+    pub fn add(&mut self, outpoints: impl IntoIterator<Item = OutPoint>) -> Result<(), Error> {
+        self.outpoints.extend(outpoints);
+        let mut contents = String::new();
+        for outpoint in &self.outpoints {
+            contents.push_str(&outpoint.to_string());
+            contents.push('\n');
+        }
+        fs::write(&self.path, contents)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_lists_round_trip_through_their_file() {
+        let path = std::env::temp_dir().join(format!(
+            "psbt-guide-ban-list-{}-{:08x}.txt",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        let lower = format!("{}:1", "ab".repeat(32));
+        let upper = format!("{}:1", "AB".repeat(32));
+        let other = format!("{}:0", "Cd".repeat(32));
+        fs::write(&path, format!("{}\n\n  {}  \n", upper, other)).unwrap();
+
+        let mut ban_list = BanList::open(&path).unwrap();
+        assert!(ban_list.contains(&lower.parse().unwrap()));
+        assert!(ban_list.contains(&other.to_lowercase().parse().unwrap()));
+        assert!(!ban_list.contains(&format!("{}:0", "ab".repeat(32)).parse().unwrap()));
+
+        // Banning an output again, in another case, does not list it twice.
+        let third: OutPoint = format!("{}:2", "ef".repeat(32)).parse().unwrap();
+        ban_list
+            .add([lower.parse().unwrap(), upper.parse().unwrap(), third])
+            .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        assert!(contents.contains(&lower) && !contents.contains(&upper));
+
+        let reopened = BanList::open(&path).unwrap();
+        assert_eq!(reopened.outpoints, ban_list.outpoints);
+        assert!(reopened.contains(&third));
+        fs::remove_file(&path).unwrap();
+
+        assert!(BanList::open(&path).unwrap().outpoints.is_empty());
+        fs::write(&path, "not an outpoint\n").unwrap();
+        assert!(matches!(BanList::open(&path), Err(Error::Config(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+//! Talking to a coordinator from a participant's side.
+
+use super::{Assignment, RoundStatus, Stage};
+use crate::address;
+use crate::amount::{Amount, FeeRate};
+use crate::coin_selection::{fee_for_vsize, input_vsize, output_vsize, TX_OVERHEAD_VSIZE};
+use crate::coinjoin::Participant;
+use crate::error::Error;
+use crate::rpc::RpcClient;
+use crate::transaction::{OutPoint, Script};
+use crate::types::{ChangeType, ListUnspentOptions, Recipient};
+use crate::verify::SigningPolicy;
+use crate::workflow::{coinjoin_participant, wallet_process_psbt_verified};
+use reqwest::blocking::{Client as ReqClient, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::thread;
+use std::time::Duration;
+
+/// An HTTP client for a coordinator's [`server`](super::server).
+#[derive(Debug, Clone)]
+pub struct CoordinatorClient {
+    url: String,
+    http: ReqClient,
+}
+
+impl CoordinatorClient {
+    /// Creates a client for the coordinator at `url`, e.g.
+    /// `http://127.0.0.1:8339`.
+    pub fn new(url: impl Into<String>) -> Result<Self, Error> {
+        let http = ReqClient::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(CoordinatorClient {
+            url: url.into().trim_end_matches('/').to_string(),
+            http,
+        })
+    }
+
+    pub fn status(&self) -> Result<RoundStatus, Error> {
+        self.send(self.http.get(format!("{}/round", self.url)))
+    }
+
+    /// Registers `participant`, returning their token.
+    pub fn register(&self, participant: &Participant) -> Result<String, Error> {
+        let response: Value = self.send(
+            self.http
+                .post(format!("{}/register", self.url))
+                .json(participant),
+        )?;
+        response["token"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| Error::Coordinator("registration returned no token".to_string()))
+    }
+
+    pub fn assignment(&self, token: &str) -> Result<Assignment, Error> {
+        self.send(self.http.get(format!("{}/psbt/{}", self.url, token)))
+    }
+
+    /// Sends the participant's signed copy of the joined PSBT.
+    pub fn submit(&self, token: &str, psbt: &str) -> Result<RoundStatus, Error> {
+        let body = json!({ "psbt": psbt });
+        self.send(
+            self.http
+                .post(format!("{}/psbt/{}", self.url, token))
+                .json(&body),
+        )
+    }
+
+    /// Polls the round every `interval` until `done` holds for its status.
+    pub fn wait_until(
+        &self,
+        interval: Duration,
+        done: impl Fn(&RoundStatus) -> bool,
+    ) -> Result<RoundStatus, Error> {
+        loop {
+            let status = self.status()?;
+            if done(&status) {
+                return Ok(status);
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let response = request.send()?;
+        let status = response.status();
+        let body: Value = response.json()?;
+        if !status.is_success() {
+            let message = body["error"].as_str().unwrap_or("unknown error");
+            return Err(Error::Coordinator(message.to_string()));
+        }
+        Ok(serde_json::from_value(body)?)
+    }
+}
+
+/// The most a participant pays towards the fee of a round, whatever the
+/// coordinator asks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeCap {
+    Absolute(Amount),
+    /// A fee rate for the participant's inputs and outputs and the whole
+    /// transaction overhead.
+    Rate(FeeRate),
+}
+
+impl FeeCap {
+    /// The cap for `participant`, which fails for a rate if the size of
+    /// their inputs or addresses is unknown.
+    pub fn amount(self, participant: &Participant) -> Result<Amount, Error> {
+        let fee_rate = match self {
+            FeeCap::Absolute(amount) => return Ok(amount),
+            FeeCap::Rate(fee_rate) => fee_rate,
+        };
+        let mut vsize = TX_OVERHEAD_VSIZE;
+        for utxo in &participant.utxos {
+            vsize += hex::decode(&utxo.script_pub_key)
+                .ok()
+                .and_then(|script| input_vsize(&Script::from(script)))
+                .ok_or_else(|| {
+                    Error::InvalidRequest(format!(
+                        "cannot estimate the size of spending {}:{}",
+                        utxo.txid, utxo.vout
+                    ))
+                })?;
+        }
+        for address in [&participant.mix_address, &participant.change_address] {
+            let script = address::to_script(address)
+                .ok_or_else(|| Error::InvalidRequest(format!("invalid address {}", address)))?;
+            vsize += output_vsize(script.len());
+        }
+        Ok(Amount::from_sat(fee_for_vsize(fee_rate, vsize)?))
+    }
+}
+
+/// Takes part in a round with the wallet's outputs matching `options`,
+/// returning the txid of the coinjoin once broadcast.
+///
+/// The joined PSBT is only signed if it spends nothing else of the wallet,
+/// pays the mix address the denomination and the change address what the
+/// inputs leave after the fee, and costs no more than `max_fee`. If the round
+/// is rebuilt without participants who did not sign, the new joined PSBT is
+/// checked and signed again.
+pub fn mix(
+    client: &RpcClient,
+    coordinator: &CoordinatorClient,
+    options: &ListUnspentOptions,
+    address_type: Option<ChangeType>,
+    max_fee: FeeCap,
+    interval: Duration,
+) -> Result<String, Error> {
+    let participant = coinjoin_participant(client, options, address_type)?;
+    let max_fee = max_fee.amount(&participant)?;
+    let token = coordinator.register(&participant)?;
+
+    coordinator.wait_until(interval, |status| {
+        status.stage >= Stage::Joined || status.error.is_some()
+    })?;
+
+    // A round rebuilt without participants who did not sign is signed again.
+    loop {
+        let assignment = coordinator.assignment(&token)?;
+        let signed = sign_assignment(client, &participant, &assignment, max_fee)?;
+        coordinator.submit(&token, &signed)?;
+
+        let status = coordinator.wait_until(interval, |status| {
+            status.stage >= Stage::Broadcast
+                || status.error.is_some()
+                || status.attempt != assignment.attempt
+        })?;
+        match (&status.txid, &status.error) {
+            (Some(txid), None) => return Ok(txid.clone()),
+            (None, None) if status.stage <= Stage::Signing => continue,
+            _ => return Err(round_failed(&status)),
+        }
+    }
+}
+
+/// Signs the joined PSBT of `assignment` if it spends nothing else of the
+/// wallet, pays `participant` the denomination and their change, and costs
+/// no more than `max_fee`.
+///
+/// The change is what the participant's outputs leave after the denomination
+/// and the fee the coordinator asks for, which must agree with the change it
+/// reported.
+fn sign_assignment(
+    client: &RpcClient,
+    participant: &Participant,
+    assignment: &Assignment,
+    max_fee: Amount,
+) -> Result<String, Error> {
+    if assignment.fee > max_fee {
+        return Err(Error::Coordinator(format!(
+            "the round asks for a fee of {}, more than the most this participant pays, {}",
+            assignment.fee, max_fee
+        )));
+    }
+    let total = Amount::checked_sum(participant.utxos.iter().map(|utxo| utxo.amount));
+    let change = total
+        .and_then(|total| total.checked_sub(assignment.denomination))
+        .and_then(|left| left.checked_sub(assignment.fee))
+        .ok_or_else(|| {
+            Error::Coordinator(format!(
+                "the outputs of this participant cannot pay {} and a fee of {}",
+                assignment.denomination, assignment.fee
+            ))
+        })?;
+    let change = Some(change).filter(|&change| change > Amount::ZERO);
+    if change != assignment.change {
+        return Err(Error::Coordinator(format!(
+            "the round reports change of {}, but the outputs of this participant leave {}",
+            assignment.change.unwrap_or(Amount::ZERO),
+            change.unwrap_or(Amount::ZERO)
+        )));
+    }
+
+    let mut policy = SigningPolicy::new(max_fee).output(Recipient::address(
+        participant.mix_address.as_str(),
+        assignment.denomination,
+    ));
+    if let Some(change) = change {
+        policy = policy.output(Recipient::address(
+            participant.change_address.as_str(),
+            change,
+        ));
+    }
+    for utxo in &participant.utxos {
+        let txid = utxo
+            .txid
+            .parse()
+            .map_err(|_| Error::InvalidRequest(format!("invalid txid {}", utxo.txid)))?;
+        policy = policy.input(OutPoint::new(txid, utxo.vout));
+    }
+
+    // Left unfinalized, so the coordinator's node can verify the signatures.
+    let signed = wallet_process_psbt_verified(client, assignment.psbt.clone(), &policy, false)?;
+    Ok(signed.psbt)
+}
+
+fn round_failed(status: &RoundStatus) -> Error {
+    Error::Coordinator(format!(
+        "round stopped in the {} stage: {}",
+        status.stage,
+        status.error.as_deref().unwrap_or("it went on without this participant")
+    ))
+}
@@ -0,0 +1,213 @@
+//! A coordinator running a coinjoin round for participants on other wallets.
+//!
+//! Participants register the outputs they mix and fresh addresses. Once all
+//! have registered, the coordinator builds an equal-denomination coinjoin,
+//! splits its fee and joins the contributions. Each participant fetches the
+//! joined PSBT with their fee share, checks and signs it, and sends it back;
+//! the last signature combines, finalizes and broadcasts the transaction.
+//!
+//! A round created with a directory saves its state and every intermediate
+//! PSBT there after each step, so an interrupted round can be resumed from
+//! the last [`Stage`] it reached.
+//!
+//! Participants who do not sign before the deadline, whose inputs are still
+//! unsigned once the copies are combined, or whose signatures the node
+//! rejects, are excluded and the round is rebuilt with the others, optionally
+//! banning their outputs from later rounds with a [`BanList`].
+//!
+//! [`server::serve`] exposes a [`Round`] over HTTP and [`client`] talks to it.
+
+mod ban_list;
+pub mod client;
+mod round;
+pub mod server;
+
+use crate::amount::{Amount, FeeRate};
+use crate::coinjoin::FeeSplit;
+use crate::error::Error;
+use crate::psbt::{JoinOrder, PartiallySignedTransaction};
+use crate::rpc::RpcClient;
+use crate::transaction::{OutPoint, TxOut};
+use crate::workflow::{
+    broadcast_transaction, finalize_psbt, get_tx_out, transaction_confirmations,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub use ban_list::BanList;
+pub use client::CoordinatorClient;
+pub use round::Round;
+
+/// The settings of a round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundConfig {
+    /// How many participants the round waits for.
+    pub participants: usize,
+    pub fee_rate: FeeRate,
+    pub fee_split: FeeSplit,
+    /// The mix amount, by default the largest standard denomination every
+    /// participant can afford.
+    pub denomination: Option<Amount>,
+    pub order: JoinOrder,
+    /// How long participants have to sign once the round is joined, after
+    /// which the round is rebuilt without those who did not.
+    pub signing_timeout: Option<Duration>,
+    /// Where the outputs of excluded participants are banned from later
+    /// rounds, and registrations are checked against.
+    pub ban_list: Option<PathBuf>,
+}
+
+impl RoundConfig {
+    pub fn new(participants: usize, fee_rate: FeeRate) -> Self {
+        RoundConfig {
+            participants,
+            fee_rate,
+            fee_split: FeeSplit::default(),
+            denomination: None,
+            order: JoinOrder::default(),
+            signing_timeout: None,
+            ban_list: None,
+        }
+    }
+
+    pub fn fee_split(mut self, fee_split: FeeSplit) -> Self {
+        self.fee_split = fee_split;
+        self
+    }
+
+    pub fn denomination(mut self, denomination: Amount) -> Self {
+        self.denomination = Some(denomination);
+        self
+    }
+
+    pub fn order(mut self, order: JoinOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn signing_timeout(mut self, signing_timeout: Duration) -> Self {
+        self.signing_timeout = Some(signing_timeout);
+        self
+    }
+
+    pub fn ban_list(mut self, ban_list: impl Into<PathBuf>) -> Self {
+        self.ban_list = Some(ban_list.into());
+        self
+    }
+}
+
+/// Where a round is. Each stage is reached once the step it names is done,
+/// and a round persisted to disk resumes from the last one reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Waiting for participants to register.
+    Created,
+    /// Every participant registered their inputs.
+    InputsRegistered,
+    /// The contributions were built and joined, waiting for signatures.
+    Joined,
+    /// Some participants sent their signed copy.
+    Signing,
+    /// Every signed copy was combined.
+    Combined,
+    /// The combined PSBT was finalized into a transaction.
+    Finalized,
+    /// The transaction was broadcast.
+    Broadcast,
+    /// The transaction was mined.
+    Confirmed,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Created => "created",
+            Stage::InputsRegistered => "inputs registered",
+            Stage::Joined => "joined",
+            Stage::Signing => "signing",
+            Stage::Combined => "combined",
+            Stage::Finalized => "finalized",
+            Stage::Broadcast => "broadcast",
+            Stage::Confirmed => "confirmed",
+        })
+    }
+}
+
+/// What a coordinator needs from a node to complete a round.
+pub trait Node {
+    /// Sends the transaction `hex`, returning its txid.
+    fn broadcast(&self, hex: String) -> Result<String, Error>;
+
+    /// How many confirmations the transaction `txid` has, `None` if unknown.
+    fn confirmations(&self, txid: &str) -> Result<Option<u32>, Error>;
+
+    /// The inputs of `psbt` the node cannot finalize, by index, because
+    /// their signatures are missing or do not verify.
+    fn unverified_inputs(&self, psbt: String) -> Result<Vec<usize>, Error>;
+
+    /// The output `outpoint`, `None` if it is spent or does not exist.
+    fn unspent_output(&self, outpoint: OutPoint) -> Result<Option<TxOut>, Error>;
+}
+
+impl Node for RpcClient {
+    fn broadcast(&self, hex: String) -> Result<String, Error> {
+        broadcast_transaction(self, hex)
+    }
+
+    fn confirmations(&self, txid: &str) -> Result<Option<u32>, Error> {
+        transaction_confirmations(self, txid)
+    }
+
+    fn unverified_inputs(&self, psbt: String) -> Result<Vec<usize>, Error> {
+        let finalized = finalize_psbt(self, psbt)?;
+        let Some(psbt) = finalized.psbt.filter(|_| !finalized.complete) else {
+            return Ok(Vec::new());
+        };
+        let psbt = PartiallySignedTransaction::from_base64(&psbt)?;
+        Ok((0..psbt.inputs.len())
+            .filter(|&index| !psbt.inputs[index].is_finalized())
+            .collect())
+    }
+
+    fn unspent_output(&self, outpoint: OutPoint) -> Result<Option<TxOut>, Error> {
+        Ok(get_tx_out(self, outpoint)?.map(|txout| TxOut {
+            value: txout.value,
+            script_pubkey: txout.script_pub_key.hex,
+        }))
+    }
+}
+
+/// What everyone may know about a round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundStatus {
+    pub stage: Stage,
+    pub participants: usize,
+    pub registered: usize,
+    pub signed: usize,
+    /// Participants excluded for not signing.
+    pub excluded: usize,
+    /// How many times the round was rebuilt without them.
+    pub attempt: u32,
+    /// When the signing stage ends, in seconds since the Unix epoch.
+    pub signing_deadline: Option<u64>,
+    pub denomination: Option<Amount>,
+    pub txid: Option<String>,
+    pub error: Option<String>,
+}
+
+/// What a participant is asked to sign.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Assignment {
+    /// The joined, unsigned PSBT.
+    pub psbt: String,
+    pub denomination: Amount,
+    /// The participant's share of the fee.
+    pub fee: Amount,
+    /// The participant's change after paying their share.
+    pub change: Option<Amount>,
+    /// The attempt the PSBT belongs to. A rebuilt round must be signed again.
+    pub attempt: u32,
+}
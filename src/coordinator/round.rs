@@ -0,0 +1,1291 @@
+//! A coinjoin round and the directory it is saved in.
+//!
+//! The directory holds `round.json`, the state of the round, and one file per
+//! intermediate result: `contribution-<n>.psbt` and `joined.psbt` once
+//! joined, `signed-<n>.psbt` as copies arrive, `combined.psbt` and
+//! `transaction.hex` once finalized. `round.json` is written last, so it
+//! never refers to a file that is not there. Before a round is rebuilt, the
+//! files of the attempt are copied to `attempt-<n>/`.
+
+use super::{Assignment, BanList, Node, RoundConfig, RoundStatus, Stage};
+use crate::amount::Amount;
+use crate::coinjoin::{allocate_fees, CoinjoinBuilder, FeeAllocation, Participant};
+use crate::error::{Error, RpcCode};
+use crate::psbt::{self, Input, PartiallySignedTransaction};
+use crate::transaction::{OutPoint, Txid};
+use crate::workflow::{combine_psbt_offline, finalize_psbt_offline, join_psbt_offline};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const STATE_FILE: &str = "round.json";
+
+/// A coinjoin round, driven by the coordinator's requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Round {
+    config: RoundConfig,
+    stage: Stage,
+    /// Registered participants and the tokens identifying them.
+    participants: Vec<(String, Participant)>,
+    /// Participants removed for not signing.
+    excluded: Vec<Exclusion>,
+    /// How many times the round was rebuilt.
+    attempt: u32,
+    /// When the signing stage ends, in seconds since the Unix epoch.
+    signing_deadline: Option<u64>,
+    denomination: Option<Amount>,
+    allocation: Option<FeeAllocation>,
+    txid: Option<String>,
+    /// Why the last step failed. The round stops until it is resumed.
+    error: Option<String>,
+    /// Saved in files of their own.
+    #[serde(skip)]
+    psbts: Psbts,
+    /// Where the round is saved, if anywhere.
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+}
+
+/// A participant removed from the round and why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Exclusion {
+    token: String,
+    /// The outputs they registered.
+    outpoints: Vec<OutPoint>,
+    reason: String,
+}
+
+/// The PSBTs a round produces on its way to the final transaction.
+#[derive(Debug, Default)]
+struct Psbts {
+    /// Each participant's contribution, after paying their fee share.
+    contributions: Vec<String>,
+    joined: Option<String>,
+    /// The signed copies, in the order of the participants.
+    signed: Vec<Option<String>>,
+    combined: Option<String>,
+    /// The finalized transaction, in hex.
+    transaction: Option<String>,
+}
+
+impl Round {
+    /// Creates a round kept in memory only.
+    pub fn new(config: RoundConfig) -> Self {
+        Round {
+            config,
+            stage: Stage::Created,
+            participants: Vec::new(),
+            excluded: Vec::new(),
+            attempt: 0,
+            signing_deadline: None,
+            denomination: None,
+            allocation: None,
+            txid: None,
+            error: None,
+            psbts: Psbts::default(),
+            dir: None,
+        }
+    }
+
+    /// Creates a round saved in `dir` after every step. The directory is
+    /// created if needed and must not hold a round already.
+    pub fn create(config: RoundConfig, dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        if dir.join(STATE_FILE).exists() {
+            return Err(Error::InvalidRequest(format!(
+                "{} already holds a round",
+                dir.display()
+            )));
+        }
+        fs::create_dir_all(dir)?;
+
+        let mut round = Round::new(config);
+        round.dir = Some(dir.to_path_buf());
+        round.save()?;
+        Ok(round)
+    }
+
+    /// Loads the round saved in `dir`, at the last stage it reached.
+    /// [`advance`](Round::advance) retries the step that failed, if any.
+    pub fn resume(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let state = read_file(dir, STATE_FILE)?.ok_or_else(|| {
+            Error::InvalidRequest(format!("{} holds no round", dir.display()))
+        })?;
+        let mut round: Round = serde_json::from_str(&state)?;
+        round.dir = Some(dir.to_path_buf());
+
+        if round.stage >= Stage::Joined {
+            for index in 0..round.participants.len() {
+                let contribution = round.required(&format!("contribution-{}.psbt", index))?;
+                round.psbts.contributions.push(contribution);
+                let signed = read_file(dir, &format!("signed-{}.psbt", index))?;
+                round.psbts.signed.push(signed);
+            }
+            round.psbts.joined = Some(round.required("joined.psbt")?);
+        }
+        if round.stage >= Stage::Combined {
+            round.psbts.combined = Some(round.required("combined.psbt")?);
+        }
+        if round.stage >= Stage::Finalized {
+            round.psbts.transaction = Some(round.required("transaction.hex")?);
+        }
+        Ok(round)
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// When participants who have not signed yet are excluded, if the round
+    /// has a signing timeout and waits for signatures.
+    pub fn signing_deadline(&self) -> Option<SystemTime> {
+        match self.stage {
+            Stage::Joined | Stage::Signing => self
+                .signing_deadline
+                .map(|deadline| UNIX_EPOCH + Duration::from_secs(deadline)),
+            _ => None,
+        }
+    }
+
+    /// Whether the transaction is confirmed or a step failed.
+    pub fn is_over(&self) -> bool {
+        self.stage == Stage::Confirmed || self.error.is_some()
+    }
+
+    pub fn status(&self) -> RoundStatus {
+        RoundStatus {
+            stage: self.stage,
+            participants: self.config.participants,
+            registered: self.participants.len(),
+            signed: self.psbts.signed.iter().flatten().count(),
+            excluded: self.excluded.len(),
+            attempt: self.attempt,
+            signing_deadline: self.signing_deadline,
+            denomination: self.denomination,
+            txid: self.txid.clone(),
+            error: self.error.clone(),
+        }
+    }
+
+    /// Registers a participant, returning the token they use for the rest of
+    /// the round.
+    ///
+    /// Each registered output must be unspent on `node` and hold the amount
+    /// and script the participant describes, since the contributions are
+    /// built and their fees split from those descriptions.
+    pub fn register(
+        &mut self,
+        participant: Participant,
+        node: &impl Node,
+    ) -> Result<String, Error> {
+        self.expect_stage(&[Stage::Created])?;
+
+        // Compared parsed, as the same txid can be spelled in either case.
+        let mut registered = HashSet::new();
+        for (_, known) in &self.participants {
+            registered.extend(outpoints(known)?);
+        }
+        let outpoints = outpoints(&participant)?;
+        if let Some(outpoint) = outpoints
+            .iter()
+            .find(|&&outpoint| !registered.insert(outpoint))
+        {
+            return Err(Error::InvalidRequest(format!(
+                "input {} is already registered",
+                outpoint
+            )));
+        }
+        if let Some(path) = &self.config.ban_list {
+            let ban_list = BanList::open(path)?;
+            if let Some(outpoint) = outpoints
+                .iter()
+                .find(|outpoint| ban_list.contains(outpoint))
+            {
+                return Err(Error::InvalidRequest(format!(
+                    "input {} is banned for not signing an earlier round",
+                    outpoint
+                )));
+            }
+        }
+        for (utxo, &outpoint) in participant.utxos.iter().zip(&outpoints) {
+            let Some(txout) = node.unspent_output(outpoint)? else {
+                return Err(Error::InvalidRequest(format!(
+                    "input {} is spent or does not exist",
+                    outpoint
+                )));
+            };
+            if txout.value != utxo.amount {
+                return Err(Error::InvalidRequest(format!(
+                    "input {} holds {}, not {}",
+                    outpoint, txout.value, utxo.amount
+                )));
+            }
+            if hex::decode(&utxo.script_pub_key).ok().as_deref()
+                != Some(txout.script_pubkey.as_bytes())
+            {
+                return Err(Error::InvalidRequest(format!(
+                    "input {} pays to {}, not {}",
+                    outpoint, txout.script_pubkey, utxo.script_pub_key
+                )));
+            }
+        }
+
+        let mut token = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut token);
+        let token = hex::encode(token);
+        self.participants.push((token.clone(), participant));
+        if self.participants.len() == self.config.participants {
+            self.stage = Stage::InputsRegistered;
+        }
+        self.save()?;
+        Ok(token)
+    }
+
+    /// The joined PSBT and the share of the participant holding `token`.
+    pub fn assignment(&self, token: &str) -> Result<Assignment, Error> {
+        self.expect_stage(&[Stage::Joined, Stage::Signing])?;
+        let index = self.participant(token)?;
+        let (Some(joined), Some(denomination), Some(allocation)) =
+            (&self.psbts.joined, self.denomination, &self.allocation)
+        else {
+            return Err(Error::Coordinator(format!(
+                "the round is in the {} stage without a joined PSBT and its fee allocation",
+                self.stage
+            )));
+        };
+
+        let share = allocation.shares.get(index).ok_or_else(|| {
+            Error::Coordinator(format!(
+                "the fee allocation has no share for participant {}",
+                index
+            ))
+        })?;
+        Ok(Assignment {
+            psbt: joined.clone(),
+            denomination,
+            fee: share.fee,
+            change: share.change,
+            attempt: self.attempt,
+        })
+    }
+
+    /// Accepts the signed copy of the participant holding `token`.
+    ///
+    /// The copy must combine with the joined PSBT and carry partial
+    /// signatures rather than finalized inputs, so the node can tell whose
+    /// signatures are invalid if it rejects the transaction.
+    pub fn submit(&mut self, token: &str, psbt: String) -> Result<(), Error> {
+        self.expect_stage(&[Stage::Joined, Stage::Signing])?;
+        let index = self.participant(token)?;
+
+        let signed = PartiallySignedTransaction::from_base64(&psbt)?;
+        let joined = self.psbts.joined.as_deref().ok_or_else(|| {
+            Error::Coordinator(format!(
+                "the round is in the {} stage without a joined PSBT",
+                self.stage
+            ))
+        })?;
+        let joined = PartiallySignedTransaction::from_base64(joined)?;
+        if signed.unsigned_tx.txid() != joined.unsigned_tx.txid() {
+            return Err(Error::InvalidRequest(
+                "the PSBT is not a copy of the joined PSBT".to_string(),
+            ));
+        }
+        if let Some(input) = signed.inputs.iter().position(Input::is_finalized) {
+            return Err(Error::InvalidRequest(format!(
+                "input {} is finalized, send partial signatures instead",
+                input
+            )));
+        }
+        if let Err(e) = psbt::combine(&[joined, signed]) {
+            return Err(Error::InvalidRequest(format!(
+                "the PSBT does not combine with the joined PSBT: {}",
+                e
+            )));
+        }
+        self.psbts.signed[index] = Some(psbt);
+        self.stage = Stage::Signing;
+        self.save()
+    }
+
+    /// Takes every step the round is ready for, saving it after each: joins
+    /// once everyone registered, then combines, finalizes and broadcasts once
+    /// everyone signed, and checks whether the transaction is confirmed.
+    ///
+    /// Once the signing deadline passes, or if inputs are still unsigned after
+    /// combining, their owners are excluded and the round is joined again
+    /// with the others, who must sign the new PSBT.
+    ///
+    /// A failing step is recorded as the round's error, which stops the round
+    /// until `advance` is called again to retry it. Only a failure to save is
+    /// returned.
+    pub fn advance(&mut self, node: &impl Node) -> Result<(), Error> {
+        if self.error.take().is_some() {
+            self.save()?;
+        }
+        loop {
+            let stage = self.stage;
+            let result = match stage {
+                Stage::InputsRegistered => self.join(),
+                Stage::Signing if self.psbts.signed.iter().all(Option::is_some) => {
+                    self.combine()
+                }
+                Stage::Joined | Stage::Signing if self.signing_expired() => {
+                    let unsigned: Vec<usize> = (0..self.participants.len())
+                        .filter(|&index| self.psbts.signed[index].is_none())
+                        .collect();
+                    self.rebuild(&unsigned, "did not sign before the deadline")
+                }
+                Stage::Combined => self.finalize(),
+                Stage::Finalized => self.broadcast(node),
+                Stage::Broadcast => self.confirm(node),
+                _ => return Ok(()),
+            };
+            match result {
+                Err(e) => {
+                    self.error = Some(e.to_string());
+                    return self.save();
+                }
+                Ok(()) if self.stage == stage => return Ok(()),
+                Ok(()) => self.save()?,
+            }
+        }
+    }
+
+    /// Builds the contributions, splits the fee and joins them.
+    fn join(&mut self) -> Result<(), Error> {
+        let mut builder = CoinjoinBuilder::new(self.config.fee_rate);
+        if let Some(denomination) = self.config.denomination {
+            builder = builder.denomination(denomination);
+        }
+        for (_, participant) in &self.participants {
+            builder = builder.participant(participant.clone());
+        }
+
+        let mut coinjoin = builder.build()?;
+        let allocation = allocate_fees(
+            &mut coinjoin.contributions,
+            self.config.fee_rate,
+            self.config.fee_split,
+        )?;
+        let contributions: Vec<String> = coinjoin
+            .contributions
+            .iter()
+            .map(|contribution| contribution.psbt.to_base64())
+            .collect();
+
+        self.psbts.joined = Some(join_psbt_offline(&contributions, self.config.order)?);
+        self.psbts.signed = vec![None; contributions.len()];
+        self.psbts.contributions = contributions;
+        self.denomination = Some(coinjoin.denomination);
+        self.allocation = Some(allocation);
+        self.signing_deadline = self.config.signing_timeout.map(|timeout| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            (now + timeout).as_secs()
+        });
+        self.stage = Stage::Joined;
+        Ok(())
+    }
+
+    fn combine(&mut self) -> Result<(), Error> {
+        let signed: Vec<String> = self.psbts.signed.iter().flatten().cloned().collect();
+        self.psbts.combined = Some(combine_psbt_offline(&signed)?);
+        self.stage = Stage::Combined;
+        Ok(())
+    }
+
+    /// Finalizes the combined PSBT, or rebuilds the round without the owners
+    /// of the inputs that cannot be finalized.
+    fn finalize(&mut self) -> Result<(), Error> {
+        let combined = self.psbts.combined.clone().unwrap_or_default();
+        let inputs = PartiallySignedTransaction::from_base64(&combined)?
+            .unsigned_tx
+            .input;
+        let finalized = finalize_psbt_offline(combined)?;
+        if !finalized.complete {
+            let owned = self.owned_outpoints()?;
+            let mut unsigned = BTreeSet::new();
+            for incomplete in &finalized.incomplete {
+                let outpoint = inputs[incomplete.index].previous_output;
+                match owned.iter().position(|owned| owned.contains(&outpoint)) {
+                    Some(owner) => unsigned.insert(owner),
+                    None => {
+                        return Err(Error::InvalidRequest(format!(
+                            "the combined PSBT is incomplete: {}",
+                            incomplete
+                        )))
+                    }
+                };
+            }
+            let unsigned: Vec<usize> = unsigned.into_iter().collect();
+            return self.rebuild(&unsigned, "left their inputs unsigned");
+        }
+        self.psbts.transaction = Some(finalized.hex);
+        self.stage = Stage::Finalized;
+        Ok(())
+    }
+
+    /// Broadcasts the transaction. If the node rejects it, the round is
+    /// rebuilt without the owners of the inputs whose signatures it cannot
+    /// verify.
+    fn broadcast(&mut self, node: &impl Node) -> Result<(), Error> {
+        let transaction = self.psbts.transaction.clone().unwrap_or_default();
+        let error = match node.broadcast(transaction) {
+            Ok(txid) => {
+                self.txid = Some(txid);
+                self.stage = Stage::Broadcast;
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        if !matches!(
+            error.rpc_code(),
+            Some(RpcCode::VerifyError | RpcCode::VerifyRejected)
+        ) {
+            return Err(error);
+        }
+
+        let combined = self.psbts.combined.clone().unwrap_or_default();
+        let inputs = PartiallySignedTransaction::from_base64(&combined)?
+            .unsigned_tx
+            .input;
+        let unverified = node.unverified_inputs(combined)?;
+        let owned = self.owned_outpoints()?;
+        let mut blamed = BTreeSet::new();
+        for index in unverified {
+            let owner = inputs.get(index).and_then(|txin| {
+                owned
+                    .iter()
+                    .position(|owned| owned.contains(&txin.previous_output))
+            });
+            match owner {
+                Some(owner) => blamed.insert(owner),
+                None => return Err(error),
+            };
+        }
+        if blamed.is_empty() {
+            return Err(error);
+        }
+        let blamed: Vec<usize> = blamed.into_iter().collect();
+        self.rebuild(&blamed, "signed inputs the node rejected")
+    }
+
+    /// Moves to [`Stage::Confirmed`] once the transaction has a confirmation.
+    fn confirm(&mut self, node: &impl Node) -> Result<(), Error> {
+        let txid = self.txid.as_deref().unwrap_or_default();
+        if node.confirmations(txid)?.is_some_and(|confirmations| confirmations > 0) {
+            self.stage = Stage::Confirmed;
+        }
+        Ok(())
+    }
+
+    fn signing_expired(&self) -> bool {
+        self.signing_deadline()
+            .is_some_and(|deadline| deadline <= SystemTime::now())
+    }
+
+    /// Excludes the participants at `blamed`, bans their outputs if the round
+    /// has a ban list, and goes back to join the others.
+    ///
+    /// Nothing is banned if too few participants are left, as the round then
+    /// stops with an error and may be retried.
+    fn rebuild(&mut self, blamed: &[usize], reason: &str) -> Result<(), Error> {
+        let left = self.participants.len() - blamed.len();
+        if left < 2 {
+            return Err(Error::InvalidRequest(format!(
+                "{} of {} participants {}, too few are left to rebuild the round",
+                blamed.len(),
+                self.participants.len(),
+                reason
+            )));
+        }
+
+        let owned = blamed
+            .iter()
+            .map(|&index| outpoints(&self.participants[index].1))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(path) = &self.config.ban_list {
+            BanList::open(path)?.add(owned.iter().flatten().copied())?;
+        }
+
+        self.archive()?;
+        for (&index, outpoints) in blamed.iter().zip(owned).rev() {
+            let (token, _) = self.participants.remove(index);
+            self.excluded.push(Exclusion {
+                token,
+                outpoints,
+                reason: reason.to_string(),
+            });
+        }
+        self.psbts = Psbts::default();
+        self.denomination = None;
+        self.allocation = None;
+        self.signing_deadline = None;
+        self.attempt += 1;
+        self.stage = Stage::InputsRegistered;
+        Ok(())
+    }
+
+    /// Copies the files of the current attempt to `attempt-<n>/`.
+    fn archive(&self) -> Result<(), Error> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        let archive = dir.join(format!("attempt-{}", self.attempt));
+        fs::create_dir_all(&archive)?;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), archive.join(entry.file_name()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The outputs each participant registered, in their order.
+    fn owned_outpoints(&self) -> Result<Vec<Vec<OutPoint>>, Error> {
+        self.participants
+            .iter()
+            .map(|(_, participant)| outpoints(participant))
+            .collect()
+    }
+
+    fn participant(&self, token: &str) -> Result<usize, Error> {
+        if let Some(exclusion) = self.excluded.iter().find(|known| known.token == token) {
+            return Err(Error::InvalidRequest(format!(
+                "the participant was excluded from the round: they {}",
+                exclusion.reason
+            )));
+        }
+        self.participants
+            .iter()
+            .position(|(known, _)| known == token)
+            .ok_or_else(|| Error::InvalidRequest("unknown participant".to_string()))
+    }
+
+    fn expect_stage(&self, stages: &[Stage]) -> Result<(), Error> {
+        if let Some(error) = &self.error {
+            return Err(Error::InvalidRequest(format!(
+                "the round stopped in the {} stage: {}",
+                self.stage, error
+            )));
+        }
+        if !stages.contains(&self.stage) {
+            let expected: Vec<String> = stages.iter().map(ToString::to_string).collect();
+            return Err(Error::InvalidRequest(format!(
+                "the round is in the {} stage, not {}",
+                self.stage,
+                expected.join(" or ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Writes the intermediate PSBTs, then the state referring to them.
+    fn save(&self) -> Result<(), Error> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+
+        for (index, contribution) in self.psbts.contributions.iter().enumerate() {
+            write_file(dir, &format!("contribution-{}.psbt", index), contribution)?;
+        }
+        if let Some(joined) = &self.psbts.joined {
+            write_file(dir, "joined.psbt", joined)?;
+        }
+        for (index, signed) in self.psbts.signed.iter().enumerate() {
+            if let Some(signed) = signed {
+                write_file(dir, &format!("signed-{}.psbt", index), signed)?;
+            }
+        }
+        // Copies of an earlier attempt must not be resumed with.
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let index = name
+                .strip_prefix("signed-")
+                .and_then(|rest| rest.strip_suffix(".psbt"))
+                .and_then(|index| index.parse::<usize>().ok());
+            if index.is_some_and(|index| self.psbts.signed.get(index).is_none_or(Option::is_none)) {
+                remove_file(dir, name)?;
+            }
+        }
+        if let Some(combined) = &self.psbts.combined {
+            write_file(dir, "combined.psbt", combined)?;
+        }
+        if let Some(transaction) = &self.psbts.transaction {
+            write_file(dir, "transaction.hex", transaction)?;
+        }
+        write_file(dir, STATE_FILE, &serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads a file the round's stage says was saved.
+    fn required(&self, name: &str) -> Result<String, Error> {
+        let dir = self.dir.as_deref().unwrap_or(Path::new("."));
+        read_file(dir, name)?.ok_or_else(|| {
+            Error::InvalidRequest(format!(
+                "{} is missing from {}, which the {} stage needs",
+                name,
+                dir.display(),
+                self.stage
+            ))
+        })
+    }
+}
+
+/// The outputs a participant registered, which fails if a txid is invalid.
+fn outpoints(participant: &Participant) -> Result<Vec<OutPoint>, Error> {
+    participant
+        .utxos
+        .iter()
+        .map(|utxo| {
+            let txid: Txid = utxo
+                .txid
+                .parse()
+                .map_err(|_| Error::InvalidRequest(format!("invalid txid {}", utxo.txid)))?;
+            Ok(OutPoint::new(txid, utxo.vout))
+        })
+        .collect()
+}
+
+fn read_file(dir: &Path, name: &str) -> Result<Option<String>, Error> {
+    match fs::read_to_string(dir.join(name)) {
+        Ok(contents) => Ok(Some(contents.trim().to_string())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn remove_file(dir: &Path, name: &str) -> Result<(), Error> {
+    match fs::remove_file(dir.join(name)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Writes through a temporary file, so an interruption leaves either the old
+/// or the new contents.
+fn write_file(dir: &Path, name: &str, contents: &str) -> Result<(), Error> {
+    let path = dir.join(name);
+    let temporary = dir.join(format!("{}.tmp", name));
+    fs::write(&temporary, format!("{}\n", contents))?;
+    fs::rename(temporary, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address;
+    use crate::amount::FeeRate;
+    use crate::network::Network;
+    use crate::transaction::{Script, Transaction, TxOut};
+    use crate::types::UnspentTxOutputs;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    /// A node holding the outputs it was given, which rejects transactions
+    /// spending `rejected` outputs as badly signed.
+    #[derive(Default)]
+    struct StubNode {
+        utxos: HashMap<OutPoint, TxOut>,
+        rejected: RefCell<Vec<OutPoint>>,
+        broadcast: RefCell<Vec<Transaction>>,
+        confirmations: Option<u32>,
+    }
+
+    impl StubNode {
+        fn with(ids: &[u8]) -> Self {
+            let mut node = StubNode::default();
+            for &id in ids {
+                node.utxos.insert(
+                    outpoint(id),
+                    TxOut {
+                        value: Amount::from_sat(AMOUNT),
+                        script_pubkey: script(id),
+                    },
+                );
+            }
+            node
+        }
+    }
+
+    impl Node for StubNode {
+        fn broadcast(&self, hex: String) -> Result<String, Error> {
+            let tx = Transaction::deserialize(&hex::decode(hex).unwrap()).unwrap();
+            let rejected = self.rejected.borrow();
+            if tx
+                .input
+                .iter()
+                .any(|txin| rejected.contains(&txin.previous_output))
+            {
+                return Err(Error::Rpc {
+                    code: RpcCode::VerifyRejected,
+                    message: "mandatory-script-verify-flag-failed".to_string(),
+                });
+            }
+            let txid = tx.txid().to_string();
+            self.broadcast.borrow_mut().push(tx);
+            Ok(txid)
+        }
+
+        fn confirmations(&self, _txid: &str) -> Result<Option<u32>, Error> {
+            Ok(self.confirmations)
+        }
+
+        fn unverified_inputs(&self, psbt: String) -> Result<Vec<usize>, Error> {
+            let psbt = PartiallySignedTransaction::from_base64(&psbt)?;
+            let rejected = self.rejected.borrow();
+            Ok((0..psbt.inputs.len())
+                .filter(|&index| rejected.contains(&psbt.unsigned_tx.input[index].previous_output))
+                .collect())
+        }
+
+        fn unspent_output(&self, outpoint: OutPoint) -> Result<Option<TxOut>, Error> {
+            Ok(self.utxos.get(&outpoint).cloned())
+        }
+    }
+
+    const AMOUNT: u64 = 1_200_000;
+
+    fn txid(id: u8) -> String {
+        hex::encode([id; 32])
+    }
+
+    fn outpoint(id: u8) -> OutPoint {
+        OutPoint::new(txid(id).parse().unwrap(), 0)
+    }
+
+    fn script(id: u8) -> Script {
+        Script([[0x51, 0x20].as_slice(), &[id; 32]].concat())
+    }
+
+    /// A participant spending one taproot output worth [`AMOUNT`].
+    fn participant(id: u8) -> Participant {
+        let address = |byte: u8| {
+            let script = Script([[0x00, 0x14].as_slice(), &[byte; 20]].concat());
+            address::from_script(&script, Network::Regtest).unwrap()
+        };
+        Participant {
+            utxos: vec![UnspentTxOutputs {
+                txid: txid(id),
+                vout: 0,
+                address: None,
+                label: None,
+                script_pub_key: hex::encode(script(id).as_bytes()),
+                amount: Amount::from_sat(AMOUNT),
+                confirmations: 6,
+                spendable: true,
+                solvable: true,
+                desc: None,
+                parent_descs: Vec::new(),
+                safe: true,
+            }],
+            mix_address: address(id.wrapping_add(100)),
+            change_address: address(id.wrapping_add(200)),
+        }
+    }
+
+    fn config(participants: usize) -> RoundConfig {
+        RoundConfig::new(participants, FeeRate::from_sat_per_vb(2).unwrap())
+    }
+
+    /// Registers participants `ids`, returning their tokens.
+    fn register(round: &mut Round, node: &StubNode, ids: &[u8]) -> Vec<String> {
+        ids.iter()
+            .map(|&id| round.register(participant(id), node).unwrap())
+            .collect()
+    }
+
+    /// Signs the inputs of participant `id` in the joined PSBT of their
+    /// assignment, without finalizing them.
+    fn sign(round: &Round, token: &str, id: u8) -> String {
+        let assignment = round.assignment(token).unwrap();
+        let mut psbt = PartiallySignedTransaction::from_base64(&assignment.psbt).unwrap();
+        for input in &mut psbt.inputs {
+            if input
+                .witness_utxo
+                .as_ref()
+                .map(|txout| &txout.script_pubkey)
+                == Some(&script(id))
+            {
+                input.tap_key_sig = Some(vec![id; 64]);
+            }
+        }
+        psbt.to_base64()
+    }
+
+    fn invalid_request(result: Result<impl std::fmt::Debug, Error>) -> String {
+        match result {
+            Err(Error::InvalidRequest(message)) => message,
+            other => panic!("expected an invalid request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn registration_is_checked_with_the_node() {
+        let mut node = StubNode::with(&[1, 2]);
+        let mut round = Round::new(config(3));
+
+        let unknown = invalid_request(round.register(participant(3), &node));
+        assert_eq!(
+            unknown,
+            format!("input {} is spent or does not exist", outpoint(3))
+        );
+
+        node.utxos.get_mut(&outpoint(1)).unwrap().value = Amount::from_sat(AMOUNT - 1);
+        let amount = invalid_request(round.register(participant(1), &node));
+        assert_eq!(
+            amount,
+            format!(
+                "input {} holds {}, not {}",
+                outpoint(1),
+                Amount::from_sat(AMOUNT - 1),
+                Amount::from_sat(AMOUNT)
+            )
+        );
+
+        node.utxos.get_mut(&outpoint(1)).unwrap().value = Amount::from_sat(AMOUNT);
+        node.utxos.get_mut(&outpoint(1)).unwrap().script_pubkey = script(9);
+        let script_error = invalid_request(round.register(participant(1), &node));
+        assert_eq!(
+            script_error,
+            format!(
+                "input {} pays to {}, not {}",
+                outpoint(1),
+                script(9),
+                script(1)
+            )
+        );
+        assert_eq!(round.status().registered, 0);
+
+        node.utxos.get_mut(&outpoint(1)).unwrap().script_pubkey = script(1);
+        round.register(participant(1), &node).unwrap();
+        assert_eq!(round.status().registered, 1);
+        assert_eq!(round.stage(), Stage::Created);
+    }
+
+    #[test]
+    fn outputs_are_registered_once() {
+        let node = StubNode::with(&[1, 2]);
+        let mut round = Round::new(config(2));
+        round.register(participant(1), &node).unwrap();
+
+        let mut again = participant(1);
+        again.utxos[0].txid = again.utxos[0].txid.to_uppercase();
+        let duplicate = invalid_request(round.register(again, &node));
+        assert_eq!(
+            duplicate,
+            format!("input {} is already registered", outpoint(1))
+        );
+
+        round.register(participant(2), &node).unwrap();
+        assert_eq!(round.stage(), Stage::InputsRegistered);
+        assert!(round.register(participant(3), &node).is_err());
+    }
+
+    #[test]
+    fn assignments_share_the_joined_psbt() {
+        let node = StubNode::with(&[1, 2, 3]);
+        let mut round = Round::new(config(3));
+        let tokens = register(&mut round, &node, &[1, 2, 3]);
+        assert!(round.assignment(&tokens[0]).is_err());
+
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Joined);
+        let assignments: Vec<Assignment> = tokens
+            .iter()
+            .map(|token| round.assignment(token).unwrap())
+            .collect();
+        let joined = PartiallySignedTransaction::from_base64(&assignments[0].psbt).unwrap();
+        assert_eq!(joined.inputs.len(), 3);
+        assert_eq!(
+            round.status().denomination,
+            Some(assignments[0].denomination)
+        );
+        for assignment in &assignments {
+            assert_eq!(assignment.psbt, assignments[0].psbt);
+            assert_eq!(assignment.denomination, Amount::from_sat(1_000_000));
+            assert_eq!(assignment.attempt, 0);
+            let change = assignment.change.unwrap();
+            assert_eq!(
+                assignment.denomination.to_sat() + change.to_sat() + assignment.fee.to_sat(),
+                AMOUNT
+            );
+        }
+
+        assert_eq!(
+            invalid_request(round.assignment("nobody")),
+            "unknown participant"
+        );
+    }
+
+    #[test]
+    fn submitted_copies_must_match_the_joined_psbt() {
+        let node = StubNode::with(&[1, 2]);
+        let mut round = Round::new(config(2));
+        let tokens = register(&mut round, &node, &[1, 2]);
+        assert!(round.submit(&tokens[0], String::new()).is_err());
+        round.advance(&node).unwrap();
+
+        let signed = sign(&round, &tokens[0], 1);
+        let mut other = PartiallySignedTransaction::from_base64(&signed).unwrap();
+        other.unsigned_tx.lock_time = 1;
+        assert_eq!(
+            invalid_request(round.submit(&tokens[0], other.to_base64())),
+            "the PSBT is not a copy of the joined PSBT"
+        );
+
+        let mut finalized = PartiallySignedTransaction::from_base64(&signed).unwrap();
+        finalized.finalize();
+        assert!(
+            invalid_request(round.submit(&tokens[0], finalized.to_base64()))
+                .ends_with("is finalized, send partial signatures instead")
+        );
+
+        let mut conflicting = PartiallySignedTransaction::from_base64(&signed).unwrap();
+        for input in &mut conflicting.inputs {
+            if let Some(txout) = &mut input.witness_utxo {
+                txout.value = Amount::from_sat(1);
+            }
+        }
+        assert!(
+            invalid_request(round.submit(&tokens[0], conflicting.to_base64()))
+                .starts_with("the PSBT does not combine with the joined PSBT")
+        );
+        assert_eq!(round.status().signed, 0);
+
+        round.submit(&tokens[0], signed).unwrap();
+        assert_eq!(round.stage(), Stage::Signing);
+        assert_eq!(round.status().signed, 1);
+    }
+
+    #[test]
+    fn signed_copies_are_combined_and_broadcast() {
+        let mut node = StubNode::with(&[1, 2, 3]);
+        let mut round = Round::new(config(3));
+        let tokens = register(&mut round, &node, &[1, 2, 3]);
+        round.advance(&node).unwrap();
+
+        for (token, id) in tokens.iter().zip(1..) {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+            round.advance(&node).unwrap();
+        }
+        assert_eq!(round.stage(), Stage::Broadcast);
+        assert_eq!(round.status().signed, 3);
+
+        let broadcast = node.broadcast.borrow()[0].clone();
+        assert_eq!(round.status().txid, Some(broadcast.txid().to_string()));
+        let combined =
+            PartiallySignedTransaction::from_base64(round.psbts.combined.as_ref().unwrap())
+                .unwrap();
+        assert_eq!(broadcast.txid(), combined.unsigned_tx.txid());
+        for txin in &broadcast.input {
+            let id = (1..=3)
+                .find(|&id| outpoint(id) == txin.previous_output)
+                .unwrap();
+            assert_eq!(txin.witness, vec![vec![id; 64]]);
+        }
+
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Broadcast);
+        node.confirmations = Some(1);
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Confirmed);
+        assert!(round.is_over());
+    }
+
+    /// A directory removed when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            TempDir(std::env::temp_dir().join(format!(
+                "psbt-guide-{}-{}-{:08x}",
+                name,
+                std::process::id(),
+                rand::random::<u32>()
+            )))
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Everything a round keeps, to compare a resumed round with the one
+    /// that was saved.
+    fn snapshot(round: &Round) -> serde_json::Value {
+        serde_json::json!({
+            "state": round,
+            "contributions": round.psbts.contributions,
+            "joined": round.psbts.joined,
+            "signed": round.psbts.signed,
+            "combined": round.psbts.combined,
+            "transaction": round.psbts.transaction,
+        })
+    }
+
+    fn assert_resumes(round: &Round, dir: &TempDir, stage: Stage) {
+        assert_eq!(round.stage(), stage);
+        let resumed = Round::resume(&dir.0).unwrap();
+        assert_eq!(snapshot(&resumed), snapshot(round));
+    }
+
+    fn signed_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("signed-"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn rounds_resume_at_every_stage() {
+        let dir = TempDir::new("resume");
+        let node = StubNode::with(&[1, 2]);
+        let mut round = Round::create(config(2), &dir.0).unwrap();
+        assert_resumes(&round, &dir, Stage::Created);
+        assert!(Round::create(config(2), &dir.0).is_err());
+
+        let tokens = register(&mut round, &node, &[1]);
+        assert_resumes(&round, &dir, Stage::Created);
+        let tokens = [tokens, register(&mut round, &node, &[2])].concat();
+        assert_resumes(&round, &dir, Stage::InputsRegistered);
+
+        round.advance(&node).unwrap();
+        assert_resumes(&round, &dir, Stage::Joined);
+
+        let signed = sign(&round, &tokens[0], 1);
+        round.submit(&tokens[0], signed).unwrap();
+        assert_resumes(&round, &dir, Stage::Signing);
+        let signed = sign(&round, &tokens[1], 2);
+        round.submit(&tokens[1], signed).unwrap();
+        assert_resumes(&round, &dir, Stage::Signing);
+
+        // Take the remaining steps one at a time.
+        round.combine().unwrap();
+        round.save().unwrap();
+        assert_resumes(&round, &dir, Stage::Combined);
+        round.finalize().unwrap();
+        round.save().unwrap();
+        assert_resumes(&round, &dir, Stage::Finalized);
+        round.advance(&node).unwrap();
+        assert_resumes(&round, &dir, Stage::Broadcast);
+
+        let mut resumed = Round::resume(&dir.0).unwrap();
+        let confirmed = StubNode {
+            confirmations: Some(1),
+            ..StubNode::default()
+        };
+        resumed.advance(&confirmed).unwrap();
+        assert_resumes(&resumed, &dir, Stage::Confirmed);
+    }
+
+    #[test]
+    fn resumed_rounds_retry_the_failed_step() {
+        let dir = TempDir::new("retry");
+        let node = StubNode::with(&[1, 2]);
+        let mut round = Round::create(config(2), &dir.0).unwrap();
+        let tokens = register(&mut round, &node, &[1, 2]);
+        round.advance(&node).unwrap();
+        for (token, id) in tokens.iter().zip(1..) {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+        }
+
+        // The node cannot be reached when broadcasting.
+        struct Unreachable;
+        impl Node for Unreachable {
+            fn broadcast(&self, _hex: String) -> Result<String, Error> {
+                Err(Error::Coordinator("the node is unreachable".to_string()))
+            }
+            fn confirmations(&self, _txid: &str) -> Result<Option<u32>, Error> {
+                unreachable!()
+            }
+            fn unverified_inputs(&self, _psbt: String) -> Result<Vec<usize>, Error> {
+                unreachable!()
+            }
+            fn unspent_output(&self, _outpoint: OutPoint) -> Result<Option<TxOut>, Error> {
+                unreachable!()
+            }
+        }
+        round.advance(&Unreachable).unwrap();
+        assert!(round.is_over());
+        assert_resumes(&round, &dir, Stage::Finalized);
+
+        let mut resumed = Round::resume(&dir.0).unwrap();
+        assert!(resumed.status().error.is_some());
+        resumed.advance(&StubNode::default()).unwrap();
+        assert_eq!(resumed.stage(), Stage::Broadcast);
+        assert_eq!(resumed.status().error, None);
+        assert_resumes(&resumed, &dir, Stage::Broadcast);
+    }
+
+    #[test]
+    fn stale_signed_copies_are_removed() {
+        let dir = TempDir::new("stale");
+        let node = StubNode::with(&[1, 2, 3]);
+        let mut round = Round::create(config(3), &dir.0).unwrap();
+        let tokens = register(&mut round, &node, &[1, 2, 3]);
+        round.advance(&node).unwrap();
+
+        // The third participant sends their copy back unsigned.
+        for (token, id) in tokens.iter().zip(1..3) {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+        }
+        fs::write(dir.0.join("signed-7.psbt"), "stale").unwrap();
+        let unsigned = round.assignment(&tokens[2]).unwrap().psbt;
+        round.submit(&tokens[2], unsigned).unwrap();
+        assert_eq!(
+            signed_files(&dir.0),
+            ["signed-0.psbt", "signed-1.psbt", "signed-2.psbt"]
+        );
+
+        round.advance(&node).unwrap();
+        assert_eq!(round.status().excluded, 1);
+        assert_eq!(round.status().attempt, 1);
+        assert_resumes(&round, &dir, Stage::Joined);
+        assert!(signed_files(&dir.0).is_empty());
+        assert_eq!(
+            signed_files(&dir.0.join("attempt-0")),
+            ["signed-0.psbt", "signed-1.psbt", "signed-2.psbt"]
+        );
+
+        // The copies of the first attempt do not count for the second.
+        assert_eq!(round.status().signed, 0);
+        assert_eq!(Round::resume(&dir.0).unwrap().status().signed, 0);
+    }
+
+    /// A round with a signing timeout and a ban list in `dir`.
+    fn timed_config(participants: usize, dir: &TempDir) -> RoundConfig {
+        config(participants)
+            .signing_timeout(Duration::from_secs(60))
+            .ban_list(dir.0.join("banned.txt"))
+    }
+
+    /// Moves the signing deadline of `round` to the past.
+    fn expire(round: &mut Round) {
+        assert!(round.signing_deadline().is_some());
+        round.signing_deadline = Some(0);
+    }
+
+    #[test]
+    fn late_signers_are_excluded() {
+        let dir = TempDir::new("late");
+        let node = StubNode::with(&[1, 2, 3]);
+        let mut round = Round::create(timed_config(3, &dir), &dir.0).unwrap();
+        let tokens = register(&mut round, &node, &[1, 2, 3]);
+        round.advance(&node).unwrap();
+        for (token, id) in tokens.iter().zip(1..3) {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+        }
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Signing);
+
+        expire(&mut round);
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Joined);
+        assert!(!round.is_over());
+        let status = round.status();
+        assert_eq!(
+            (status.registered, status.excluded, status.attempt),
+            (2, 1, 1)
+        );
+        assert!(invalid_request(round.assignment(&tokens[2]))
+            .ends_with("excluded from the round: they did not sign before the deadline"));
+        let banned = BanList::open(dir.0.join("banned.txt")).unwrap();
+        assert!(banned.contains(&outpoint(3)));
+        assert!(!banned.contains(&outpoint(1)));
+
+        // The others sign the rebuilt PSBT, which no longer spends the
+        // excluded output.
+        let assignment = round.assignment(&tokens[0]).unwrap();
+        let joined = PartiallySignedTransaction::from_base64(&assignment.psbt).unwrap();
+        assert_eq!(assignment.attempt, 1);
+        assert_eq!(joined.unsigned_tx.input.len(), 2);
+        for (token, id) in tokens.iter().zip(1..3) {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+        }
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Broadcast);
+    }
+
+    #[test]
+    fn too_few_participants_left_stop_the_round() {
+        let dir = TempDir::new("too-few");
+        let node = StubNode::with(&[1, 2]);
+        let mut round = Round::create(timed_config(2, &dir), &dir.0).unwrap();
+        let tokens = register(&mut round, &node, &[1, 2]);
+        round.advance(&node).unwrap();
+        let signed = sign(&round, &tokens[0], 1);
+        round.submit(&tokens[0], signed).unwrap();
+
+        expire(&mut round);
+        round.advance(&node).unwrap();
+        assert!(round.is_over());
+        let status = round.status();
+        assert_eq!(status.stage, Stage::Signing);
+        assert_eq!((status.excluded, status.attempt), (0, 0));
+        assert_eq!(
+            status.error.as_deref(),
+            Some(
+                "invalid request: 1 of 2 participants did not sign before the deadline, \
+                 too few are left to rebuild the round"
+            )
+        );
+        assert!(!dir.0.join("banned.txt").exists());
+        assert!(!dir.0.join("attempt-0").exists());
+    }
+
+    #[test]
+    fn rejected_signatures_are_blamed_on_their_owner() {
+        let dir = TempDir::new("rejected");
+        let node = StubNode::with(&[1, 2, 3]);
+        let mut round = Round::create(timed_config(3, &dir), &dir.0).unwrap();
+        let tokens = register(&mut round, &node, &[1, 2, 3]);
+        round.advance(&node).unwrap();
+
+        // The node cannot verify the signature of the second participant.
+        node.rejected.borrow_mut().push(outpoint(2));
+        for (token, id) in tokens.iter().zip(1..) {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+        }
+        round.advance(&node).unwrap();
+        assert!(node.broadcast.borrow().is_empty());
+        assert_eq!(round.stage(), Stage::Joined);
+        assert_eq!(round.status().attempt, 1);
+        assert!(invalid_request(round.assignment(&tokens[1]))
+            .ends_with("excluded from the round: they signed inputs the node rejected"));
+        let banned = BanList::open(dir.0.join("banned.txt")).unwrap();
+        assert!(banned.contains(&outpoint(2)));
+        assert!(!banned.contains(&outpoint(1)) && !banned.contains(&outpoint(3)));
+
+        for (token, id) in [(&tokens[0], 1), (&tokens[2], 3)] {
+            let signed = sign(&round, token, id);
+            round.submit(token, signed).unwrap();
+        }
+        round.advance(&node).unwrap();
+        assert_eq!(round.stage(), Stage::Broadcast);
+        assert_eq!(node.broadcast.borrow()[0].input.len(), 2);
+    }
+}
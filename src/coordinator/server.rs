@@ -0,0 +1,123 @@
+//! The coordinator's HTTP interface.
+//!
+//! Every body is JSON, and errors are returned as `{"error": "..."}`:
+//!
+//! - `GET /round` returns the [`RoundStatus`].
+//! - `POST /register` takes a [`Participant`] and returns `{"token": "..."}`.
+//! - `GET /psbt/<token>` returns the participant's [`Assignment`].
+//! - `POST /psbt/<token>` takes `{"psbt": "..."}`, the signed copy, and
+//!   returns the [`RoundStatus`].
+
+use super::{Node, Round, RoundStatus, Stage};
+use crate::coinjoin::Participant;
+use crate::error::Error;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant, SystemTime};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// How long the server keeps answering after the round is over, so
+/// participants can learn the result.
+const LINGER: Duration = Duration::from_secs(10);
+
+/// How often a broadcast transaction is checked for a confirmation.
+const CONFIRMATION_POLL: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+pub(crate) struct Signed {
+    pub(crate) psbt: String,
+}
+
+/// Runs `round` on `addr`, e.g. `127.0.0.1:8339`, until its transaction is
+/// confirmed or a step fails, and no participant has asked for the result for
+/// a while.
+///
+/// Requests are handled one at a time, and the round is advanced after each
+/// registration and signed copy, at the signing deadline and while waiting
+/// for a confirmation. `node` checks the registered outputs, broadcasts the
+/// final transaction and reports its confirmations. A resumed round first
+/// retries the step it stopped at.
+pub fn serve(addr: &str, round: &mut Round, node: &impl Node) -> Result<RoundStatus, Error> {
+    let server = Server::http(addr)
+        .map_err(|e| Error::Config(format!("cannot listen on {}: {}", addr, e)))?;
+    round.advance(node)?;
+
+    let mut checked = Instant::now();
+    loop {
+        let request = if round.is_over() {
+            match server.recv_timeout(LINGER)? {
+                Some(request) => request,
+                None => return Ok(round.status()),
+            }
+        } else if let Some(wait) = next_check(round, checked) {
+            match server.recv_timeout(wait)? {
+                Some(request) => request,
+                None => {
+                    round.advance(node)?;
+                    checked = Instant::now();
+                    continue;
+                }
+            }
+        } else {
+            server.recv()?
+        };
+        handle(request, round, node);
+    }
+}
+
+/// How long until the round may advance without a request: at the signing
+/// deadline, or at the next confirmation check.
+fn next_check(round: &Round, checked: Instant) -> Option<Duration> {
+    if round.stage() == Stage::Broadcast {
+        return Some(CONFIRMATION_POLL.saturating_sub(checked.elapsed()));
+    }
+    round.signing_deadline().map(|deadline| {
+        deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    })
+}
+
+fn handle(mut request: Request, round: &mut Round, node: &impl Node) {
+    let url = request.url().to_string();
+    let segments: Vec<&str> = url.trim_matches('/').split('/').collect();
+
+    let result = match (request.method(), segments.as_slice()) {
+        (Method::Get, ["round"]) => Ok(json!(round.status())),
+        (Method::Post, ["register"]) => read_json::<Participant>(&mut request)
+            .and_then(|participant| round.register(participant, node))
+            .and_then(|token| round.advance(node).map(|()| json!({ "token": token }))),
+        (Method::Get, ["psbt", token]) => {
+            round.assignment(token).map(|assignment| json!(assignment))
+        }
+        (Method::Post, ["psbt", token]) => read_json::<Signed>(&mut request)
+            .and_then(|signed| round.submit(token, signed.psbt))
+            .and_then(|()| round.advance(node))
+            .map(|()| json!(round.status())),
+        _ => {
+            respond(request, 404, json!({ "error": "not found" }));
+            return;
+        }
+    };
+
+    match result {
+        Ok(body) => respond(request, 200, body),
+        Err(e) => respond(request, 400, json!({ "error": e.to_string() })),
+    }
+}
+
+fn read_json<T: DeserializeOwned>(request: &mut Request) -> Result<T, Error> {
+    serde_json::from_reader(request.as_reader())
+        .map_err(|e| Error::InvalidRequest(format!("invalid request body: {}", e)))
+}
+
+fn respond(request: Request, status: u16, body: Value) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+        .expect("static header is valid");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    // A participant that went away before the answer does not stop the round.
+    let _ = request.respond(response);
+}
@@ -0,0 +1,149 @@
+use std::fmt;
+
+/// Errors returned when decoding consensus-encoded data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The data ended before a value was complete.
+    UnexpectedEof,
+    /// A CompactSize was not encoded in its shortest form.
+    NonMinimalCompactSize,
+    /// A length exceeds what the remaining data could hold.
+    OversizedLength(u64),
+    /// Bytes were left over after decoding a value.
+    TrailingData(usize),
+    /// The data decoded, but to an invalid value.
+    InvalidData(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnexpectedEof => write!(f, "unexpected end of data"),
+            Error::NonMinimalCompactSize => write!(f, "non-minimal CompactSize"),
+            Error::OversizedLength(len) => write!(f, "length {} exceeds the remaining data", len),
+            Error::TrailingData(len) => write!(f, "{} unexpected trailing bytes", len),
+            Error::InvalidData(msg) => write!(f, "invalid data: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Reads consensus-encoded values from a byte slice.
+#[derive(Debug, Clone)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Fails unless every byte has been read.
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        match self.remaining() {
+            0 => Ok(()),
+            len => Err(Error::TrailingData(len)),
+        }
+    }
+
+    pub(crate) fn peek_u8(&self) -> Result<u8, Error> {
+        self.data.get(self.pos).copied().ok_or(Error::UnexpectedEof)
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            return Err(Error::UnexpectedEof);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub(crate) fn read_u16_le(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32_le(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_i32_le(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64_le(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_compact_size(&mut self) -> Result<u64, Error> {
+        let (value, min) = match self.read_u8()? {
+            0xfd => (self.read_u16_le()? as u64, 0xfd),
+            0xfe => (self.read_u32_le()? as u64, 0x1_0000),
+            0xff => (self.read_u64_le()?, 0x1_0000_0000),
+            n => return Ok(n as u64),
+        };
+        if value < min {
+            return Err(Error::NonMinimalCompactSize);
+        }
+        Ok(value)
+    }
+
+    /// Reads a CompactSize used as a length, checking it against the data left.
+    pub(crate) fn read_length(&mut self) -> Result<usize, Error> {
+        let len = self.read_compact_size()?;
+        if len > self.remaining() as u64 {
+            return Err(Error::OversizedLength(len));
+        }
+        Ok(len as usize)
+    }
+
+    /// Reads a CompactSize-prefixed byte string.
+    pub(crate) fn read_var_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_length()?;
+        self.read_bytes(len)
+    }
+}
+
+pub(crate) fn write_compact_size(out: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xfe);
+            out.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            out.push(0xff);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+pub(crate) fn write_var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_compact_size(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
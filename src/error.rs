@@ -0,0 +1,198 @@
+use std::fmt;
+
+/// Bitcoin Core RPC error codes, as defined in `src/rpc/protocol.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcCode {
+    /// -1: `std::exception` thrown in command handling.
+    MiscError,
+    /// -3: unexpected type was passed as parameter.
+    TypeError,
+    /// -4: unspecified problem with the wallet, including insufficient funds
+    /// when funding a transaction.
+    WalletError,
+    /// -5: invalid address or key.
+    InvalidAddressOrKey,
+    /// -6: not enough funds in wallet or account.
+    WalletInsufficientFunds,
+    /// -8: invalid, missing or duplicate parameter.
+    InvalidParameter,
+    /// -13: enter the wallet passphrase with walletpassphrase first.
+    WalletUnlockNeeded,
+    /// -18: invalid wallet specified or the wallet is not loaded.
+    WalletNotFound,
+    /// -19: no wallet specified while multiple wallets are loaded.
+    WalletNotSpecified,
+    /// -22: error parsing or validating structure in raw format.
+    DeserializationError,
+    /// -25: general error during transaction or block submission, e.g. missing inputs.
+    VerifyError,
+    /// -26: transaction or block was rejected by network rules.
+    VerifyRejected,
+    /// -27: transaction already in chain.
+    VerifyAlreadyInChain,
+    /// -28: client still warming up.
+    InWarmup,
+    /// -35: this same wallet is already loaded.
+    WalletAlreadyLoaded,
+    /// Any code not listed above.
+    Other(i64),
+}
+
+impl From<i64> for RpcCode {
+    fn from(code: i64) -> Self {
+        match code {
+            -1 => RpcCode::MiscError,
+            -3 => RpcCode::TypeError,
+            -4 => RpcCode::WalletError,
+            -5 => RpcCode::InvalidAddressOrKey,
+            -6 => RpcCode::WalletInsufficientFunds,
+            -8 => RpcCode::InvalidParameter,
+            -13 => RpcCode::WalletUnlockNeeded,
+            -18 => RpcCode::WalletNotFound,
+            -19 => RpcCode::WalletNotSpecified,
+            -22 => RpcCode::DeserializationError,
+            -25 => RpcCode::VerifyError,
+            -26 => RpcCode::VerifyRejected,
+            -27 => RpcCode::VerifyAlreadyInChain,
+            -28 => RpcCode::InWarmup,
+            -35 => RpcCode::WalletAlreadyLoaded,
+            other => RpcCode::Other(other),
+        }
+    }
+}
+
+impl RpcCode {
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcCode::MiscError => -1,
+            RpcCode::TypeError => -3,
+            RpcCode::WalletError => -4,
+            RpcCode::InvalidAddressOrKey => -5,
+            RpcCode::WalletInsufficientFunds => -6,
+            RpcCode::InvalidParameter => -8,
+            RpcCode::WalletUnlockNeeded => -13,
+            RpcCode::WalletNotFound => -18,
+            RpcCode::WalletNotSpecified => -19,
+            RpcCode::DeserializationError => -22,
+            RpcCode::VerifyError => -25,
+            RpcCode::VerifyRejected => -26,
+            RpcCode::VerifyAlreadyInChain => -27,
+            RpcCode::InWarmup => -28,
+            RpcCode::WalletAlreadyLoaded => -35,
+            RpcCode::Other(code) => *code,
+        }
+    }
+}
+
+/// Errors returned by this crate.
+#[derive(Debug)]
+pub enum Error {
+    /// The HTTP request could not be sent or its response could not be read.
+    Transport(reqwest::Error),
+    /// The node rejected the credentials (HTTP 401).
+    Unauthorized,
+    /// The node refused the request, e.g. because of `rpcwhitelist` (HTTP 403).
+    Forbidden,
+    /// Any other non-success HTTP status without a JSON-RPC error body.
+    Http { status: u16, body: String },
+    /// The node returned a JSON-RPC error object.
+    Rpc { code: RpcCode, message: String },
+    /// The `result` did not match the expected type.
+    Deserialize(serde_json::Error),
+    /// The client is missing or has invalid configuration.
+    Config(String),
+    /// The request was rejected before it was sent to the node.
+    InvalidRequest(String),
+    /// A PSBT could not be parsed.
+    Psbt(crate::psbt::Error),
+    /// Reading or writing a PSBT or transaction file failed.
+    Io(std::io::Error),
+    /// The PSBT does not match what the wallet agreed to sign.
+    UnsafeToSign(Vec<crate::verify::Violation>),
+    /// No selection of the wallet's spendable outputs pays for the
+    /// recipients and the fee.
+    InsufficientFunds {
+        needed: crate::amount::Amount,
+        available: crate::amount::Amount,
+    },
+    /// The coinjoin coordinator rejected a request or the round failed.
+    Coordinator(String),
+}
+
+impl Error {
+    /// Returns the RPC error code if this is a JSON-RPC error.
+    pub fn rpc_code(&self) -> Option<RpcCode> {
+        match self {
+            Error::Rpc { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Unauthorized => write!(f, "RPC credentials were rejected (HTTP 401)"),
+            Error::Forbidden => write!(f, "RPC request was forbidden (HTTP 403)"),
+            Error::Http { status, body } => {
+                write!(f, "unexpected HTTP status {}: {}", status, body)
+            }
+            Error::Rpc { code, message } => write!(f, "RPC error {}: {}", code.code(), message),
+            Error::Deserialize(e) => write!(f, "unexpected RPC response: {}", e),
+            Error::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            Error::InvalidRequest(msg) => write!(f, "invalid request: {}", msg),
+            Error::Psbt(e) => write!(f, "invalid PSBT: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::UnsafeToSign(violations) => {
+                write!(f, "refusing to sign:")?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+            Error::InsufficientFunds { needed, available } => write!(
+                f,
+                "insufficient funds: {} needed after fees, {} available",
+                needed, available
+            ),
+            Error::Coordinator(msg) => write!(f, "coordinator error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            Error::Deserialize(e) => Some(e),
+            Error::Psbt(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Deserialize(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<crate::psbt::Error> for Error {
+    fn from(e: crate::psbt::Error) -> Self {
+        Error::Psbt(e)
+    }
+}
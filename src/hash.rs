@@ -0,0 +1,16 @@
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// SHA256 applied twice, as used for transaction ids.
+pub(crate) fn sha256d(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+/// RIPEMD160 of SHA256, as used for P2PKH and P2SH.
+pub(crate) fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(sha256(data)).into()
+}
@@ -0,0 +1,242 @@
+use crate::address;
+use crate::amount::{Amount, FeeRate};
+use crate::network::Network;
+use crate::psbt::{input_sighashes, Missing, PartiallySignedTransaction};
+use crate::transaction::{OutPoint, Script, ScriptType};
+use serde::Serialize;
+use std::fmt;
+
+/// A human-readable summary of a PSBT, built by the local parser and
+/// optionally completed with what the node knows.
+#[derive(Debug, Clone, Serialize)]
+pub struct PsbtSummary {
+    pub version: u32,
+    pub tx_version: i32,
+    pub lock_time: u32,
+    /// Whether any input signals BIP125 replaceability.
+    pub replaceable: bool,
+    pub inputs: Vec<InputSummary>,
+    pub outputs: Vec<OutputSummary>,
+    /// The fee, known once every input has its UTXO.
+    pub fee: Option<Amount>,
+    /// The exact size once every input is finalized, otherwise the node's
+    /// estimate if it was asked.
+    pub vsize: Option<u64>,
+    pub fee_rate: Option<FeeRate>,
+    /// The role that should act next, as reported by `analyzepsbt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputSummary {
+    pub prevout: OutPoint,
+    pub sequence: u32,
+    pub amount: Option<Amount>,
+    pub script_type: Option<ScriptType>,
+    pub address: Option<String>,
+    pub signatures: usize,
+    /// The sighash types of the signatures present, e.g. `ALL|ANYONECANPAY`.
+    pub sighash_types: Vec<String>,
+    pub finalized: bool,
+    /// What the input still needs before it can be finalized.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputSummary {
+    pub amount: Amount,
+    pub script_pubkey: Script,
+    pub script_type: ScriptType,
+    pub address: Option<String>,
+    /// Whether the PSBT carries key origins for the output, which wallets add
+    /// to their own change outputs.
+    pub has_key_origin: bool,
+    /// Whether the wallet owns the address, if a wallet was asked.
+    pub is_mine: Option<bool>,
+    /// Whether the wallet handed the address out as change, if a wallet was
+    /// asked.
+    pub is_change: Option<bool>,
+}
+
+impl PsbtSummary {
+    /// Summarizes the PSBT without a node, encoding addresses for `network`.
+    pub fn new(psbt: &PartiallySignedTransaction, network: Network) -> Self {
+        let tx = &psbt.unsigned_tx;
+
+        let mut finalized = psbt.clone();
+        let incomplete = finalized.finalize();
+
+        let inputs = tx
+            .input
+            .iter()
+            .zip(&psbt.inputs)
+            .enumerate()
+            .map(|(index, (txin, input))| {
+                let spent = input.spent_output(&txin.previous_output);
+                let is_finalized = input.is_finalized();
+                let sighash_types = if is_finalized {
+                    Vec::new()
+                } else {
+                    input_sighashes(input).map(sighash_name).collect()
+                };
+                InputSummary {
+                    prevout: txin.previous_output,
+                    sequence: txin.sequence,
+                    amount: spent.map(|txout| txout.value),
+                    script_type: spent.map(|txout| txout.script_pubkey.script_type()),
+                    address: spent
+                        .and_then(|txout| address::from_script(&txout.script_pubkey, network)),
+                    signatures: sighash_types.len(),
+                    sighash_types,
+                    finalized: is_finalized,
+                    missing: incomplete
+                        .iter()
+                        .filter(|incomplete| incomplete.index == index)
+                        .flat_map(|incomplete| &incomplete.missing)
+                        .map(Missing::to_string)
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let outputs = tx
+            .output
+            .iter()
+            .zip(&psbt.outputs)
+            .map(|(txout, output)| OutputSummary {
+                amount: txout.value,
+                script_pubkey: txout.script_pubkey.clone(),
+                script_type: txout.script_pubkey.script_type(),
+                address: address::from_script(&txout.script_pubkey, network),
+                has_key_origin: !output.bip32_derivation.is_empty()
+                    || !output.tap_key_origins.is_empty(),
+                is_mine: None,
+                is_change: None,
+            })
+            .collect();
+
+        let fee = psbt.fee().ok();
+        let vsize = psbt.extract_tx().ok().map(|tx| tx.vsize());
+
+        PsbtSummary {
+            version: psbt.version,
+            tx_version: tx.version,
+            lock_time: tx.lock_time,
+            replaceable: tx.input.iter().any(|txin| txin.sequence < 0xffff_fffe),
+            inputs,
+            outputs,
+            fee,
+            vsize,
+            fee_rate: fee
+                .zip(vsize)
+                .and_then(|(fee, vsize)| FeeRate::from_fee(fee, vsize)),
+            next: None,
+        }
+    }
+}
+
+fn sighash_name(sighash: u8) -> String {
+    let base = match sighash & 0x1f {
+        0x00 => "DEFAULT",
+        0x01 => "ALL",
+        0x02 => "NONE",
+        0x03 => "SINGLE",
+        _ => return format!("{:#04x}", sighash),
+    };
+    if sighash & 0x80 != 0 {
+        format!("{}|ANYONECANPAY", base)
+    } else {
+        base.to_string()
+    }
+}
+
+fn or_unknown<T: fmt::Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "unknown".to_string(), T::to_string)
+}
+
+impl fmt::Display for PsbtSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "PSBT version {}, transaction version {}, locktime {}, {}",
+            self.version,
+            self.tx_version,
+            self.lock_time,
+            if self.replaceable {
+                "replaceable"
+            } else {
+                "not replaceable"
+            }
+        )?;
+        match self.fee {
+            Some(fee) => write!(f, "fee: {} ({})", fee, fee.display_sat())?,
+            None => write!(f, "fee: unknown")?,
+        }
+        if let (Some(fee_rate), Some(vsize)) = (self.fee_rate, self.vsize) {
+            write!(f, ", {} over {} vB", fee_rate, vsize)?;
+        }
+        writeln!(f)?;
+        if let Some(next) = &self.next {
+            writeln!(f, "next: {}", next)?;
+        }
+
+        writeln!(f, "\ninputs:")?;
+        for (index, input) in self.inputs.iter().enumerate() {
+            writeln!(
+                f,
+                "  {}: {} {} {} {}",
+                index,
+                input.prevout,
+                or_unknown(&input.amount),
+                or_unknown(&input.script_type),
+                input.address.as_deref().unwrap_or("")
+            )?;
+            if input.finalized {
+                writeln!(f, "     finalized")?;
+            } else if input.signatures > 0 {
+                writeln!(
+                    f,
+                    "     {} signature(s): {}",
+                    input.signatures,
+                    input.sighash_types.join(", ")
+                )?;
+            }
+            for missing in &input.missing {
+                writeln!(f, "     {}", missing)?;
+            }
+        }
+
+        writeln!(f, "\noutputs:")?;
+        for (index, output) in self.outputs.iter().enumerate() {
+            write!(
+                f,
+                "  {}: {} {} {}",
+                index,
+                output.amount,
+                output.script_type,
+                output
+                    .address
+                    .clone()
+                    .unwrap_or_else(|| output.script_pubkey.to_string())
+            )?;
+            let mut tags = Vec::new();
+            if output.is_mine == Some(true) {
+                tags.push("mine");
+            }
+            if output.is_change == Some(true) {
+                tags.push("change");
+            }
+            if output.is_mine.is_none() && output.has_key_origin {
+                tags.push("has key origin");
+            }
+            if !tags.is_empty() {
+                write!(f, " ({})", tags.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
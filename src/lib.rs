@@ -0,0 +1,61 @@
+//! Build, sign and broadcast PSBTs with Bitcoin Core.
+//!
+//! [`RpcClient`] talks to the node, and the workflow functions cover each
+//! stage of a manual coinjoin: [`create_psbt`] for every participant,
+//! [`join_psbt`] to merge their contributions, [`wallet_process_psbt`] for each
+//! participant to sign, then [`combine_psbt`], [`finalize_psbt`] and
+//! [`broadcast_transaction`].
+//!
+//! ```no_run
+//! use psbt_guide::{list_unspent, RpcClient};
+//!
+//! let client = RpcClient::from_env()?.with_wallet("alice");
+//! for utxo in list_unspent(&client)? {
+//!     println!("{}:{} {}", utxo.txid, utxo.vout, utxo.amount);
+//! }
+//! # Ok::<(), psbt_guide::Error>(())
+//! ```
+
+pub mod address;
+pub mod amount;
+pub mod coin_selection;
+pub mod coinjoin;
+pub mod coordinator;
+pub mod encode;
+pub mod error;
+mod hash;
+pub mod inspect;
+pub mod network;
+pub mod psbt;
+pub mod rpc;
+pub mod transaction;
+pub mod types;
+pub mod verify;
+pub mod wallet;
+pub mod workflow;
+
+pub use amount::{Amount, FeeRate};
+pub use coin_selection::{Algorithm, CoinSelectionParams, Selection};
+pub use coinjoin::{
+    allocate_fees, Coinjoin, CoinjoinBuilder, FeeAllocation, FeeSplit, Participant,
+};
+pub use coordinator::{BanList, CoordinatorClient, Node, Round, RoundConfig, Stage};
+pub use error::{Error, RpcCode};
+pub use inspect::PsbtSummary;
+pub use network::Network;
+pub use psbt::{JoinOrder, PartiallySignedTransaction};
+pub use rpc::{Auth, RpcClient, RpcConfig};
+pub use transaction::{OutPoint, Script, ScriptType, Transaction, TxIn, TxOut, Txid};
+pub use types::{
+    AnalyzedPsbt, ChangeType, EstimateMode, ExtractedTransaction, FinalizedPsbtResponse,
+    FundingOptions, Input, ListUnspentOptions, Psbt, Recipient, ScriptPubKey, TxOutResponse,
+    UnspentTxOutputs, WalletProcessPsbt,
+};
+pub use verify::SigningPolicy;
+pub use workflow::{
+    analyze_psbt, broadcast_transaction, coinjoin_participant, combine_psbt, combine_psbt_offline,
+    create_psbt, create_psbt_with_options, decode_psbt, extract_transaction, finalize_psbt,
+    finalize_psbt_offline, get_tx_out, inspect_psbt, join_psbt, join_psbt_offline, list_unspent,
+    list_unspent_with_options, select_inputs, transaction_confirmations, wallet_process_psbt,
+    wallet_process_psbt_verified,
+};
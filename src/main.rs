@@ -1,250 +1,284 @@
-use base64;
+mod cli;
+
+use clap::Parser;
+use cli::{read_arg, read_args, write_output, Cli, Command};
 use dotenvy::dotenv;
-use reqwest::blocking::Client as ReqClient;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{json, Value};
+use psbt_guide::coordinator::client::{mix, FeeCap};
+use psbt_guide::coordinator::server::serve;
+use psbt_guide::{
+    allocate_fees, broadcast_transaction, coinjoin_participant, combine_psbt, combine_psbt_offline,
+    create_psbt_with_options, decode_psbt, extract_transaction, finalize_psbt,
+    finalize_psbt_offline, inspect_psbt, join_psbt, join_psbt_offline, list_unspent_with_options,
+    select_inputs, wallet_process_psbt, wallet_process_psbt_verified, CoinSelectionParams,
+    CoinjoinBuilder, CoordinatorClient, Error, PartiallySignedTransaction, PsbtSummary, Round,
+    RoundConfig, RpcClient,
+};
 use std::env;
-use std::error::Error;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct UnspentTxOutputs {
-    txid: String,
-    vout: u32,
-    address: String,
-    label: String,
-    scriptPubKey: String,
-    amount: f32,
-    confirmations: u32,
-    spendable: bool,
-    solvable: bool,
-    desc: String,
-    parent_descs: Vec<String>,
-    safe: bool,
-}
-
-#[derive(Debug, Clone)]
-struct Input {
-    txid: String,
-    vout: u32,
-}
-
-#[derive(Debug, Deserialize)]
-struct Psbt {
-    psbt: String,
-    fee: f32,
-    changepos: i32,
-}
-
-#[derive(Debug, Deserialize)]
-struct WalletProcessPsbt {
-    psbt: String,
-    complete: bool,
-}
-
-#[derive(Debug, Deserialize)]
-struct FinalizedPsbtResponse {
-    hex: String,
-    complete: bool,
-}
-
-/// This function makes an RPC call to Bitcoin Core
-fn send_rpc_request(method: &str, params: &Value, wallet_request: bool) -> Result<Value, Box<dyn Error>> {
-    let rpc_url;
-    let client = ReqClient::new();
-
-    match wallet_request {
-        true => {
-            let rpc_link = env::var("RPC_HOST").expect("RPC_HOST not found in environment");
-            rpc_url = format!("{rpc_link}/wallet/codeplanet");
-        },
-        false => {
-            rpc_url = env::var("RPC_HOST").expect("RPC_HOST not found in environment");
-        },
-    }
-
-    let rpc_user = env::var("RPC_USER").expect("RPC_USER not found in environment");
-    let rpc_password = env::var("RPC_PASSWORD").expect("RPC_PASSWORD not found in environment");
-
-    let credentials = format!("{}:{}", rpc_user, rpc_password);
-    let encoded_credentials = format!("Basic {}", base64::encode(credentials));
-    let auth = reqwest::header::HeaderValue::from_str(&encoded_credentials.as_str()).unwrap();
-
-    let request_body = json!({
-        "jsonrpc": "1.0",
-        "id": "curltest",
-        "method": method,
-        "params": params,
-    });
-
-
-    let response = client
-        .post(rpc_url)
-        .header(reqwest::header::CONTENT_TYPE, "text/plain")
-        .header(reqwest::header::AUTHORIZATION, auth)
-        .body(request_body.to_string())
-        .send()?;
-
-    let json_response = response.json()?;
-
-    Ok(json_response)
-}
-
-/// This function is used to deserialize the result value response
-/// from Bitcoin Core.
-fn deserialize_response<T: DeserializeOwned>(response: &Value) -> Option<T> {
-    let json_response = &response["result"];
-    let deserialized: Option<T> = serde_json::from_value(json_response.to_owned()).ok();
-    deserialized
+use std::process;
+use std::time::Duration;
+
+/// Connects to the node, only done by commands that need it.
+fn connect(wallet: Option<&str>) -> Result<RpcClient, Error> {
+    let client = RpcClient::from_env()?;
+    Ok(match wallet {
+        Some(wallet) => client.with_wallet(wallet),
+        None => client,
+    })
 }
 
-/// Creates a PSBT and returns the newly created PSBT.
-fn create_psbt(input: Input, output: Vec<Value>) -> Result<Psbt, Box<dyn Error>> {
-    let utxos = vec![json!({
-        "txid": input.txid,
-        "vout": input.vout,
-    })];
-
-    let body = json!([utxos, output]);
-
-    let response = send_rpc_request("walletcreatefundedpsbt", &body, true);
+fn run(cli: Cli) -> Result<(), Error> {
+    let client = || connect(cli.wallet.as_deref());
 
-
-    match response {
-        Ok(psbt) => {
-            let response_json: Psbt = deserialize_response(&psbt).unwrap();
-            Ok(response_json)
-        },
-        Err(e) => {
-            Err(e)
+    let output = match cli.command {
+        Command::ListUtxos { query } => {
+            let utxos = list_unspent_with_options(&client()?, &query.options())?;
+            serde_json::to_string_pretty(&utxos)?
         }
-    }
-}
-
-/// Joins multiple PSBTs into a single large PSBT.
-fn join_psbt() -> Result<String, Box<dyn Error>> {
-    let ifeanyi_wallet_psbt = env::var("IFEANYI_WALLET_PSBT").expect("User PSBT not found in environment");
-    let codeplanet_wallet_psbt = env::var("CODEPLANET_WALLET_PSBT").expect("User PSBT not found in environment");
-
-    let psbts = json!([ifeanyi_wallet_psbt, codeplanet_wallet_psbt]);
-
-    let body = json!([psbts]);
-
-    let response = send_rpc_request("joinpsbts", &body, false);
+        Command::Create {
+            mut inputs,
+            mut recipients,
+            data,
+            select,
+            min_conf,
+            funding,
+        } => {
+            recipients.extend(data);
+            let client = client()?;
+            let mut options = funding.options();
+
+            if let (Some(select), Some(fee_rate)) = (select, options.fee_rate) {
+                let params = CoinSelectionParams::for_recipients(&recipients, fee_rate)?
+                    .min_confirmations(min_conf);
+                let selection = select_inputs(&client, &params, select.into())?;
+                eprintln!(
+                    "selected {} input(s) worth {} with {}, waste: {} sat",
+                    selection.utxos.len(),
+                    selection.total(),
+                    selection.algorithm,
+                    selection.waste
+                );
+                inputs = selection.inputs();
+                options = options.add_inputs(false);
+            }
 
-    match response {
-        Ok(psbt) => {
-            let result: String = deserialize_response(&psbt).unwrap();
-            Ok(result)
-        },
-        Err(e) => {
-            Err(e)
+            let psbt = create_psbt_with_options(&client, &inputs, &recipients, &options)?;
+            eprintln!(
+                "fee: {} ({}), change position: {}",
+                psbt.fee,
+                psbt.fee.display_sat(),
+                psbt.changepos
+            );
+            psbt.psbt
         }
-    }
-}
-
-/// This function is used to sign the Joined PSBT.
-fn wallet_process_psbt(psbt: String) -> Result<WalletProcessPsbt, Box<dyn Error>> {
-    let body = json!([psbt]);
-
-    let response = send_rpc_request("walletprocesspsbt", &body, true);
-
-    match response {
-        Ok(psbt) => {
-            let result: WalletProcessPsbt = deserialize_response(&psbt).unwrap();
-            Ok(result)
-        },
-        Err(e) => {
-            Err(e)
+        Command::Join {
+            psbts,
+            offline,
+            order,
+        } => {
+            let psbts = read_args(&psbts)?;
+            if offline {
+                join_psbt_offline(&psbts, order.into())?
+            } else {
+                join_psbt(&client()?, psbts)?
+            }
         }
-    }
-}
-
-/// Combines all signatures and input information into the same PSBT
-fn combine_psbt(psbt: String) -> Result<String, Box<dyn Error>> {
-    let body = json!([psbt]);
-
-    let request_body = json!([body]);
-
-    let response = send_rpc_request("combinepsbt", &request_body, false);
-
-    match response {
-        Ok(psbt) => {
-            let result: String = deserialize_response(&psbt).unwrap();
-            Ok(result)
-        },
-        Err(e) => {
-            Err(e)
+        Command::Process { psbt, verify } => {
+            let psbt = read_arg(&psbt)?;
+            let processed = match verify.policy() {
+                Some(policy) => wallet_process_psbt_verified(&client()?, psbt, &policy, true)?,
+                None => wallet_process_psbt(&client()?, psbt)?,
+            };
+            eprintln!("complete: {}", processed.complete);
+            processed.psbt
         }
-    }
-}
-
-/// Finalizes the PSBT and creates a raw network transaction ready to be broadcasted.
-fn finalize_psbt(psbt: String) -> Result<FinalizedPsbtResponse, Box<dyn Error>> {
-    let body = json!([psbt]);
-
-    let response = send_rpc_request("finalizepsbt", &body, false);
-
-    match response {
-        Ok(psbt) => {
-            let result: FinalizedPsbtResponse = deserialize_response(&psbt).unwrap();
-            Ok(result)
-        },
-        Err(e) => {
-            Err(e)
+        Command::Combine { psbts, offline } => {
+            let psbts = read_args(&psbts)?;
+            if offline {
+                combine_psbt_offline(&psbts)?
+            } else {
+                combine_psbt(&client()?, psbts)?
+            }
         }
-    }
-}
-
-/// Broadcasts the transaction to the network.
-fn broadcast_transaction(hex: String) -> Result<String, Box<dyn Error>> {
-    let body = json!([hex]);
-
-    let response = send_rpc_request("sendrawtransaction", &body, false);
+        Command::Finalize { psbt, offline } => {
+            let psbt = read_arg(&psbt)?;
+            let finalized = if offline {
+                finalize_psbt_offline(psbt)?
+            } else {
+                finalize_psbt(&client()?, psbt)?
+            };
+            eprintln!("complete: {}", finalized.complete);
+            for incomplete in &finalized.incomplete {
+                eprintln!("{}", incomplete);
+            }
+            match finalized.psbt {
+                Some(psbt) if !finalized.complete => psbt,
+                _ => finalized.hex,
+            }
+        }
+        Command::Extract { psbt } => {
+            let extracted = extract_transaction(read_arg(&psbt)?)?;
+            eprintln!("txid: {}", extracted.txid);
+            eprintln!("wtxid: {}", extracted.wtxid);
+            eprintln!("size: {} vB ({} WU)", extracted.vsize, extracted.weight);
+            eprintln!(
+                "fee: {} ({}), fee rate: {}",
+                extracted.fee,
+                extracted.fee.display_sat(),
+                extracted.fee_rate
+            );
+            extracted.hex
+        }
+        Command::Broadcast { hex } => broadcast_transaction(&client()?, read_arg(&hex)?)?,
+        Command::Decode { psbt } => {
+            let decoded = decode_psbt(&client()?, read_arg(&psbt)?)?;
+            serde_json::to_string_pretty(&decoded)?
+        }
+        Command::Participant {
+            query,
+            address_type,
+        } => {
+            let participant =
+                coinjoin_participant(&client()?, &query.options(), address_type.map(Into::into))?;
+            serde_json::to_string_pretty(&participant)?
+        }
+        Command::Coinjoin {
+            participants,
+            fee_rate,
+            denomination,
+            fee_split,
+        } => {
+            let mut builder = CoinjoinBuilder::new(fee_rate);
+            if let Some(denomination) = denomination {
+                builder = builder.denomination(denomination);
+            }
+            for participant in read_args(&participants)? {
+                builder = builder.participant(serde_json::from_str(&participant)?);
+            }
 
-    match response {
-        Ok(txid) => {
-            let result: String = deserialize_response(&txid).unwrap();
-            Ok(result)
-        },
-        Err(e) => {
-            Err(e)
+            let mut coinjoin = builder.build()?;
+            let allocation =
+                allocate_fees(&mut coinjoin.contributions, fee_rate, fee_split.into())?;
+            eprintln!("denomination: {}", coinjoin.denomination);
+            eprint!("{}", allocation);
+            coinjoin
+                .contributions
+                .iter()
+                .map(|contribution| contribution.psbt.to_base64())
+                .collect::<Vec<_>>()
+                .join("\n")
         }
-    }
+        Command::Coordinate {
+            participants,
+            fee_rate,
+            denomination,
+            fee_split,
+            order,
+            listen,
+            signing_timeout,
+            ban_list,
+            state,
+            resume,
+        } => {
+            let mut round = match (resume, participants, fee_rate) {
+                (Some(dir), _, _) => {
+                    let round = Round::resume(&dir)?;
+                    eprintln!("resuming the round at the {} stage", round.stage());
+                    round
+                }
+                (None, Some(participants), Some(fee_rate)) => {
+                    let mut config = RoundConfig::new(participants as usize, fee_rate)
+                        .fee_split(fee_split.into())
+                        .order(order.into());
+                    if let Some(denomination) = denomination {
+                        config = config.denomination(denomination);
+                    }
+                    if let Some(signing_timeout) = signing_timeout {
+                        config = config.signing_timeout(Duration::from_secs(signing_timeout));
+                    }
+                    if let Some(ban_list) = ban_list {
+                        config = config.ban_list(ban_list);
+                    }
+                    let round = match state {
+                        Some(dir) => Round::create(config, dir)?,
+                        None => Round::new(config),
+                    };
+                    eprintln!("waiting for {} participants on {}", participants, listen);
+                    round
+                }
+                _ => unreachable!("clap requires the round settings without --resume"),
+            };
+
+            let status = serve(&listen, &mut round, &client()?)?;
+            match (status.txid, status.error) {
+                (Some(txid), None) => txid,
+                (_, error) => {
+                    return Err(Error::Coordinator(format!(
+                        "round stopped in the {} stage: {}",
+                        status.stage,
+                        error.unwrap_or_default()
+                    )))
+                }
+            }
+        }
+        Command::Mix {
+            coordinator,
+            query,
+            address_type,
+            max_fee,
+            max_fee_rate,
+            interval,
+        } => {
+            let max_fee = match (max_fee, max_fee_rate) {
+                (Some(max_fee), None) => FeeCap::Absolute(max_fee),
+                (None, Some(max_fee_rate)) => FeeCap::Rate(max_fee_rate),
+                _ => unreachable!("clap requires one of --max-fee and --max-fee-rate"),
+            };
+            mix(
+                &client()?,
+                &CoordinatorClient::new(coordinator)?,
+                &query.options(),
+                address_type.map(Into::into),
+                max_fee,
+                Duration::from_secs(interval),
+            )?
+        }
+        Command::Inspect {
+            psbt,
+            node,
+            json,
+            network,
+        } => {
+            let psbt = read_arg(&psbt)?;
+            let summary = if node {
+                inspect_psbt(&client()?, psbt)?
+            } else {
+                let network = match network {
+                    Some(network) => network,
+                    None => env::var("RPC_NETWORK")
+                        .ok()
+                        .filter(|network| !network.is_empty())
+                        .map(|network| network.parse())
+                        .transpose()?
+                        .unwrap_or_default(),
+                };
+                PsbtSummary::new(&PartiallySignedTransaction::from_base64(&psbt)?, network)
+            };
+            if json {
+                serde_json::to_string_pretty(&summary)?
+            } else {
+                summary.to_string().trim_end().to_string()
+            }
+        }
+    };
+
+    write_output(cli.output.as_deref(), &output)
 }
 
 fn main() {
     dotenv().ok();
 
-    let response = send_rpc_request("listunspent", &json!([]), true);
-
-    match response {
-        Ok(unspent_tx_outputs) => {
-            let utxos: Option<Vec<UnspentTxOutputs>> =
-                deserialize_response(&unspent_tx_outputs).unwrap();
-
-            for (index, utxo) in utxos.unwrap().iter().enumerate() {
-                
-                // Manually selecting the utxo to spend
-                if index == 1 {
-
-                    let input = Input {
-                        txid: utxo.txid.clone(),
-                        vout: utxo.vout.clone(),
-                    };
-                    
-                    let output = vec![json!({
-                        "bcrt1qpfk7t93jfl240a4qv78kplqvqntxafg03rx68p": 0.0001
-                    })];
-
-                    let create_psbt = create_psbt(input, output);
-
-                    match create_psbt {
-                        Ok(val) => println!("val here: {:?}", val),
-                        Err(e) => println!("error here: {:?}", e),
-                    }
-                }
-            }
-        }
-        Err(e) => println!("Error here: {:?}", e),
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("error: {}", e);
+        process::exit(1);
     }
 }
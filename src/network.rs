@@ -0,0 +1,94 @@
+use crate::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The Bitcoin network a node runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Network {
+    #[default]
+    Bitcoin,
+    Testnet,
+    Testnet4,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    /// The default RPC port of Bitcoin Core for this network.
+    pub fn default_rpc_port(&self) -> u16 {
+        match self {
+            Network::Bitcoin => 8332,
+            Network::Testnet => 18332,
+            Network::Testnet4 => 48332,
+            Network::Signet => 38332,
+            Network::Regtest => 18443,
+        }
+    }
+
+    /// The subdirectory of the datadir used by this network, if any.
+    pub fn datadir_subdir(&self) -> Option<&'static str> {
+        match self {
+            Network::Bitcoin => None,
+            Network::Testnet => Some("testnet3"),
+            Network::Testnet4 => Some("testnet4"),
+            Network::Signet => Some("signet"),
+            Network::Regtest => Some("regtest"),
+        }
+    }
+
+    /// Location of the `.cookie` file written by a node using `datadir`.
+    pub fn cookie_path(&self, datadir: &Path) -> PathBuf {
+        match self.datadir_subdir() {
+            Some(subdir) => datadir.join(subdir).join(".cookie"),
+            None => datadir.join(".cookie"),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    /// Accepts the names used by `bitcoin-cli -chain=<name>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" | "mainnet" | "bitcoin" => Ok(Network::Bitcoin),
+            "test" | "testnet" | "testnet3" => Ok(Network::Testnet),
+            "testnet4" => Ok(Network::Testnet4),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            other => Err(Error::Config(format!("unknown network: {other}"))),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Network::Bitcoin => "main",
+            Network::Testnet => "test",
+            Network::Testnet4 => "testnet4",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Bitcoin Core's default datadir for the current platform.
+pub fn default_datadir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("Bitcoin"))
+    } else {
+        let home = PathBuf::from(std::env::var_os("HOME")?);
+        if cfg!(target_os = "macos") {
+            Some(
+                home.join("Library")
+                    .join("Application Support")
+                    .join("Bitcoin"),
+            )
+        } else {
+            Some(home.join(".bitcoin"))
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::psbt::raw::{Pair, RawKey};
+use crate::psbt::{Error, PartiallySignedTransaction, MAGIC};
+use std::fmt;
+
+/// The map of a PSBT a key belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapLocation {
+    Global,
+    Input(usize),
+    Output(usize),
+}
+
+impl fmt::Display for MapLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapLocation::Global => write!(f, "global map"),
+            MapLocation::Input(index) => write!(f, "input {}", index),
+            MapLocation::Output(index) => write!(f, "output {}", index),
+        }
+    }
+}
+
+/// A key that has different values in two of the combined PSBTs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub location: MapLocation,
+    pub key: RawKey,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conflicting values for key {} in {}",
+            self.key, self.location
+        )
+    }
+}
+
+/// Combines copies of the same PSBT following the BIP174 combiner rules.
+///
+/// Every key-value pair of every copy is kept, so partial signatures, scripts,
+/// derivation paths and unknown pairs added by different signers are merged
+/// into one PSBT. All copies must describe the same transaction, and a key
+/// that appears with different values in two copies is reported as a conflict
+/// instead of silently picking one.
+pub fn combine(psbts: &[PartiallySignedTransaction]) -> Result<PartiallySignedTransaction, Error> {
+    let (first, rest) = psbts.split_first().ok_or(Error::NothingToCombine)?;
+    if rest
+        .iter()
+        .any(|psbt| psbt.version != first.version || psbt.unsigned_tx != first.unsigned_tx)
+    {
+        return Err(Error::DifferentTransactions);
+    }
+
+    let mut conflicts = Vec::new();
+    let mut merged = MAGIC.to_vec();
+
+    let global = merge(
+        psbts.iter().map(|psbt| psbt.global_pairs()),
+        MapLocation::Global,
+        &mut conflicts,
+    );
+    write_map(&mut merged, &global);
+
+    for (index, txin) in first.unsigned_tx.input.iter().enumerate() {
+        let pairs = merge(
+            psbts
+                .iter()
+                .map(|psbt| psbt.inputs[index].pairs(psbt.version, txin)),
+            MapLocation::Input(index),
+            &mut conflicts,
+        );
+        write_map(&mut merged, &pairs);
+    }
+
+    for (index, txout) in first.unsigned_tx.output.iter().enumerate() {
+        let pairs = merge(
+            psbts
+                .iter()
+                .map(|psbt| psbt.outputs[index].pairs(psbt.version, txout)),
+            MapLocation::Output(index),
+            &mut conflicts,
+        );
+        write_map(&mut merged, &pairs);
+    }
+
+    if !conflicts.is_empty() {
+        return Err(Error::Conflicts(conflicts));
+    }
+
+    // Parsing the merged maps validates the combination, e.g. that two copies
+    // did not add incompatible locktime requirements.
+    PartiallySignedTransaction::deserialize(&merged)
+}
+
+/// Merges the pairs of one map across copies, keeping the first copy's order.
+fn merge(
+    copies: impl Iterator<Item = Vec<Pair>>,
+    location: MapLocation,
+    conflicts: &mut Vec<Conflict>,
+) -> Vec<Pair> {
+    let mut merged: Vec<Pair> = Vec::new();
+    for pairs in copies {
+        for pair in pairs {
+            match merged.iter().find(|existing| existing.key == pair.key) {
+                Some(existing) if existing.value != pair.value => {
+                    let conflict = Conflict {
+                        location,
+                        key: pair.key,
+                    };
+                    if !conflicts.contains(&conflict) {
+                        conflicts.push(conflict);
+                    }
+                }
+                Some(_) => {}
+                None => merged.push(pair),
+            }
+        }
+    }
+    merged
+}
+
+fn write_map(out: &mut Vec<u8>, pairs: &[Pair]) {
+    for pair in pairs {
+        pair.encode(out);
+    }
+    out.push(0x00);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::psbt::raw::input as input_type;
+    use crate::transaction::{OutPoint, Script, Transaction, TxIn, TxOut, Txid};
+
+    fn unsigned() -> PartiallySignedTransaction {
+        PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid([1; 32]), 0),
+                script_sig: Script::default(),
+                sequence: 0xffff_fffd,
+                witness: Vec::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: Script([[0x00, 0x14].as_slice(), &[2; 20]].concat()),
+            }],
+        })
+        .unwrap()
+    }
+
+    fn pubkey(byte: u8) -> Vec<u8> {
+        let mut pubkey = vec![byte; 33];
+        pubkey[0] = 0x02;
+        pubkey
+    }
+
+    #[test]
+    fn partial_signatures_are_merged() {
+        let mut first = unsigned();
+        first.inputs[0]
+            .partial_sigs
+            .insert(pubkey(1), vec![0x30, 1, 0x01]);
+        let mut second = unsigned();
+        second.inputs[0]
+            .partial_sigs
+            .insert(pubkey(2), vec![0x30, 2, 0x01]);
+
+        let combined = combine(&[first, second]).unwrap();
+        let sigs = &combined.inputs[0].partial_sigs;
+        assert_eq!(sigs.len(), 2);
+        assert_eq!(sigs[&pubkey(1)], vec![0x30, 1, 0x01]);
+        assert_eq!(sigs[&pubkey(2)], vec![0x30, 2, 0x01]);
+    }
+
+    #[test]
+    fn identical_pairs_are_not_conflicts() {
+        let mut first = unsigned();
+        first.inputs[0].sighash_type = Some(1);
+        let combined = combine(&[first.clone(), first.clone()]).unwrap();
+        assert_eq!(combined, first);
+    }
+
+    #[test]
+    fn copies_of_different_transactions_are_rejected() {
+        let mut other = unsigned();
+        other.unsigned_tx.lock_time = 1;
+        assert_eq!(
+            combine(&[unsigned(), other]),
+            Err(Error::DifferentTransactions)
+        );
+        assert_eq!(
+            combine(&[unsigned(), unsigned().into_v2().unwrap()]),
+            Err(Error::DifferentTransactions)
+        );
+        assert_eq!(combine(&[]), Err(Error::NothingToCombine));
+    }
+
+    #[test]
+    fn different_values_for_a_key_conflict() {
+        let mut first = unsigned();
+        first.inputs[0]
+            .partial_sigs
+            .insert(pubkey(1), vec![0x30, 1, 0x01]);
+        let mut second = unsigned();
+        second.inputs[0]
+            .partial_sigs
+            .insert(pubkey(1), vec![0x30, 2, 0x01]);
+
+        assert_eq!(
+            combine(&[first, second]),
+            Err(Error::Conflicts(vec![Conflict {
+                location: MapLocation::Input(0),
+                key: RawKey::new(input_type::PARTIAL_SIG, pubkey(1)),
+            }]))
+        );
+    }
+}
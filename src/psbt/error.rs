@@ -0,0 +1,161 @@
+use crate::encode;
+use crate::psbt::raw::RawKey;
+use crate::psbt::Conflict;
+use crate::transaction::OutPoint;
+use std::fmt;
+
+/// Errors returned when parsing or validating a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The data is not valid base64.
+    InvalidBase64,
+    /// The data does not start with the `psbt\xff` magic bytes.
+    InvalidMagic,
+    /// The binary encoding is malformed.
+    Encode(encode::Error),
+    /// A key appears more than once in the same map.
+    DuplicateKey(RawKey),
+    /// The key data is not valid for the key type.
+    InvalidKey(RawKey),
+    /// The value is not valid for the key type.
+    InvalidValue { key: RawKey, reason: String },
+    /// The PSBT version is not supported.
+    UnsupportedVersion(u32),
+    /// The global unsigned transaction is missing from a version 0 PSBT.
+    MissingUnsignedTx,
+    /// A key required by the PSBT version is missing.
+    MissingKey { map: &'static str, type_value: u64 },
+    /// A key is not allowed in this PSBT version.
+    KeyNotAllowed { version: u32, key: RawKey },
+    /// The inputs require both a height and a time based locktime.
+    LockTimeConflict,
+    /// The transaction version is below 2, which PSBT version 2 requires.
+    InvalidTxVersion(i32),
+    /// Inputs cannot be added without invalidating signatures or the
+    /// `PSBT_GLOBAL_TX_MODIFIABLE` flags.
+    InputsNotModifiable,
+    /// Outputs cannot be added without invalidating signatures or the
+    /// `PSBT_GLOBAL_TX_MODIFIABLE` flags.
+    OutputsNotModifiable,
+    /// The unsigned transaction has scriptSigs or witnesses.
+    UnsignedTxHasScripts,
+    /// The number of input maps does not match the transaction's inputs.
+    InputCountMismatch { expected: usize, found: usize },
+    /// The number of output maps does not match the transaction's outputs.
+    OutputCountMismatch { expected: usize, found: usize },
+    /// No PSBTs were given to combine.
+    NothingToCombine,
+    /// The PSBTs to combine describe different transactions.
+    DifferentTransactions,
+    /// The PSBTs to combine have different values for the same keys.
+    Conflicts(Vec<Conflict>),
+    /// No PSBTs were given to join.
+    NothingToJoin,
+    /// The same input is spent by more than one of the PSBTs to join.
+    DuplicateInput(OutPoint),
+    /// An input signed with `SIGHASH_SINGLE` would no longer share its index
+    /// with the output it signs.
+    SighashSingleReordered(OutPoint),
+    /// Joining would change the transaction version or locktime that a
+    /// contribution's signatures commit to.
+    SignedFieldsChanged,
+    /// The input at this index is not finalized.
+    NotFinalized(usize),
+    /// The input at this index has no UTXO, or its non-witness UTXO does not
+    /// match the outpoint it spends.
+    MissingUtxo(usize),
+    /// The outputs are worth more than the inputs, or the values overflow.
+    NegativeFee,
+}
+
+impl Error {
+    pub(crate) fn invalid_value(key: &RawKey, reason: impl Into<String>) -> Self {
+        Error::InvalidValue {
+            key: key.clone(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidBase64 => write!(f, "PSBT is not valid base64"),
+            Error::InvalidMagic => write!(f, "missing PSBT magic bytes"),
+            Error::Encode(e) => write!(f, "malformed PSBT: {}", e),
+            Error::DuplicateKey(key) => write!(f, "duplicate key {}", key),
+            Error::InvalidKey(key) => write!(f, "invalid key {}", key),
+            Error::InvalidValue { key, reason } => {
+                write!(f, "invalid value for key {}: {}", key, reason)
+            }
+            Error::UnsupportedVersion(version) => write!(f, "unsupported PSBT version {}", version),
+            Error::MissingUnsignedTx => write!(f, "missing unsigned transaction"),
+            Error::MissingKey { map, type_value } => {
+                write!(f, "{} map is missing key {:#04x}", map, type_value)
+            }
+            Error::KeyNotAllowed { version, key } => {
+                write!(
+                    f,
+                    "key {} is not allowed in a version {} PSBT",
+                    key, version
+                )
+            }
+            Error::LockTimeConflict => {
+                write!(f, "inputs require both height and time based locktimes")
+            }
+            Error::InvalidTxVersion(version) => {
+                write!(f, "transaction version {} is below 2", version)
+            }
+            Error::InputsNotModifiable => write!(f, "inputs of this PSBT are not modifiable"),
+            Error::OutputsNotModifiable => write!(f, "outputs of this PSBT are not modifiable"),
+            Error::NothingToCombine => write!(f, "no PSBTs to combine"),
+            Error::DifferentTransactions => {
+                write!(f, "the PSBTs to combine describe different transactions")
+            }
+            Error::Conflicts(conflicts) => {
+                let conflicts: Vec<String> = conflicts.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", conflicts.join("; "))
+            }
+            Error::NotFinalized(index) => write!(f, "input {} is not finalized", index),
+            Error::MissingUtxo(index) => write!(f, "input {} has no matching UTXO", index),
+            Error::NegativeFee => write!(f, "outputs are worth more than the inputs"),
+            Error::NothingToJoin => write!(f, "no PSBTs to join"),
+            Error::DuplicateInput(outpoint) => {
+                write!(f, "input {} is spent more than once", outpoint)
+            }
+            Error::SighashSingleReordered(outpoint) => write!(
+                f,
+                "input {} is signed with SIGHASH_SINGLE and would lose its paired output",
+                outpoint
+            ),
+            Error::SignedFieldsChanged => write!(
+                f,
+                "joining would change the transaction version or locktime of signed inputs"
+            ),
+            Error::UnsignedTxHasScripts => {
+                write!(f, "unsigned transaction has scriptSigs or witnesses")
+            }
+            Error::InputCountMismatch { expected, found } => {
+                write!(f, "expected {} input maps, found {}", expected, found)
+            }
+            Error::OutputCountMismatch { expected, found } => {
+                write!(f, "expected {} output maps, found {}", expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<encode::Error> for Error {
+    fn from(e: encode::Error) -> Self {
+        Error::Encode(e)
+    }
+}
@@ -0,0 +1,560 @@
+use crate::amount::Amount;
+use crate::hash::{hash160, sha256};
+use crate::psbt::{Error, Input, PartiallySignedTransaction};
+use crate::transaction::{OutPoint, Script, Transaction, TxOut};
+use std::fmt;
+
+/// Data an input still needs before it can be finalized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Missing {
+    /// Neither the spent output nor the transaction creating it is present.
+    Utxo,
+    /// The P2SH redeem script is missing or does not match the spent output.
+    RedeemScript,
+    /// The P2WSH witness script is missing or does not match the spent output.
+    WitnessScript,
+    /// A signature from the public key with this HASH160.
+    Signature([u8; 20]),
+    /// Not enough signatures for a multisig script.
+    Signatures { required: usize, found: usize },
+    /// A signature from this public key of a multisig script.
+    PubkeySignature(Vec<u8>),
+    /// The Schnorr signature for a taproot key path spend.
+    TapKeySig,
+    /// The script is not one of the standard types this finalizer supports.
+    UnsupportedScript(Script),
+}
+
+impl fmt::Display for Missing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Missing::Utxo => write!(f, "missing the spent output"),
+            Missing::RedeemScript => write!(f, "missing a matching redeem script"),
+            Missing::WitnessScript => write!(f, "missing a matching witness script"),
+            Missing::Signature(key_hash) => {
+                write!(
+                    f,
+                    "missing a signature for key hash {}",
+                    hex::encode(key_hash)
+                )
+            }
+            Missing::Signatures { required, found } => {
+                write!(f, "has {} of {} required signatures", found, required)
+            }
+            Missing::PubkeySignature(pubkey) => {
+                write!(f, "missing a signature from key {}", hex::encode(pubkey))
+            }
+            Missing::TapKeySig => write!(f, "missing the taproot key path signature"),
+            Missing::UnsupportedScript(script) => write!(f, "unsupported script {}", script),
+        }
+    }
+}
+
+/// An input that could not be finalized, with everything it is missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incomplete {
+    pub index: usize,
+    pub missing: Vec<Missing>,
+}
+
+impl fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing: Vec<String> = self.missing.iter().map(Missing::to_string).collect();
+        write!(f, "input {} {}", self.index, missing.join(", "))
+    }
+}
+
+impl PartiallySignedTransaction {
+    /// Finalizes every input that has the signatures and scripts it needs,
+    /// returning the inputs that do not.
+    ///
+    /// P2PKH, P2WPKH, P2SH-P2WPKH, multisig in P2SH, P2WSH or P2SH-P2WSH and
+    /// taproot key path inputs are supported; taproot script path spends are
+    /// reported as unsupported scripts. Signatures are not verified.
+    /// Finalized inputs keep only their UTXO, final scripts, required
+    /// locktimes, proprietary and unknown pairs, as BIP174 specifies.
+    pub fn finalize(&mut self) -> Vec<Incomplete> {
+        let mut incomplete = Vec::new();
+        for (index, (input, txin)) in self
+            .inputs
+            .iter_mut()
+            .zip(&self.unsigned_tx.input)
+            .enumerate()
+        {
+            if input.is_finalized() {
+                continue;
+            }
+            match finalize_input(input, &txin.previous_output) {
+                Ok((script_sig, witness)) => {
+                    *input = Input {
+                        non_witness_utxo: input.non_witness_utxo.take(),
+                        witness_utxo: input.witness_utxo.take(),
+                        final_script_sig: (!script_sig.is_empty()).then_some(script_sig),
+                        final_script_witness: (!witness.is_empty()).then_some(witness),
+                        required_time_locktime: input.required_time_locktime,
+                        required_height_locktime: input.required_height_locktime,
+                        proprietary: std::mem::take(&mut input.proprietary),
+                        unknown: std::mem::take(&mut input.unknown),
+                        key_order: std::mem::take(&mut input.key_order),
+                        ..Input::default()
+                    };
+                }
+                Err(missing) => incomplete.push(Incomplete { index, missing }),
+            }
+        }
+        incomplete
+    }
+
+    /// The absolute fee: the value of the spent outputs minus the value of
+    /// the transaction's outputs.
+    pub fn fee(&self) -> Result<Amount, Error> {
+        let spent = self
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(&self.inputs)
+            .enumerate()
+            .map(|(index, (txin, input))| {
+                input
+                    .spent_output(&txin.previous_output)
+                    .map(|txout| txout.value)
+                    .ok_or(Error::MissingUtxo(index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let spent = Amount::checked_sum(spent).ok_or(Error::NegativeFee)?;
+        let sent = Amount::checked_sum(self.unsigned_tx.output.iter().map(|txout| txout.value))
+            .ok_or(Error::NegativeFee)?;
+        spent.checked_sub(sent).ok_or(Error::NegativeFee)
+    }
+
+    /// Builds the network transaction from a PSBT whose inputs are all
+    /// finalized.
+    pub fn extract_tx(&self) -> Result<Transaction, Error> {
+        let mut tx = self.unsigned_tx.clone();
+        for (index, (txin, input)) in tx.input.iter_mut().zip(&self.inputs).enumerate() {
+            if !input.is_finalized() {
+                return Err(Error::NotFinalized(index));
+            }
+            txin.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            txin.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        Ok(tx)
+    }
+}
+
+impl Input {
+    /// The output this input spends, from the witness UTXO or the non-witness
+    /// UTXO, whose txid must match `prevout`.
+    pub fn spent_output(&self, prevout: &OutPoint) -> Option<&TxOut> {
+        self.witness_utxo.as_ref().or_else(|| {
+            let tx = self.non_witness_utxo.as_ref()?;
+            if tx.txid() != prevout.txid {
+                return None;
+            }
+            tx.output.get(prevout.vout as usize)
+        })
+    }
+}
+
+/// Builds the final scriptSig and witness of an input, or lists what it is
+/// missing.
+fn finalize_input(
+    input: &Input,
+    prevout: &OutPoint,
+) -> Result<(Script, Vec<Vec<u8>>), Vec<Missing>> {
+    let script_pubkey = &input
+        .spent_output(prevout)
+        .ok_or_else(|| vec![Missing::Utxo])?
+        .script_pubkey;
+
+    if script_pubkey.is_p2tr() {
+        if let Some(sig) = &input.tap_key_sig {
+            return Ok((Script::new(), vec![sig.clone()]));
+        }
+        // Script path signatures, or leaves without a key to spend the key
+        // path with, mean the input is meant to be spent by a script.
+        let script_path = !input.tap_script_sigs.is_empty()
+            || (input.tap_internal_key.is_none() && !input.tap_scripts.is_empty());
+        if !script_path {
+            return Err(vec![Missing::TapKeySig]);
+        }
+        let mut missing = Vec::new();
+        for (script, _) in input.tap_scripts.values() {
+            let unsupported = Missing::UnsupportedScript(script.clone());
+            if !missing.contains(&unsupported) {
+                missing.push(unsupported);
+            }
+        }
+        if missing.is_empty() {
+            missing.push(Missing::UnsupportedScript(script_pubkey.clone()));
+        }
+        return Err(missing);
+    }
+    if script_pubkey.is_p2wpkh() || script_pubkey.is_p2wsh() {
+        return Ok((Script::new(), segwit_witness(input, script_pubkey)?));
+    }
+    if script_pubkey.is_p2sh() {
+        let redeem_script = input
+            .redeem_script
+            .as_ref()
+            .filter(|script| Some(&hash160(script.as_bytes())[..]) == script_pubkey.payload())
+            .ok_or_else(|| vec![Missing::RedeemScript])?;
+
+        let mut script_sig = Script::new();
+        if redeem_script.is_p2wpkh() || redeem_script.is_p2wsh() {
+            script_sig.push_slice(redeem_script.as_bytes());
+            return Ok((script_sig, segwit_witness(input, redeem_script)?));
+        }
+        for item in satisfy(input, redeem_script)? {
+            script_sig.push_slice(&item);
+        }
+        script_sig.push_slice(redeem_script.as_bytes());
+        return Ok((script_sig, Vec::new()));
+    }
+
+    let mut script_sig = Script::new();
+    for item in satisfy(input, script_pubkey)? {
+        script_sig.push_slice(&item);
+    }
+    Ok((script_sig, Vec::new()))
+}
+
+/// The witness for a P2WPKH or P2WSH program.
+fn segwit_witness(input: &Input, program: &Script) -> Result<Vec<Vec<u8>>, Vec<Missing>> {
+    if program.is_p2wpkh() {
+        return satisfy(input, program);
+    }
+
+    let witness_script = input
+        .witness_script
+        .as_ref()
+        .filter(|script| Some(&sha256(script.as_bytes())[..]) == program.payload())
+        .ok_or_else(|| vec![Missing::WitnessScript])?;
+    let mut witness = satisfy(input, witness_script)?;
+    witness.push(witness_script.0.clone());
+    Ok(witness)
+}
+
+/// The stack items satisfying a single-key or multisig script.
+fn satisfy(input: &Input, script: &Script) -> Result<Vec<Vec<u8>>, Vec<Missing>> {
+    if script.is_p2pkh() || script.is_p2wpkh() {
+        let mut key_hash = [0u8; 20];
+        key_hash.copy_from_slice(script.payload().unwrap_or_default());
+        let (pubkey, sig) = input
+            .partial_sigs
+            .iter()
+            .find(|(pubkey, _)| hash160(pubkey) == key_hash)
+            .ok_or_else(|| vec![Missing::Signature(key_hash)])?;
+        return Ok(vec![sig.clone(), pubkey.clone()]);
+    }
+
+    if let Some((required, pubkeys)) = parse_multisig(script) {
+        // CHECKMULTISIG pops one extra item, and expects the signatures in
+        // the order of their keys.
+        let mut stack = vec![Vec::new()];
+        stack.extend(
+            pubkeys
+                .iter()
+                .filter_map(|pubkey| input.partial_sigs.get(*pubkey).cloned())
+                .take(required),
+        );
+        let found = stack.len() - 1;
+        if found < required {
+            let mut missing = vec![Missing::Signatures { required, found }];
+            missing.extend(
+                pubkeys
+                    .iter()
+                    .filter(|pubkey| !input.partial_sigs.contains_key(**pubkey))
+                    .map(|pubkey| Missing::PubkeySignature(pubkey.to_vec())),
+            );
+            return Err(missing);
+        }
+        return Ok(stack);
+    }
+
+    Err(vec![Missing::UnsupportedScript(script.clone())])
+}
+
+/// Parses `OP_m <pubkey>... OP_n OP_CHECKMULTISIG`, returning `m` and the keys.
+fn parse_multisig(script: &Script) -> Option<(usize, Vec<&[u8]>)> {
+    let small_int = |op: u8| (0x51..=0x60).contains(&op).then(|| (op - 0x50) as usize);
+
+    let (&last, rest) = script.as_bytes().split_last()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let (&n_op, _) = rest.split_last()?;
+    rest = &rest[..rest.len() - 1];
+    if last != 0xae {
+        return None;
+    }
+    let required = small_int(first)?;
+    let total = small_int(n_op)?;
+
+    let mut pubkeys = Vec::new();
+    while let Some((&len, tail)) = rest.split_first() {
+        if !(len == 33 || len == 65) || tail.len() < len as usize {
+            return None;
+        }
+        pubkeys.push(&tail[..len as usize]);
+        rest = &tail[len as usize..];
+    }
+
+    (pubkeys.len() == total && required <= total).then_some((required, pubkeys))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{TxIn, Txid};
+
+    fn pubkey(byte: u8) -> Vec<u8> {
+        let mut pubkey = vec![byte; 33];
+        pubkey[0] = 0x02;
+        pubkey
+    }
+
+    fn sig(byte: u8) -> Vec<u8> {
+        let mut sig = vec![byte; 71];
+        sig[0] = 0x30;
+        sig[70] = 0x01;
+        sig
+    }
+
+    fn p2wpkh(pubkey: &[u8]) -> Script {
+        Script([[0x00, 0x14].as_slice(), &hash160(pubkey)].concat())
+    }
+
+    fn p2sh(script: &Script) -> Script {
+        Script(
+            [
+                [0xa9, 0x14].as_slice(),
+                &hash160(script.as_bytes()),
+                &[0x87],
+            ]
+            .concat(),
+        )
+    }
+
+    fn p2wsh(script: &Script) -> Script {
+        Script([[0x00, 0x20].as_slice(), &sha256(script.as_bytes())].concat())
+    }
+
+    fn multisig(required: u8, pubkeys: &[Vec<u8>]) -> Script {
+        let mut script = Script(vec![0x50 + required]);
+        for pubkey in pubkeys {
+            script.push_slice(pubkey);
+        }
+        script
+            .0
+            .extend_from_slice(&[0x50 + pubkeys.len() as u8, 0xae]);
+        script
+    }
+
+    /// A PSBT spending one output with `script_pubkey`, its input map filled
+    /// in by `update`.
+    fn spending(
+        script_pubkey: Script,
+        update: impl FnOnce(&mut Input),
+    ) -> PartiallySignedTransaction {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid([1; 32]), 0),
+                script_sig: Script::new(),
+                sequence: 0xffff_fffd,
+                witness: Vec::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: p2wpkh(&pubkey(9)),
+            }],
+        })
+        .unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey,
+        });
+        update(&mut psbt.inputs[0]);
+        psbt
+    }
+
+    fn finalized(mut psbt: PartiallySignedTransaction) -> Input {
+        assert_eq!(psbt.finalize(), vec![]);
+        let input = psbt.inputs[0].clone();
+        assert!(input.partial_sigs.is_empty());
+        assert!(input.witness_utxo.is_some());
+        assert!(psbt.extract_tx().is_ok());
+        input
+    }
+
+    #[test]
+    fn finalizes_p2pkh() {
+        let script_pubkey = Script(
+            [
+                [0x76, 0xa9, 0x14].as_slice(),
+                &hash160(&pubkey(1)),
+                &[0x88, 0xac],
+            ]
+            .concat(),
+        );
+        let input = finalized(spending(script_pubkey, |input| {
+            input.partial_sigs.insert(pubkey(1), sig(1));
+        }));
+
+        let mut script_sig = Script::new();
+        script_sig.push_slice(&sig(1));
+        script_sig.push_slice(&pubkey(1));
+        assert_eq!(input.final_script_sig, Some(script_sig));
+        assert_eq!(input.final_script_witness, None);
+    }
+
+    #[test]
+    fn finalizes_p2wpkh() {
+        let input = finalized(spending(p2wpkh(&pubkey(1)), |input| {
+            input.partial_sigs.insert(pubkey(2), sig(2));
+            input.partial_sigs.insert(pubkey(1), sig(1));
+        }));
+        assert_eq!(input.final_script_sig, None);
+        assert_eq!(input.final_script_witness, Some(vec![sig(1), pubkey(1)]));
+    }
+
+    #[test]
+    fn finalizes_p2sh_p2wpkh() {
+        let redeem_script = p2wpkh(&pubkey(1));
+        let input = finalized(spending(p2sh(&redeem_script), |input| {
+            input.redeem_script = Some(redeem_script.clone());
+            input.partial_sigs.insert(pubkey(1), sig(1));
+        }));
+
+        let mut script_sig = Script::new();
+        script_sig.push_slice(redeem_script.as_bytes());
+        assert_eq!(input.final_script_sig, Some(script_sig));
+        assert_eq!(input.final_script_witness, Some(vec![sig(1), pubkey(1)]));
+        assert_eq!(input.redeem_script, None);
+    }
+
+    #[test]
+    fn finalizes_two_of_three_p2wsh() {
+        let pubkeys = [pubkey(1), pubkey(2), pubkey(3)];
+        let witness_script = multisig(2, &pubkeys);
+        let input = finalized(spending(p2wsh(&witness_script), |input| {
+            input.witness_script = Some(witness_script.clone());
+            input.partial_sigs.insert(pubkey(3), sig(3));
+            input.partial_sigs.insert(pubkey(1), sig(1));
+        }));
+
+        // The signatures follow the order of their keys in the script.
+        assert_eq!(
+            input.final_script_witness,
+            Some(vec![Vec::new(), sig(1), sig(3), witness_script.0])
+        );
+        assert_eq!(input.witness_script, None);
+    }
+
+    #[test]
+    fn finalizes_taproot_key_path() {
+        let script_pubkey = Script([[0x51, 0x20].as_slice(), &[7; 32]].concat());
+        let input = finalized(spending(script_pubkey, |input| {
+            input.tap_key_sig = Some(vec![7; 64]);
+            input.tap_internal_key = Some([7; 32]);
+        }));
+        assert_eq!(input.final_script_sig, None);
+        assert_eq!(input.final_script_witness, Some(vec![vec![7; 64]]));
+        assert_eq!(input.tap_internal_key, None);
+    }
+
+    #[test]
+    fn incomplete_multisig_lists_the_unsigned_keys() {
+        let pubkeys = [pubkey(1), pubkey(2), pubkey(3)];
+        let witness_script = multisig(2, &pubkeys);
+        let mut psbt = spending(p2wsh(&witness_script), |input| {
+            input.witness_script = Some(witness_script.clone());
+            input.partial_sigs.insert(pubkey(2), sig(2));
+        });
+        let before = psbt.inputs[0].clone();
+
+        let incomplete = psbt.finalize();
+        assert_eq!(
+            incomplete,
+            vec![Incomplete {
+                index: 0,
+                missing: vec![
+                    Missing::Signatures {
+                        required: 2,
+                        found: 1
+                    },
+                    Missing::PubkeySignature(pubkey(1)),
+                    Missing::PubkeySignature(pubkey(3)),
+                ],
+            }]
+        );
+        assert_eq!(
+            incomplete[0].to_string(),
+            format!(
+                "input 0 has 1 of 2 required signatures, missing a signature from key {}, \
+                 missing a signature from key {}",
+                hex::encode(pubkey(1)),
+                hex::encode(pubkey(3))
+            )
+        );
+        // Incomplete inputs are left untouched.
+        assert_eq!(psbt.inputs[0], before);
+        assert_eq!(psbt.extract_tx(), Err(Error::NotFinalized(0)));
+    }
+
+    #[test]
+    fn missing_data_is_reported() {
+        let key_hash = hash160(&pubkey(1));
+        let mut psbt = spending(p2wpkh(&pubkey(1)), |_| {});
+        psbt.inputs[0].witness_utxo = None;
+        assert_eq!(psbt.finalize()[0].missing, vec![Missing::Utxo]);
+
+        let mut psbt = spending(p2wpkh(&pubkey(1)), |_| {});
+        assert_eq!(
+            psbt.finalize()[0].missing,
+            vec![Missing::Signature(key_hash)]
+        );
+
+        let mut psbt = spending(p2sh(&p2wpkh(&pubkey(1))), |input| {
+            input.redeem_script = Some(p2wpkh(&pubkey(2)));
+        });
+        assert_eq!(psbt.finalize()[0].missing, vec![Missing::RedeemScript]);
+
+        let mut psbt = spending(p2wsh(&multisig(1, &[pubkey(1)])), |_| {});
+        assert_eq!(psbt.finalize()[0].missing, vec![Missing::WitnessScript]);
+    }
+
+    #[test]
+    fn taproot_script_paths_are_unsupported() {
+        let script_pubkey = Script([[0x51, 0x20].as_slice(), &[7; 32]].concat());
+        let mut key_path = spending(script_pubkey.clone(), |input| {
+            input.tap_internal_key = Some([7; 32]);
+        });
+        assert_eq!(key_path.finalize()[0].missing, vec![Missing::TapKeySig]);
+
+        let leaf = Script([[0x20].as_slice(), &[8; 32], &[0xac]].concat());
+        let mut script_path = spending(script_pubkey.clone(), |input| {
+            input.tap_internal_key = Some([7; 32]);
+            input
+                .tap_scripts
+                .insert(vec![0xc0; 33], (leaf.clone(), 0xc0));
+            input
+                .tap_script_sigs
+                .insert(([8; 32], [9; 32]), vec![8; 64]);
+        });
+        assert_eq!(
+            script_path.finalize()[0].missing,
+            vec![Missing::UnsupportedScript(leaf)]
+        );
+
+        let mut no_leaves = spending(script_pubkey.clone(), |input| {
+            input
+                .tap_script_sigs
+                .insert(([8; 32], [9; 32]), vec![8; 64]);
+        });
+        assert_eq!(
+            no_leaves.finalize()[0].missing,
+            vec![Missing::UnsupportedScript(script_pubkey)]
+        );
+    }
+}
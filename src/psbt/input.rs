@@ -0,0 +1,462 @@
+use crate::encode::{write_compact_size, Reader};
+use crate::psbt::raw::{
+    self, decode_value, expect_empty_key, insert_unique, key_array, key_pubkey, set_unique,
+    value_array, KeyOrder, KeySource, Pair, ProprietaryKey, RawKey,
+};
+use crate::psbt::Error;
+use crate::transaction::{
+    decode_witness, encode_witness, OutPoint, Script, Transaction, TxIn, TxOut, Txid,
+};
+use std::collections::BTreeMap;
+
+use raw::input as key_type;
+
+/// A per-input map of a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Input {
+    /// The full transaction creating the spent output.
+    pub non_witness_utxo: Option<Transaction>,
+    /// The spent output, for segwit inputs.
+    pub witness_utxo: Option<TxOut>,
+    /// Signatures, keyed by public key.
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    /// Key sources, keyed by public key.
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    pub final_script_sig: Option<Script>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    /// Hash preimages, keyed by hash.
+    pub ripemd160_preimages: BTreeMap<[u8; 20], Vec<u8>>,
+    pub sha256_preimages: BTreeMap<[u8; 32], Vec<u8>>,
+    pub hash160_preimages: BTreeMap<[u8; 20], Vec<u8>>,
+    pub hash256_preimages: BTreeMap<[u8; 32], Vec<u8>>,
+    /// The Schnorr signature for a taproot key path spend.
+    pub tap_key_sig: Option<Vec<u8>>,
+    /// Schnorr signatures for script path spends, keyed by x-only key and leaf hash.
+    pub tap_script_sigs: BTreeMap<([u8; 32], [u8; 32]), Vec<u8>>,
+    /// Leaf scripts and their leaf versions, keyed by control block.
+    pub tap_scripts: BTreeMap<Vec<u8>, (Script, u8)>,
+    /// Leaf hashes and key sources, keyed by x-only key.
+    pub tap_key_origins: BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeySource)>,
+    pub tap_internal_key: Option<[u8; 32]>,
+    pub tap_merkle_root: Option<[u8; 32]>,
+    /// The minimum time based locktime this input needs (PSBT version 2).
+    pub required_time_locktime: Option<u32>,
+    /// The minimum height based locktime this input needs (PSBT version 2).
+    pub required_height_locktime: Option<u32>,
+    pub proprietary: BTreeMap<ProprietaryKey, Vec<u8>>,
+    /// Pairs with key types this crate does not know, kept as they were read.
+    pub unknown: BTreeMap<RawKey, Vec<u8>>,
+    pub(crate) key_order: KeyOrder,
+}
+
+impl Input {
+    /// Whether the input has a final scriptSig or scriptWitness.
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    /// Decodes an input map. For version 2 PSBTs the transaction input the
+    /// map describes is returned as well.
+    pub(crate) fn decode(reader: &mut Reader, version: u32) -> Result<(Self, Option<TxIn>), Error> {
+        let mut input = Input::default();
+        let mut fields = TxInFields::default();
+        while let Some(pair) = Pair::decode(reader)? {
+            input.key_order.push(pair.key.clone());
+            if version >= 2 && fields.insert_pair(&pair)? {
+                continue;
+            }
+            input.insert_pair(pair, version)?;
+        }
+
+        if version < 2 {
+            return Ok((input, None));
+        }
+        let missing = |type_value| Error::MissingKey {
+            map: "input",
+            type_value,
+        };
+        let txid = fields
+            .previous_txid
+            .ok_or_else(|| missing(key_type::PREVIOUS_TXID))?;
+        let vout = fields
+            .output_index
+            .ok_or_else(|| missing(key_type::OUTPUT_INDEX))?;
+        let txin = TxIn {
+            previous_output: OutPoint::new(Txid(txid), vout),
+            script_sig: Script::new(),
+            sequence: fields.sequence.unwrap_or(0xffff_ffff),
+            witness: Vec::new(),
+        };
+        Ok((input, Some(txin)))
+    }
+
+    fn insert_pair(&mut self, pair: Pair, version: u32) -> Result<(), Error> {
+        let raw = &pair.key;
+        match raw.type_value {
+            key_type::NON_WITNESS_UTXO => {
+                expect_empty_key(&pair)?;
+                let tx = decode_value(&pair, |r| Transaction::decode(r, true))?;
+                set_unique(&mut self.non_witness_utxo, tx, raw)
+            }
+            key_type::WITNESS_UTXO => {
+                expect_empty_key(&pair)?;
+                let txout = decode_value(&pair, TxOut::decode)?;
+                set_unique(&mut self.witness_utxo, txout, raw)
+            }
+            key_type::PARTIAL_SIG => {
+                let pubkey = key_pubkey(&pair)?;
+                insert_unique(&mut self.partial_sigs, pubkey, pair.value.clone(), raw)
+            }
+            key_type::SIGHASH_TYPE => {
+                expect_empty_key(&pair)?;
+                let sighash = u32::from_le_bytes(value_array(&pair)?);
+                set_unique(&mut self.sighash_type, sighash, raw)
+            }
+            key_type::REDEEM_SCRIPT => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.redeem_script, Script(pair.value.clone()), raw)
+            }
+            key_type::WITNESS_SCRIPT => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.witness_script, Script(pair.value.clone()), raw)
+            }
+            key_type::BIP32_DERIVATION => {
+                let pubkey = key_pubkey(&pair)?;
+                let source = decode_value(&pair, KeySource::decode)?;
+                insert_unique(&mut self.bip32_derivation, pubkey, source, raw)
+            }
+            key_type::FINAL_SCRIPTSIG => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.final_script_sig, Script(pair.value.clone()), raw)
+            }
+            key_type::FINAL_SCRIPTWITNESS => {
+                expect_empty_key(&pair)?;
+                let witness = decode_value(&pair, decode_witness)?;
+                set_unique(&mut self.final_script_witness, witness, raw)
+            }
+            key_type::RIPEMD160 => {
+                let hash = key_array(&pair)?;
+                insert_unique(&mut self.ripemd160_preimages, hash, pair.value.clone(), raw)
+            }
+            key_type::SHA256 => {
+                let hash = key_array(&pair)?;
+                insert_unique(&mut self.sha256_preimages, hash, pair.value.clone(), raw)
+            }
+            key_type::HASH160 => {
+                let hash = key_array(&pair)?;
+                insert_unique(&mut self.hash160_preimages, hash, pair.value.clone(), raw)
+            }
+            key_type::HASH256 => {
+                let hash = key_array(&pair)?;
+                insert_unique(&mut self.hash256_preimages, hash, pair.value.clone(), raw)
+            }
+            key_type::TAP_KEY_SIG => {
+                expect_empty_key(&pair)?;
+                check_schnorr_sig(&pair)?;
+                set_unique(&mut self.tap_key_sig, pair.value.clone(), raw)
+            }
+            key_type::TAP_SCRIPT_SIG => {
+                let key: [u8; 64] = key_array(&pair)?;
+                check_schnorr_sig(&pair)?;
+                let mut xonly = [0u8; 32];
+                let mut leaf_hash = [0u8; 32];
+                xonly.copy_from_slice(&key[..32]);
+                leaf_hash.copy_from_slice(&key[32..]);
+                insert_unique(
+                    &mut self.tap_script_sigs,
+                    (xonly, leaf_hash),
+                    pair.value.clone(),
+                    raw,
+                )
+            }
+            key_type::TAP_LEAF_SCRIPT => {
+                let control_block = &pair.key.key;
+                if control_block.len() < 33 || !(control_block.len() - 33).is_multiple_of(32) {
+                    return Err(Error::InvalidKey(raw.clone()));
+                }
+                let (leaf_version, script) = pair
+                    .value
+                    .split_last()
+                    .ok_or_else(|| Error::invalid_value(raw, "missing leaf version"))?;
+                let leaf = (Script(script.to_vec()), *leaf_version);
+                insert_unique(&mut self.tap_scripts, control_block.clone(), leaf, raw)
+            }
+            key_type::TAP_BIP32_DERIVATION => {
+                let xonly = key_array(&pair)?;
+                let origin = decode_value(&pair, decode_tap_key_origin)?;
+                insert_unique(&mut self.tap_key_origins, xonly, origin, raw)
+            }
+            key_type::TAP_INTERNAL_KEY => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.tap_internal_key, value_array(&pair)?, raw)
+            }
+            key_type::TAP_MERKLE_ROOT => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.tap_merkle_root, value_array(&pair)?, raw)
+            }
+            key_type::REQUIRED_TIME_LOCKTIME if version >= 2 => {
+                expect_empty_key(&pair)?;
+                let locktime = u32::from_le_bytes(value_array(&pair)?);
+                if locktime < LOCKTIME_THRESHOLD {
+                    return Err(Error::invalid_value(raw, "time locktime below 500000000"));
+                }
+                set_unique(&mut self.required_time_locktime, locktime, raw)
+            }
+            key_type::REQUIRED_HEIGHT_LOCKTIME if version >= 2 => {
+                expect_empty_key(&pair)?;
+                let locktime = u32::from_le_bytes(value_array(&pair)?);
+                if locktime == 0 || locktime >= LOCKTIME_THRESHOLD {
+                    return Err(Error::invalid_value(raw, "height locktime out of range"));
+                }
+                set_unique(&mut self.required_height_locktime, locktime, raw)
+            }
+            key_type::PREVIOUS_TXID
+            | key_type::OUTPUT_INDEX
+            | key_type::SEQUENCE
+            | key_type::REQUIRED_TIME_LOCKTIME
+            | key_type::REQUIRED_HEIGHT_LOCKTIME => Err(Error::KeyNotAllowed {
+                version,
+                key: raw.clone(),
+            }),
+            key_type::PROPRIETARY => {
+                let key = ProprietaryKey::decode(raw)?;
+                insert_unique(&mut self.proprietary, key, pair.value.clone(), raw)
+            }
+            _ => insert_unique(&mut self.unknown, raw.clone(), pair.value.clone(), raw),
+        }
+    }
+
+    /// The map's key-value pairs, in the order they are serialized. Version 2
+    /// PSBTs also carry the fields of `txin`, the input the map describes.
+    pub(crate) fn pairs(&self, version: u32, txin: &TxIn) -> Vec<Pair> {
+        let mut pairs = Vec::new();
+        if version >= 2 {
+            let outpoint = &txin.previous_output;
+            pairs.push(Pair::new(
+                key_type::PREVIOUS_TXID,
+                vec![],
+                outpoint.txid.0.to_vec(),
+            ));
+            pairs.push(Pair::new(
+                key_type::OUTPUT_INDEX,
+                vec![],
+                outpoint.vout.to_le_bytes().to_vec(),
+            ));
+            if txin.sequence != 0xffff_ffff || self.key_order.contains(key_type::SEQUENCE) {
+                pairs.push(Pair::new(
+                    key_type::SEQUENCE,
+                    vec![],
+                    txin.sequence.to_le_bytes().to_vec(),
+                ));
+            }
+            if let Some(locktime) = self.required_time_locktime {
+                pairs.push(Pair::new(
+                    key_type::REQUIRED_TIME_LOCKTIME,
+                    vec![],
+                    locktime.to_le_bytes().to_vec(),
+                ));
+            }
+            if let Some(locktime) = self.required_height_locktime {
+                pairs.push(Pair::new(
+                    key_type::REQUIRED_HEIGHT_LOCKTIME,
+                    vec![],
+                    locktime.to_le_bytes().to_vec(),
+                ));
+            }
+        }
+        if let Some(tx) = &self.non_witness_utxo {
+            pairs.push(Pair::new(
+                key_type::NON_WITNESS_UTXO,
+                vec![],
+                tx.serialize(),
+            ));
+        }
+        if let Some(txout) = &self.witness_utxo {
+            pairs.push(Pair::new(key_type::WITNESS_UTXO, vec![], txout.serialize()));
+        }
+        for (pubkey, sig) in &self.partial_sigs {
+            pairs.push(Pair::new(
+                key_type::PARTIAL_SIG,
+                pubkey.clone(),
+                sig.clone(),
+            ));
+        }
+        if let Some(sighash) = self.sighash_type {
+            pairs.push(Pair::new(
+                key_type::SIGHASH_TYPE,
+                vec![],
+                sighash.to_le_bytes().to_vec(),
+            ));
+        }
+        if let Some(script) = &self.redeem_script {
+            pairs.push(Pair::new(key_type::REDEEM_SCRIPT, vec![], script.0.clone()));
+        }
+        if let Some(script) = &self.witness_script {
+            pairs.push(Pair::new(
+                key_type::WITNESS_SCRIPT,
+                vec![],
+                script.0.clone(),
+            ));
+        }
+        for (pubkey, source) in &self.bip32_derivation {
+            pairs.push(Pair::new(
+                key_type::BIP32_DERIVATION,
+                pubkey.clone(),
+                source.serialize(),
+            ));
+        }
+        if let Some(script) = &self.final_script_sig {
+            pairs.push(Pair::new(
+                key_type::FINAL_SCRIPTSIG,
+                vec![],
+                script.0.clone(),
+            ));
+        }
+        if let Some(witness) = &self.final_script_witness {
+            let mut value = Vec::new();
+            encode_witness(&mut value, witness);
+            pairs.push(Pair::new(key_type::FINAL_SCRIPTWITNESS, vec![], value));
+        }
+        for (hash, preimage) in &self.ripemd160_preimages {
+            pairs.push(Pair::new(
+                key_type::RIPEMD160,
+                hash.to_vec(),
+                preimage.clone(),
+            ));
+        }
+        for (hash, preimage) in &self.sha256_preimages {
+            pairs.push(Pair::new(key_type::SHA256, hash.to_vec(), preimage.clone()));
+        }
+        for (hash, preimage) in &self.hash160_preimages {
+            pairs.push(Pair::new(
+                key_type::HASH160,
+                hash.to_vec(),
+                preimage.clone(),
+            ));
+        }
+        for (hash, preimage) in &self.hash256_preimages {
+            pairs.push(Pair::new(
+                key_type::HASH256,
+                hash.to_vec(),
+                preimage.clone(),
+            ));
+        }
+        if let Some(sig) = &self.tap_key_sig {
+            pairs.push(Pair::new(key_type::TAP_KEY_SIG, vec![], sig.clone()));
+        }
+        for ((xonly, leaf_hash), sig) in &self.tap_script_sigs {
+            let key = [xonly.as_slice(), leaf_hash.as_slice()].concat();
+            pairs.push(Pair::new(key_type::TAP_SCRIPT_SIG, key, sig.clone()));
+        }
+        for (control_block, (script, leaf_version)) in &self.tap_scripts {
+            let mut value = script.0.clone();
+            value.push(*leaf_version);
+            pairs.push(Pair::new(
+                key_type::TAP_LEAF_SCRIPT,
+                control_block.clone(),
+                value,
+            ));
+        }
+        for (xonly, origin) in &self.tap_key_origins {
+            let value = encode_tap_key_origin(origin);
+            pairs.push(Pair::new(
+                key_type::TAP_BIP32_DERIVATION,
+                xonly.to_vec(),
+                value,
+            ));
+        }
+        if let Some(key) = &self.tap_internal_key {
+            pairs.push(Pair::new(key_type::TAP_INTERNAL_KEY, vec![], key.to_vec()));
+        }
+        if let Some(root) = &self.tap_merkle_root {
+            pairs.push(Pair::new(key_type::TAP_MERKLE_ROOT, vec![], root.to_vec()));
+        }
+        for (key, value) in &self.proprietary {
+            pairs.push(Pair {
+                key: key.to_raw(key_type::PROPRIETARY),
+                value: value.clone(),
+            });
+        }
+        for (key, value) in &self.unknown {
+            pairs.push(Pair {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+
+        self.key_order.apply(&mut pairs);
+        pairs
+    }
+}
+
+/// Locktimes below this value are block heights, the rest are timestamps.
+pub(crate) const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// The transaction input fields carried by a version 2 input map.
+#[derive(Default)]
+struct TxInFields {
+    previous_txid: Option<[u8; 32]>,
+    output_index: Option<u32>,
+    sequence: Option<u32>,
+}
+
+impl TxInFields {
+    /// Takes the pair if it is one of the fields, returning whether it was.
+    fn insert_pair(&mut self, pair: &Pair) -> Result<bool, Error> {
+        let raw = &pair.key;
+        match raw.type_value {
+            key_type::PREVIOUS_TXID => {
+                expect_empty_key(pair)?;
+                set_unique(&mut self.previous_txid, value_array(pair)?, raw)?;
+            }
+            key_type::OUTPUT_INDEX => {
+                expect_empty_key(pair)?;
+                let vout = u32::from_le_bytes(value_array(pair)?);
+                set_unique(&mut self.output_index, vout, raw)?;
+            }
+            key_type::SEQUENCE => {
+                expect_empty_key(pair)?;
+                let sequence = u32::from_le_bytes(value_array(pair)?);
+                set_unique(&mut self.sequence, sequence, raw)?;
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn check_schnorr_sig(pair: &Pair) -> Result<(), Error> {
+    match pair.value.len() {
+        64 | 65 => Ok(()),
+        _ => Err(Error::invalid_value(
+            &pair.key,
+            "expected a 64 or 65 byte signature",
+        )),
+    }
+}
+
+/// Decodes the leaf hashes and key source of a taproot key.
+pub(crate) fn decode_tap_key_origin(
+    reader: &mut Reader,
+) -> Result<(Vec<[u8; 32]>, KeySource), crate::encode::Error> {
+    let count = reader.read_length()?;
+    let mut leaf_hashes = Vec::with_capacity(count);
+    for _ in 0..count {
+        leaf_hashes.push(reader.read_array()?);
+    }
+    let source = KeySource::decode(reader)?;
+    Ok((leaf_hashes, source))
+}
+
+pub(crate) fn encode_tap_key_origin((leaf_hashes, source): &(Vec<[u8; 32]>, KeySource)) -> Vec<u8> {
+    let mut value = Vec::new();
+    write_compact_size(&mut value, leaf_hashes.len() as u64);
+    for hash in leaf_hashes {
+        value.extend_from_slice(hash);
+    }
+    source.encode(&mut value);
+    value
+}
@@ -0,0 +1,523 @@
+use crate::psbt::input::LOCKTIME_THRESHOLD;
+use crate::psbt::raw::{global as key_type, RawKey};
+use crate::psbt::{
+    input_sighashes, modifiable, Conflict, Error, MapLocation, PartiallySignedTransaction,
+    SIGHASH_SINGLE,
+};
+use crate::transaction::Transaction;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// How the inputs and outputs of a joined PSBT are ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinOrder {
+    /// Shuffle inputs and outputs so their order does not reveal which
+    /// participant contributed them.
+    #[default]
+    Random,
+    /// Sort inputs and outputs as BIP69 specifies.
+    Bip69,
+    /// Keep the contributions' inputs and outputs in the order given.
+    Preserve,
+}
+
+/// Joins the inputs and outputs of several PSBTs into one.
+///
+/// The joined PSBT is version 2 if every contribution is, and version 0
+/// otherwise. Its transaction version is the highest of the contributions and
+/// its locktime the highest they ask for, which fails if some ask for a block
+/// height and others for a time. Inputs spent by more than one contribution
+/// are rejected, as are contributions whose signatures or modifiable flags
+/// forbid adding inputs and outputs.
+pub fn join(
+    psbts: &[PartiallySignedTransaction],
+    order: JoinOrder,
+) -> Result<PartiallySignedTransaction, Error> {
+    if psbts.is_empty() {
+        return Err(Error::NothingToJoin);
+    }
+
+    let version = if psbts.iter().all(|psbt| psbt.version >= 2) {
+        2
+    } else {
+        0
+    };
+    let psbts = psbts
+        .iter()
+        .map(|psbt| match version {
+            0 => psbt.clone().into_v0(),
+            _ => Ok(psbt.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut prevouts = HashSet::new();
+    for psbt in &psbts {
+        if !psbt.inputs_modifiable() {
+            return Err(Error::InputsNotModifiable);
+        }
+        if !psbt.outputs_modifiable() {
+            return Err(Error::OutputsNotModifiable);
+        }
+        for txin in &psbt.unsigned_tx.input {
+            if !prevouts.insert(txin.previous_output) {
+                return Err(Error::DuplicateInput(txin.previous_output));
+            }
+        }
+    }
+
+    let tx_version = psbts
+        .iter()
+        .map(|psbt| psbt.unsigned_tx.version)
+        .max()
+        .unwrap_or(2);
+    let mut joined = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+        version: tx_version,
+        lock_time: 0,
+        input: Vec::new(),
+        output: Vec::new(),
+    })?;
+    joined.version = version;
+    if version >= 2 {
+        joined.tx_modifiable = Some(modifiable::INPUTS | modifiable::OUTPUTS);
+        let fallbacks: Vec<u32> = psbts
+            .iter()
+            .filter_map(|psbt| psbt.fallback_locktime)
+            .collect();
+        if !fallbacks.is_empty() {
+            joined.fallback_locktime = Some(max_lock_time(fallbacks)?);
+        }
+    } else {
+        joined.unsigned_tx.lock_time =
+            max_lock_time(psbts.iter().map(|psbt| psbt.unsigned_tx.lock_time))?;
+    }
+    merge_globals(&mut joined, &psbts)?;
+
+    // Each entry is (contribution, index within it).
+    let mut inputs: Vec<(usize, usize)> = positions(&psbts, |psbt| psbt.inputs.len());
+    let mut outputs: Vec<(usize, usize)> = positions(&psbts, |psbt| psbt.outputs.len());
+    match order {
+        JoinOrder::Random => {
+            let mut rng = rand::thread_rng();
+            inputs.shuffle(&mut rng);
+            outputs.shuffle(&mut rng);
+        }
+        JoinOrder::Bip69 => {
+            inputs.sort_by_key(|&(c, i)| {
+                let outpoint = psbts[c].unsigned_tx.input[i].previous_output;
+                let mut txid = outpoint.txid.0;
+                txid.reverse();
+                (txid, outpoint.vout)
+            });
+            outputs.sort_by_key(|&(c, i)| {
+                let txout = &psbts[c].unsigned_tx.output[i];
+                (txout.value, txout.script_pubkey.as_bytes().to_vec())
+            });
+        }
+        JoinOrder::Preserve => {}
+    }
+
+    let mut has_sighash_single = false;
+    for (position, &(c, i)) in inputs.iter().enumerate() {
+        if input_sighashes(&psbts[c].inputs[i]).any(|sighash| sighash & 0x1f == SIGHASH_SINGLE) {
+            has_sighash_single = true;
+            if outputs.get(position) != Some(&(c, i)) {
+                return Err(Error::SighashSingleReordered(
+                    psbts[c].unsigned_tx.input[i].previous_output,
+                ));
+            }
+        }
+    }
+
+    // Outputs go first: a version 0 PSBT stops accepting outputs once it has
+    // inputs signed with SIGHASH_ALL | SIGHASH_ANYONECANPAY.
+    for &(c, i) in &outputs {
+        let psbt = &psbts[c];
+        joined.add_output(psbt.unsigned_tx.output[i].clone(), psbt.outputs[i].clone())?;
+    }
+    for &(c, i) in &inputs {
+        let psbt = &psbts[c];
+        joined.add_input(psbt.unsigned_tx.input[i].clone(), psbt.inputs[i].clone())?;
+    }
+    if has_sighash_single {
+        joined.tx_modifiable = joined
+            .tx_modifiable
+            .map(|flags| flags | modifiable::HAS_SIGHASH_SINGLE);
+    }
+
+    let signed_fields_changed = psbts.iter().any(|psbt| {
+        psbt.signature_sighashes().next().is_some()
+            && (psbt.unsigned_tx.version != joined.unsigned_tx.version
+                || psbt.unsigned_tx.lock_time != joined.unsigned_tx.lock_time)
+    });
+    if signed_fields_changed {
+        return Err(Error::SignedFieldsChanged);
+    }
+
+    Ok(joined)
+}
+
+fn positions(
+    psbts: &[PartiallySignedTransaction],
+    len: impl Fn(&PartiallySignedTransaction) -> usize,
+) -> Vec<(usize, usize)> {
+    psbts
+        .iter()
+        .enumerate()
+        .flat_map(|(c, psbt)| (0..len(psbt)).map(move |i| (c, i)))
+        .collect()
+}
+
+/// The highest of the locktimes, ignoring zero. Heights and times cannot be
+/// mixed.
+fn max_lock_time(lock_times: impl IntoIterator<Item = u32>) -> Result<u32, Error> {
+    let lock_times: Vec<u32> = lock_times.into_iter().filter(|&lt| lt != 0).collect();
+    let heights = lock_times
+        .iter()
+        .filter(|&&lt| lt < LOCKTIME_THRESHOLD)
+        .count();
+    if heights != 0 && heights != lock_times.len() {
+        return Err(Error::LockTimeConflict);
+    }
+    Ok(lock_times.into_iter().max().unwrap_or(0))
+}
+
+/// Copies the xpubs, proprietary and unknown global pairs of every
+/// contribution, reporting keys that have different values.
+fn merge_globals(
+    joined: &mut PartiallySignedTransaction,
+    psbts: &[PartiallySignedTransaction],
+) -> Result<(), Error> {
+    fn merge<K: Ord + Clone, V: PartialEq + Clone>(
+        merged: &mut BTreeMap<K, V>,
+        other: &BTreeMap<K, V>,
+        raw: impl Fn(&K) -> RawKey,
+        conflicts: &mut Vec<Conflict>,
+    ) {
+        for (key, value) in other {
+            match merged.get(key) {
+                Some(existing) if existing != value => conflicts.push(Conflict {
+                    location: MapLocation::Global,
+                    key: raw(key),
+                }),
+                Some(_) => {}
+                None => {
+                    merged.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    let mut conflicts = Vec::new();
+    for psbt in psbts {
+        merge(
+            &mut joined.xpubs,
+            &psbt.xpubs,
+            |xpub| RawKey::new(key_type::XPUB, xpub.clone()),
+            &mut conflicts,
+        );
+        merge(
+            &mut joined.proprietary,
+            &psbt.proprietary,
+            |key| key.to_raw(key_type::PROPRIETARY),
+            &mut conflicts,
+        );
+        merge(
+            &mut joined.unknown,
+            &psbt.unknown,
+            RawKey::clone,
+            &mut conflicts,
+        );
+    }
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Conflicts(conflicts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::transaction::{OutPoint, Script, TxIn, TxOut};
+
+    fn outpoint(txid: &str, vout: u32) -> OutPoint {
+        OutPoint::new(txid.parse().unwrap(), vout)
+    }
+
+    fn txout(sat: u64, script: &str) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(sat),
+            script_pubkey: Script(hex::decode(script).unwrap()),
+        }
+    }
+
+    fn contribution(
+        version: i32,
+        lock_time: u32,
+        inputs: &[OutPoint],
+        outputs: &[TxOut],
+    ) -> PartiallySignedTransaction {
+        PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version,
+            lock_time,
+            input: inputs
+                .iter()
+                .map(|&previous_output| TxIn {
+                    previous_output,
+                    script_sig: Script::default(),
+                    sequence: 0xffff_ffff,
+                    witness: Vec::new(),
+                })
+                .collect(),
+            output: outputs.to_vec(),
+        })
+        .unwrap()
+    }
+
+    fn single(byte: u8, lock_time: u32) -> PartiallySignedTransaction {
+        let txid = hex::encode([byte; 32]);
+        contribution(
+            2,
+            lock_time,
+            &[outpoint(&txid, 0)],
+            &[txout(
+                10_000 * byte as u64,
+                "0014aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            )],
+        )
+    }
+
+    fn prevouts(psbt: &PartiallySignedTransaction) -> Vec<OutPoint> {
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect()
+    }
+
+    #[test]
+    fn inputs_spent_twice_are_rejected() {
+        let first = single(1, 0);
+        let mut second = single(2, 0);
+        second.unsigned_tx.input[0].previous_output = first.unsigned_tx.input[0].previous_output;
+        assert_eq!(
+            join(&[first.clone(), second], JoinOrder::Preserve),
+            Err(Error::DuplicateInput(
+                first.unsigned_tx.input[0].previous_output
+            ))
+        );
+        assert_eq!(join(&[], JoinOrder::Preserve), Err(Error::NothingToJoin));
+    }
+
+    #[test]
+    fn version_and_locktime_are_the_highest_asked_for() {
+        let mut old = single(1, 100);
+        old.unsigned_tx.version = 1;
+        let joined = join(&[old, single(2, 0), single(3, 200)], JoinOrder::Preserve).unwrap();
+        assert_eq!(joined.version, 0);
+        assert_eq!(joined.unsigned_tx.version, 2);
+        assert_eq!(joined.unsigned_tx.lock_time, 200);
+
+        assert_eq!(
+            join(
+                &[single(1, 100), single(2, 500_000_000)],
+                JoinOrder::Preserve
+            ),
+            Err(Error::LockTimeConflict)
+        );
+    }
+
+    #[test]
+    fn version_2_contributions_join_their_fallback_locktimes() {
+        let v2 = |byte, lock_time| single(byte, lock_time).into_v2().unwrap();
+        let joined = join(&[v2(1, 300), v2(2, 0), v2(3, 200)], JoinOrder::Preserve).unwrap();
+        assert_eq!(joined.version, 2);
+        assert_eq!(joined.fallback_locktime, Some(300));
+        assert_eq!(joined.compute_lock_time(), Ok(300));
+
+        // A single version 0 contribution makes the joined PSBT version 0.
+        let joined = join(&[v2(1, 300), single(2, 0)], JoinOrder::Preserve).unwrap();
+        assert_eq!(joined.version, 0);
+        assert_eq!(joined.unsigned_tx.lock_time, 300);
+    }
+
+    #[test]
+    fn preserve_keeps_the_contributions_in_order() {
+        let contributions = [single(3, 0), single(1, 0), single(2, 0)];
+        let joined = join(&contributions, JoinOrder::Preserve).unwrap();
+        let expected: Vec<OutPoint> = contributions.iter().flat_map(prevouts).collect();
+        assert_eq!(prevouts(&joined), expected);
+        let values: Vec<u64> = joined
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.value.to_sat())
+            .collect();
+        assert_eq!(values, [30_000, 10_000, 20_000]);
+    }
+
+    #[test]
+    fn random_keeps_every_input_and_output() {
+        let contributions: Vec<_> = (1..=8).map(|byte| single(byte, 0)).collect();
+        let joined = join(&contributions, JoinOrder::Random).unwrap();
+
+        let mut expected: Vec<OutPoint> = contributions.iter().flat_map(prevouts).collect();
+        let mut found = prevouts(&joined);
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected);
+        assert_eq!(joined.inputs.len(), 8);
+        assert_eq!(joined.outputs.len(), 8);
+
+        // Inputs keep their own PSBT fields wherever they end up.
+        for (txin, input) in joined.unsigned_tx.input.iter().zip(&joined.inputs) {
+            let (c, i) = contributions
+                .iter()
+                .enumerate()
+                .find_map(|(c, psbt)| {
+                    prevouts(psbt)
+                        .iter()
+                        .position(|&prevout| prevout == txin.previous_output)
+                        .map(|i| (c, i))
+                })
+                .unwrap();
+            assert_eq!(input, &contributions[c].inputs[i]);
+        }
+    }
+
+    /// The first example transaction of BIP69
+    /// (0a6a357e2f7796444e02638749d9611c008b253fb55f5dc88b739b230ed0c4c3),
+    /// with its inputs listed in their sorted order.
+    const BIP69_INPUTS: [(&str, u32); 17] = [
+        (
+            "0e53ec5dfb2cb8a71fec32dc9a634a35b7e24799295ddd5278217822e0b31f57",
+            0,
+        ),
+        (
+            "26aa6e6d8b9e49bb0630aac301db6757c02e3619feb4ee0eea81eb1672947024",
+            1,
+        ),
+        (
+            "28e0fdd185542f2c6ea19030b0796051e7772b6026dd5ddccd7a2f93b73e6fc2",
+            0,
+        ),
+        (
+            "381de9b9ae1a94d9c17f6a08ef9d341a5ce29e2e60c36a52d333ff6203e58d5d",
+            1,
+        ),
+        (
+            "3b8b2f8efceb60ba78ca8bba206a137f14cb5ea4035e761ee204302d46b98de2",
+            0,
+        ),
+        (
+            "402b2c02411720bf409eff60d05adad684f135838962823f3614cc657dd7bc0a",
+            1,
+        ),
+        (
+            "54ffff182965ed0957dba1239c27164ace5a73c9b62a660c74b7b7f15ff61e7a",
+            1,
+        ),
+        (
+            "643e5f4e66373a57251fb173151e838ccd27d279aca882997e005016bb53d5aa",
+            0,
+        ),
+        (
+            "6c1d56f31b2de4bfc6aaea28396b333102b1f600da9c6d6149e96ca43f1102b1",
+            1,
+        ),
+        (
+            "7a1de137cbafb5c70405455c49c5104ca3057a1f1243e6563bb9245c9c88c191",
+            0,
+        ),
+        (
+            "7d037ceb2ee0dc03e82f17be7935d238b35d1deabf953a892a4507bfbeeb3ba4",
+            1,
+        ),
+        (
+            "a5e899dddb28776ea9ddac0a502316d53a4a3fca607c72f66c470e0412e34086",
+            0,
+        ),
+        (
+            "b4112b8f900a7ca0c8b0e7c4dfad35c6be5f6be46b3458974988e1cdb2fa61b8",
+            0,
+        ),
+        (
+            "bafd65e3c7f3f9fdfdc1ddb026131b278c3be1af90a4a6ffa78c4658f9ec0c85",
+            0,
+        ),
+        (
+            "de0411a1e97484a2804ff1dbde260ac19de841bebad1880c782941aca883b4e9",
+            1,
+        ),
+        (
+            "f0a130a84912d03c1d284974f563c5949ac13f8342b8112edff52971599e6a45",
+            0,
+        ),
+        (
+            "f320832a9d2e2452af63154bc687493484a0e7745ebd3aaf9ca19eb80834ad60",
+            0,
+        ),
+    ];
+
+    #[test]
+    fn bip69_sorts_the_bip_example() {
+        let sorted: Vec<OutPoint> = BIP69_INPUTS
+            .iter()
+            .map(|&(txid, vout)| outpoint(txid, vout))
+            .collect();
+        let small = txout(
+            400_057_456,
+            "76a9144a5fba237213a062f6f57978f796390bdcf8d01588ac",
+        );
+        let large = txout(
+            40_000_000_000,
+            "76a9145be32612930b8323add2212a4ec03c1562084f8488ac",
+        );
+
+        // Split the inputs over three contributions, out of order.
+        let mut scrambled = sorted.clone();
+        scrambled.reverse();
+        scrambled.swap(2, 9);
+        let contributions = [
+            contribution(1, 0, &scrambled[..6], std::slice::from_ref(&large)),
+            contribution(1, 0, &scrambled[6..11], &[]),
+            contribution(1, 0, &scrambled[11..], std::slice::from_ref(&small)),
+        ];
+
+        let joined = join(&contributions, JoinOrder::Bip69).unwrap();
+        assert_eq!(prevouts(&joined), sorted);
+        assert_eq!(joined.unsigned_tx.output, vec![small, large]);
+    }
+
+    #[test]
+    fn bip69_breaks_ties_on_vout_and_script() {
+        let txid = hex::encode([7; 32]);
+        let contributions = [
+            contribution(
+                2,
+                0,
+                &[outpoint(&txid, 1)],
+                &[txout(1_000, "0014bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb")],
+            ),
+            contribution(
+                2,
+                0,
+                &[outpoint(&txid, 0)],
+                &[txout(1_000, "0014aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa")],
+            ),
+        ];
+        let joined = join(&contributions, JoinOrder::Bip69).unwrap();
+        assert_eq!(prevouts(&joined), [outpoint(&txid, 0), outpoint(&txid, 1)]);
+        assert_eq!(
+            joined.unsigned_tx.output,
+            [
+                txout(1_000, "0014aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"),
+                txout(1_000, "0014bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"),
+            ]
+        );
+    }
+}
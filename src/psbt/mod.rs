@@ -0,0 +1,988 @@
+mod combine;
+mod error;
+mod finalize;
+mod input;
+mod join;
+mod output;
+pub mod raw;
+
+pub use combine::{combine, Conflict, MapLocation};
+pub use error::Error;
+pub use finalize::{Incomplete, Missing};
+pub use input::Input;
+pub use join::{join, JoinOrder};
+pub use output::{Output, TapLeaf};
+pub use raw::{KeySource, Pair, ProprietaryKey, RawKey};
+
+use crate::encode::{write_compact_size, Reader};
+use crate::transaction::{Transaction, TxIn, TxOut};
+use raw::{global as key_type, insert_unique, set_unique, value_array, KeyOrder};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The magic bytes every serialized PSBT starts with.
+pub const MAGIC: &[u8; 5] = b"psbt\xff";
+
+/// Bits of `PSBT_GLOBAL_TX_MODIFIABLE` (BIP370).
+pub mod modifiable {
+    /// Inputs may be added or removed.
+    pub const INPUTS: u8 = 0x01;
+    /// Outputs may be added or removed.
+    pub const OUTPUTS: u8 = 0x02;
+    /// Some input is signed with `SIGHASH_SINGLE`, so its output must keep its
+    /// index.
+    pub const HAS_SIGHASH_SINGLE: u8 = 0x04;
+}
+
+/// A PSBT in the BIP174 data model, in version 0 or version 2 (BIP370).
+///
+/// Parsing and serializing round-trips exactly: known keys are decoded into
+/// typed fields, unknown keys are kept as raw pairs, and keys are written back
+/// in the order they were read.
+///
+/// Both versions keep the transaction being built in `unsigned_tx`. A version 2
+/// PSBT serializes it as per-input and per-output fields instead of a global
+/// transaction, and its `lock_time` is derived from `fallback_locktime` and the
+/// inputs' required locktimes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartiallySignedTransaction {
+    /// The transaction being built, without any signatures.
+    pub unsigned_tx: Transaction,
+    /// The PSBT version, 0 or 2.
+    pub version: u32,
+    /// Extended public keys and their sources, keyed by the 78-byte
+    /// serialized xpub.
+    pub xpubs: BTreeMap<Vec<u8>, KeySource>,
+    /// The locktime used when no input requires one (version 2).
+    pub fallback_locktime: Option<u32>,
+    /// The [`modifiable`] flags (version 2).
+    pub tx_modifiable: Option<u8>,
+    pub proprietary: BTreeMap<ProprietaryKey, Vec<u8>>,
+    /// Pairs with key types this crate does not know, kept as they were read.
+    pub unknown: BTreeMap<RawKey, Vec<u8>>,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+    key_order: KeyOrder,
+}
+
+impl PartiallySignedTransaction {
+    /// Creates an empty version 0 PSBT for `unsigned_tx`, which must not have
+    /// any scriptSigs or witnesses.
+    pub fn from_unsigned_tx(unsigned_tx: Transaction) -> Result<Self, Error> {
+        check_unsigned_tx(&unsigned_tx)?;
+        Ok(PartiallySignedTransaction {
+            inputs: vec![Input::default(); unsigned_tx.input.len()],
+            outputs: vec![Output::default(); unsigned_tx.output.len()],
+            unsigned_tx,
+            version: 0,
+            xpubs: BTreeMap::new(),
+            fallback_locktime: None,
+            tx_modifiable: None,
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            key_order: KeyOrder::default(),
+        })
+    }
+
+    /// Parses a binary PSBT.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        if reader.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(Error::InvalidMagic);
+        }
+
+        let mut global = GlobalFields::default();
+        let mut xpubs = BTreeMap::new();
+        let mut proprietary = BTreeMap::new();
+        let mut unknown = BTreeMap::new();
+        let mut key_order = KeyOrder::default();
+
+        while let Some(pair) = Pair::decode(&mut reader)? {
+            key_order.push(pair.key.clone());
+            let raw = &pair.key;
+            match raw.type_value {
+                key_type::XPUB => {
+                    if raw.key.len() != 78 {
+                        return Err(Error::InvalidKey(raw.clone()));
+                    }
+                    let source = raw::decode_value(&pair, KeySource::decode)?;
+                    insert_unique(&mut xpubs, raw.key.clone(), source, raw)?;
+                }
+                key_type::PROPRIETARY => {
+                    let key = ProprietaryKey::decode(raw)?;
+                    insert_unique(&mut proprietary, key, pair.value.clone(), raw)?;
+                }
+                _ => {
+                    if !global.insert_pair(&pair)? {
+                        insert_unique(&mut unknown, raw.clone(), pair.value.clone(), raw)?;
+                    }
+                }
+            }
+        }
+
+        let version = global.version.unwrap_or(0);
+        let (mut unsigned_tx, input_count, output_count) = match version {
+            0 => {
+                global.check_v0()?;
+                let tx = global.unsigned_tx.ok_or(Error::MissingUnsignedTx)?;
+                check_unsigned_tx(&tx)?;
+                let counts = (tx.input.len(), tx.output.len());
+                (tx, counts.0, counts.1)
+            }
+            2 => global.v2_template()?,
+            version => return Err(Error::UnsupportedVersion(version)),
+        };
+
+        let mut inputs = Vec::with_capacity(input_count.min(reader.remaining()));
+        for _ in 0..input_count {
+            if reader.is_empty() {
+                return Err(Error::InputCountMismatch {
+                    expected: input_count,
+                    found: inputs.len(),
+                });
+            }
+            let (input, txin) = Input::decode(&mut reader, version)?;
+            unsigned_tx.input.extend(txin);
+            inputs.push(input);
+        }
+
+        let mut outputs = Vec::with_capacity(output_count.min(reader.remaining()));
+        for _ in 0..output_count {
+            if reader.is_empty() {
+                return Err(Error::OutputCountMismatch {
+                    expected: output_count,
+                    found: outputs.len(),
+                });
+            }
+            let (output, txout) = Output::decode(&mut reader, version)?;
+            unsigned_tx.output.extend(txout);
+            outputs.push(output);
+        }
+        reader.finish()?;
+
+        let mut psbt = PartiallySignedTransaction {
+            unsigned_tx,
+            version,
+            xpubs,
+            fallback_locktime: global.fallback_locktime,
+            tx_modifiable: global.tx_modifiable,
+            proprietary,
+            unknown,
+            inputs,
+            outputs,
+            key_order,
+        };
+        if version >= 2 {
+            psbt.unsigned_tx.lock_time = psbt.compute_lock_time()?;
+        }
+        Ok(psbt)
+    }
+
+    /// Serializes the PSBT to its binary form.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for pair in self.global_pairs() {
+            pair.encode(&mut out);
+        }
+        out.push(0x00);
+
+        for (input, txin) in self.inputs.iter().zip(&self.unsigned_tx.input) {
+            for pair in input.pairs(self.version, txin) {
+                pair.encode(&mut out);
+            }
+            out.push(0x00);
+        }
+        for (output, txout) in self.outputs.iter().zip(&self.unsigned_tx.output) {
+            for pair in output.pairs(self.version, txout) {
+                pair.encode(&mut out);
+            }
+            out.push(0x00);
+        }
+        out
+    }
+
+    /// Parses a base64-encoded PSBT, as used by Bitcoin Core's RPCs.
+    pub fn from_base64(psbt: &str) -> Result<Self, Error> {
+        let bytes = base64::decode(psbt.trim()).map_err(|_| Error::InvalidBase64)?;
+        PartiallySignedTransaction::deserialize(&bytes)
+    }
+
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.serialize())
+    }
+
+    /// The global map's key-value pairs, in the order they are serialized.
+    pub fn global_pairs(&self) -> Vec<Pair> {
+        let mut pairs = Vec::new();
+        if self.version >= 2 {
+            let tx = &self.unsigned_tx;
+            pairs.push(Pair::new(
+                key_type::TX_VERSION,
+                vec![],
+                tx.version.to_le_bytes().to_vec(),
+            ));
+            if let Some(locktime) = self.fallback_locktime {
+                pairs.push(Pair::new(
+                    key_type::FALLBACK_LOCKTIME,
+                    vec![],
+                    locktime.to_le_bytes().to_vec(),
+                ));
+            }
+            pairs.push(Pair::new(
+                key_type::INPUT_COUNT,
+                vec![],
+                compact_size(tx.input.len()),
+            ));
+            pairs.push(Pair::new(
+                key_type::OUTPUT_COUNT,
+                vec![],
+                compact_size(tx.output.len()),
+            ));
+            if let Some(flags) = self.tx_modifiable {
+                pairs.push(Pair::new(key_type::TX_MODIFIABLE, vec![], vec![flags]));
+            }
+        } else {
+            pairs.push(Pair::new(
+                key_type::UNSIGNED_TX,
+                vec![],
+                self.unsigned_tx.serialize_no_witness(),
+            ));
+        }
+        for (xpub, source) in &self.xpubs {
+            pairs.push(Pair::new(key_type::XPUB, xpub.clone(), source.serialize()));
+        }
+        if self.version != 0 || self.key_order.contains(key_type::VERSION) {
+            pairs.push(Pair::new(
+                key_type::VERSION,
+                vec![],
+                self.version.to_le_bytes().to_vec(),
+            ));
+        }
+        for (key, value) in &self.proprietary {
+            pairs.push(Pair {
+                key: key.to_raw(key_type::PROPRIETARY),
+                value: value.clone(),
+            });
+        }
+        for (key, value) in &self.unknown {
+            pairs.push(Pair {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+
+        self.key_order.apply(&mut pairs);
+        pairs
+    }
+
+    /// Converts a version 0 PSBT to version 2, keeping the transaction's
+    /// locktime as the fallback locktime and deriving the modifiable flags from
+    /// the signatures already present.
+    pub fn into_v2(mut self) -> Result<Self, Error> {
+        if self.version >= 2 {
+            return Ok(self);
+        }
+        if self.unsigned_tx.version < 2 {
+            return Err(Error::InvalidTxVersion(self.unsigned_tx.version));
+        }
+        let mut flags = 0;
+        if self.inputs_modifiable() {
+            flags |= modifiable::INPUTS;
+        }
+        if self.outputs_modifiable() {
+            flags |= modifiable::OUTPUTS;
+        }
+        if self
+            .signature_sighashes()
+            .any(|sighash| sighash & 0x1f == SIGHASH_SINGLE)
+        {
+            flags |= modifiable::HAS_SIGHASH_SINGLE;
+        }
+        self.version = 2;
+        self.tx_modifiable = Some(flags);
+        self.fallback_locktime = Some(self.unsigned_tx.lock_time);
+        Ok(self)
+    }
+
+    /// Converts a version 2 PSBT to version 0, fixing the transaction's
+    /// locktime and dropping the fields version 0 cannot carry, including the
+    /// version field itself.
+    pub fn into_v0(mut self) -> Result<Self, Error> {
+        if self.version == 0 {
+            return Ok(self);
+        }
+        self.unsigned_tx.lock_time = self.compute_lock_time()?;
+        self.version = 0;
+        self.key_order.remove(key_type::VERSION);
+        self.fallback_locktime = None;
+        self.tx_modifiable = None;
+        for input in &mut self.inputs {
+            input.required_time_locktime = None;
+            input.required_height_locktime = None;
+        }
+        Ok(self)
+    }
+
+    /// Determines the transaction's locktime as BIP370 specifies: the largest
+    /// required locktime of the inputs, preferring heights when every input
+    /// with a requirement allows one, or the fallback locktime if none do.
+    pub fn compute_lock_time(&self) -> Result<u32, Error> {
+        if self.version < 2 {
+            return Ok(self.unsigned_tx.lock_time);
+        }
+
+        let constrained: Vec<&Input> = self
+            .inputs
+            .iter()
+            .filter(|input| {
+                input.required_time_locktime.is_some() || input.required_height_locktime.is_some()
+            })
+            .collect();
+        if constrained.is_empty() {
+            return Ok(self.fallback_locktime.unwrap_or(0));
+        }
+
+        if constrained
+            .iter()
+            .all(|input| input.required_height_locktime.is_some())
+        {
+            return Ok(constrained
+                .iter()
+                .filter_map(|input| input.required_height_locktime)
+                .max()
+                .unwrap_or(0));
+        }
+        if constrained
+            .iter()
+            .all(|input| input.required_time_locktime.is_some())
+        {
+            return Ok(constrained
+                .iter()
+                .filter_map(|input| input.required_time_locktime)
+                .max()
+                .unwrap_or(0));
+        }
+        Err(Error::LockTimeConflict)
+    }
+
+    /// Whether inputs can be added. Version 2 PSBTs follow their
+    /// `PSBT_GLOBAL_TX_MODIFIABLE` flags; for version 0 every signature present
+    /// must use `SIGHASH_ANYONECANPAY`.
+    pub fn inputs_modifiable(&self) -> bool {
+        if self.version >= 2 {
+            return self.tx_modifiable.unwrap_or(0) & modifiable::INPUTS != 0;
+        }
+        self.signature_sighashes()
+            .all(|sighash| sighash & SIGHASH_ANYONECANPAY != 0)
+    }
+
+    /// Whether outputs can be added. Version 2 PSBTs follow their
+    /// `PSBT_GLOBAL_TX_MODIFIABLE` flags; for version 0 every signature present
+    /// must use `SIGHASH_NONE` or `SIGHASH_SINGLE`.
+    pub fn outputs_modifiable(&self) -> bool {
+        if self.version >= 2 {
+            return self.tx_modifiable.unwrap_or(0) & modifiable::OUTPUTS != 0;
+        }
+        self.signature_sighashes()
+            .all(|sighash| matches!(sighash & 0x1f, SIGHASH_NONE | SIGHASH_SINGLE))
+    }
+
+    /// Appends an input, failing if the PSBT's inputs are not modifiable or the
+    /// input's locktime requirement conflicts with the other inputs.
+    pub fn add_input(&mut self, txin: TxIn, input: Input) -> Result<(), Error> {
+        if !self.inputs_modifiable() {
+            return Err(Error::InputsNotModifiable);
+        }
+        if !txin.script_sig.is_empty() || !txin.witness.is_empty() {
+            return Err(Error::UnsignedTxHasScripts);
+        }
+
+        self.unsigned_tx.input.push(txin);
+        self.inputs.push(input);
+        if self.version >= 2 {
+            match self.compute_lock_time() {
+                Ok(lock_time) => self.unsigned_tx.lock_time = lock_time,
+                Err(e) => {
+                    self.unsigned_tx.input.pop();
+                    self.inputs.pop();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends an output, failing if the PSBT's outputs are not modifiable.
+    /// Outputs are only ever appended, so outputs paired with `SIGHASH_SINGLE`
+    /// inputs keep their index.
+    pub fn add_output(&mut self, txout: TxOut, output: Output) -> Result<(), Error> {
+        if !self.outputs_modifiable() {
+            return Err(Error::OutputsNotModifiable);
+        }
+        self.unsigned_tx.output.push(txout);
+        self.outputs.push(output);
+        Ok(())
+    }
+
+    /// The sighash types of every signature in the PSBT.
+    fn signature_sighashes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.inputs.iter().flat_map(input_sighashes)
+    }
+}
+
+/// The sighash types of the input's signatures, counting a finalized input
+/// as signed with `SIGHASH_ALL`.
+pub(crate) fn input_sighashes(input: &Input) -> impl Iterator<Item = u8> + '_ {
+    let ecdsa = input
+        .partial_sigs
+        .values()
+        .filter_map(|sig| sig.last().copied());
+    let schnorr = input
+        .tap_key_sig
+        .iter()
+        .chain(input.tap_script_sigs.values())
+        .map(|sig| {
+            if sig.len() == 65 {
+                sig[64]
+            } else {
+                SIGHASH_ALL
+            }
+        });
+    let finalized = input.is_finalized().then_some(SIGHASH_ALL);
+    ecdsa.chain(schnorr).chain(finalized)
+}
+
+const SIGHASH_ALL: u8 = 0x01;
+const SIGHASH_NONE: u8 = 0x02;
+const SIGHASH_SINGLE: u8 = 0x03;
+const SIGHASH_ANYONECANPAY: u8 = 0x80;
+
+/// The global fields that differ between PSBT versions.
+#[derive(Default)]
+struct GlobalFields {
+    unsigned_tx: Option<Transaction>,
+    version: Option<u32>,
+    tx_version: Option<i32>,
+    fallback_locktime: Option<u32>,
+    input_count: Option<u64>,
+    output_count: Option<u64>,
+    tx_modifiable: Option<u8>,
+}
+
+impl GlobalFields {
+    /// Takes the pair if it is one of the fields, returning whether it was.
+    fn insert_pair(&mut self, pair: &Pair) -> Result<bool, Error> {
+        let raw = &pair.key;
+        let known = matches!(
+            raw.type_value,
+            key_type::UNSIGNED_TX
+                | key_type::VERSION
+                | key_type::TX_VERSION
+                | key_type::FALLBACK_LOCKTIME
+                | key_type::INPUT_COUNT
+                | key_type::OUTPUT_COUNT
+                | key_type::TX_MODIFIABLE
+        );
+        if !known {
+            return Ok(false);
+        }
+        raw::expect_empty_key(pair)?;
+
+        match raw.type_value {
+            key_type::UNSIGNED_TX => {
+                let tx = raw::decode_value(pair, |r| Transaction::decode(r, false))?;
+                set_unique(&mut self.unsigned_tx, tx, raw)?;
+            }
+            key_type::VERSION => {
+                set_unique(
+                    &mut self.version,
+                    u32::from_le_bytes(value_array(pair)?),
+                    raw,
+                )?;
+            }
+            key_type::TX_VERSION => {
+                set_unique(
+                    &mut self.tx_version,
+                    i32::from_le_bytes(value_array(pair)?),
+                    raw,
+                )?;
+            }
+            key_type::FALLBACK_LOCKTIME => {
+                let locktime = u32::from_le_bytes(value_array(pair)?);
+                set_unique(&mut self.fallback_locktime, locktime, raw)?;
+            }
+            key_type::INPUT_COUNT => {
+                let count = raw::decode_value(pair, |r| r.read_compact_size())?;
+                set_unique(&mut self.input_count, count, raw)?;
+            }
+            key_type::OUTPUT_COUNT => {
+                let count = raw::decode_value(pair, |r| r.read_compact_size())?;
+                set_unique(&mut self.output_count, count, raw)?;
+            }
+            _ => {
+                let [flags] = value_array(pair)?;
+                set_unique(&mut self.tx_modifiable, flags, raw)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Version 0 PSBTs must not carry any version 2 fields.
+    fn check_v0(&self) -> Result<(), Error> {
+        let v2_fields = [
+            (key_type::TX_VERSION, self.tx_version.is_some()),
+            (
+                key_type::FALLBACK_LOCKTIME,
+                self.fallback_locktime.is_some(),
+            ),
+            (key_type::INPUT_COUNT, self.input_count.is_some()),
+            (key_type::OUTPUT_COUNT, self.output_count.is_some()),
+            (key_type::TX_MODIFIABLE, self.tx_modifiable.is_some()),
+        ];
+        match v2_fields.into_iter().find(|(_, present)| *present) {
+            Some((type_value, _)) => Err(Error::KeyNotAllowed {
+                version: 0,
+                key: RawKey::new(type_value, vec![]),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Builds the transaction skeleton of a version 2 PSBT, whose inputs and
+    /// outputs are filled in from their maps, along with the map counts.
+    fn v2_template(&self) -> Result<(Transaction, usize, usize), Error> {
+        if self.unsigned_tx.is_some() {
+            return Err(Error::KeyNotAllowed {
+                version: 2,
+                key: RawKey::new(key_type::UNSIGNED_TX, vec![]),
+            });
+        }
+        let missing = |type_value| Error::MissingKey {
+            map: "global",
+            type_value,
+        };
+        let version = self
+            .tx_version
+            .ok_or_else(|| missing(key_type::TX_VERSION))?;
+        if version < 2 {
+            return Err(Error::InvalidTxVersion(version));
+        }
+        let input_count = self
+            .input_count
+            .ok_or_else(|| missing(key_type::INPUT_COUNT))?;
+        let output_count = self
+            .output_count
+            .ok_or_else(|| missing(key_type::OUTPUT_COUNT))?;
+
+        let tx = Transaction {
+            version,
+            lock_time: 0,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        let count = |count: u64| {
+            usize::try_from(count)
+                .map_err(|_| Error::Encode(crate::encode::Error::OversizedLength(count)))
+        };
+        Ok((tx, count(input_count)?, count(output_count)?))
+    }
+}
+
+fn compact_size(n: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_compact_size(&mut out, n as u64);
+    out
+}
+
+/// The unsigned transaction must not carry any signature data.
+fn check_unsigned_tx(tx: &Transaction) -> Result<(), Error> {
+    if tx
+        .input
+        .iter()
+        .any(|input| !input.script_sig.is_empty() || !input.witness.is_empty())
+    {
+        return Err(Error::UnsignedTxHasScripts);
+    }
+    Ok(())
+}
+
+/// Formats the PSBT as base64.
+impl fmt::Display for PartiallySignedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_base64())
+    }
+}
+
+impl FromStr for PartiallySignedTransaction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PartiallySignedTransaction::from_base64(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::psbt::raw::{input as input_type, output as output_type};
+
+    /// BIP174: one P2PKH input, outputs empty.
+    const BIP174_P2PKH: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA8PUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+
+    /// BIP174: one P2PKH and one P2SH-P2WPKH input, the first finalized.
+    const BIP174_FINALIZED: &str = "cHNidP8BAKACAAAAAqsJSaCMWvfEm4IS9Bfi8Vqz9cM9zxU4IagTn4d6W3vkAAAAAAD+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEHakcwRAIgR1lmF5fAGwNrJZKJSGhiGDR9iYZLcZ4ff89X0eURZYcCIFMJ6r9Wqk2Ikf/REf3xM286KdqGbX+EhtdVRs7tr5MZASEDXNxh/HupccC1AaZGoqg7ECy0OIEhfKaC3Ibi1z+ogpIAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIAAAA";
+
+    /// BIP174: one P2PKH input with a sighash type, outputs empty.
+    const BIP174_SIGHASH: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA8PUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAQMEAQAAAAAAAA==";
+
+    /// BIP174: a P2PKH and a P2SH-P2WPKH input, with output derivations.
+    const BIP174_OUTPUTS: &str = "cHNidP8BAKACAAAAAqsJSaCMWvfEm4IS9Bfi8Vqz9cM9zxU4IagTn4d6W3vkAAAAAAD+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEA3wIAAAABJoFxNx7f8oXpN63upLN7eAAMBWbLs61kZBcTykIXG/YAAAAAakcwRAIgcLIkUSPmv0dNYMW1DAQ9TGkaXSQ18Jo0p2YqncJReQoCIAEynKnazygL3zB0DsA5BCJCLIHLRYOUV663b8Eu3ZWzASECZX0RjTNXuOD0ws1G23s59tnDjZpwq8ubLeXcjb/kzjH+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIACICAurVlmh8qAYEPtw94RbN8p1eklfBls0FXPaYyNAr8k6ZELSmumcAAACAAAAAgAIAAIAAIgIDlPYr6d8ZlSxVh3aK63aYBhrSxKJciU9H2MFitNchPQUQtKa6ZwAAAIABAACAAgAAgAA=";
+
+    /// BIP370: one input and two outputs with only the required fields.
+    const BIP370_MINIMAL: &str = "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEOIAsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAQ8EAAAAAAABAwgIrwkAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLvKZkmJywAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==";
+
+    fn bytes(psbt: &str) -> Vec<u8> {
+        base64::decode(psbt).unwrap()
+    }
+
+    fn encode(maps: &[Vec<Pair>]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for map in maps {
+            for pair in map {
+                pair.encode(&mut out);
+            }
+            out.push(0x00);
+        }
+        out
+    }
+
+    /// A version 2 PSBT with one input and one output, and `input` as the
+    /// input's extra pairs.
+    fn v2(global: Vec<Pair>, input: Vec<Pair>) -> Vec<u8> {
+        let mut global_map = vec![
+            Pair::new(key_type::TX_VERSION, vec![], 2u32.to_le_bytes().to_vec()),
+            Pair::new(key_type::INPUT_COUNT, vec![], vec![1]),
+            Pair::new(key_type::OUTPUT_COUNT, vec![], vec![1]),
+            Pair::new(key_type::VERSION, vec![], 2u32.to_le_bytes().to_vec()),
+        ];
+        global_map.extend(global);
+        let mut input_map = vec![
+            Pair::new(input_type::PREVIOUS_TXID, vec![], vec![0xab; 32]),
+            Pair::new(
+                input_type::OUTPUT_INDEX,
+                vec![],
+                0u32.to_le_bytes().to_vec(),
+            ),
+        ];
+        input_map.extend(input);
+        let output_map = vec![
+            Pair::new(
+                output_type::AMOUNT,
+                vec![],
+                10_000u64.to_le_bytes().to_vec(),
+            ),
+            Pair::new(
+                output_type::SCRIPT,
+                vec![],
+                [&[0x00, 0x14][..], &[0x11; 20]].concat(),
+            ),
+        ];
+        encode(&[global_map, input_map, output_map])
+    }
+
+    #[test]
+    fn bip174_valid_vectors_round_trip() {
+        for vector in [
+            BIP174_P2PKH,
+            BIP174_FINALIZED,
+            BIP174_SIGHASH,
+            BIP174_OUTPUTS,
+        ] {
+            let psbt = PartiallySignedTransaction::from_base64(vector).unwrap();
+            assert_eq!(psbt.version, 0);
+            assert_eq!(psbt.serialize(), bytes(vector));
+            assert_eq!(psbt.to_base64(), vector);
+        }
+    }
+
+    #[test]
+    fn bip174_vectors_decode_their_fields() {
+        let psbt = PartiallySignedTransaction::from_base64(BIP174_P2PKH).unwrap();
+        assert_eq!(psbt.inputs.len(), 1);
+        assert_eq!(psbt.outputs.len(), 2);
+        assert!(psbt.inputs[0].non_witness_utxo.is_some());
+
+        let psbt = PartiallySignedTransaction::from_base64(BIP174_FINALIZED).unwrap();
+        assert!(psbt.inputs[0].final_script_sig.is_some());
+        assert!(psbt.inputs[1].witness_utxo.is_some());
+        assert!(psbt.inputs[1].redeem_script.is_some());
+
+        let psbt = PartiallySignedTransaction::from_base64(BIP174_SIGHASH).unwrap();
+        assert_eq!(psbt.inputs[0].sighash_type, Some(1));
+
+        let psbt = PartiallySignedTransaction::from_base64(BIP174_OUTPUTS).unwrap();
+        assert_eq!(psbt.outputs[0].bip32_derivation.len(), 1);
+        assert_eq!(psbt.outputs[1].bip32_derivation.len(), 1);
+    }
+
+    #[test]
+    fn bip174_invalid_vectors() {
+        // A network transaction, not a PSBT.
+        let network_tx = "AgAAAAEmgXE3Ht/yhek3re6ks3t4AAwFZsuzrWRkFxPKQhcb9gAAAABqRzBEAiBwsiRRI+a/R01gxbUMBD1MaRpdJDXwmjSnZiqdwlF5CgIgATKcqdrPKAvfMHQOwDkEIkIsgctFg5RXrrdvwS7dlbMBIQJlfRGNM1e44PTCzUbbezn22cONmnCry5st5dyNv+TOMf7///8C09/1BQAAAAAZdqkU0MWZA8W6woaHYOkP1SGkZlqnZSCIrADh9QUAAAAAF6kUNUXm4zuDLEcFDyTT7rk8nAOUi8eHsy4TAA==";
+        assert_eq!(
+            PartiallySignedTransaction::from_base64(network_tx),
+            Err(Error::InvalidMagic)
+        );
+
+        // Missing the output maps.
+        let mut missing_outputs = bytes(BIP174_P2PKH);
+        missing_outputs.truncate(missing_outputs.len() - 2);
+        assert!(matches!(
+            PartiallySignedTransaction::deserialize(&missing_outputs),
+            Err(Error::OutputCountMismatch { expected: 2, .. })
+        ));
+
+        // A scriptSig in the unsigned transaction.
+        let script_sig = "cHNidP8BAP0KAQIAAAACqwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QAAAAAakcwRAIgR1lmF5fAGwNrJZKJSGhiGDR9iYZLcZ4ff89X0eURZYcCIFMJ6r9Wqk2Ikf/REf3xM286KdqGbX+EhtdVRs7tr5MZASEDXNxh/HupccC1AaZGoqg7ECy0OIEhfKaC3Ibi1z+ogpL+////qwlJoIxa98SbghL0F+LxWrP1wz3PFTghqBOfh3pbe+QBAAAAAP7///8CYDvqCwAAAAAZdqkUdopAu9dAy+gdmI5x3ipNXHE5ax2IrI4kAAAAAAAAGXapFG9GILVT+glechue4O/p+gOcykWXiKwAAAAAAAEA3wIAAAABJoFxNx7f8oXpN63upLN7eAAMBWbLs61kZBcTykIXG/YAAAAAakcwRAIgcLIkUSPmv0dNYMW1DAQ9TGkaXSQ18Jo0p2YqncJReQoCIAEynKnazygL3zB0DsA5BCJCLIHLRYOUV663b8Eu3ZWzASECZX0RjTNXuOD0ws1G23s59tnDjZpwq8ubLeXcjb/kzjH+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQEgAOH1BQAAAAAXqRQ1RebjO4MsRwUPJNPuuTycA5SLx4cBBBYAFIXRNTfy4mVAWjTbr6nj3aAfuCMIAAAA";
+        assert_eq!(
+            PartiallySignedTransaction::from_base64(script_sig),
+            Err(Error::UnsignedTxHasScripts)
+        );
+
+        // Input and output maps without an unsigned transaction.
+        let no_tx = "cHNidP8AAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAA=";
+        assert_eq!(
+            PartiallySignedTransaction::from_base64(no_tx),
+            Err(Error::MissingUnsignedTx)
+        );
+
+        // A non-witness UTXO key with key data.
+        let keyed_utxo = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA8PUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAQA/AgAAAAH//////////////////////////////////////////wAAAAAA/////wEAAAAAAAAAAANqAQAAAAAAAAAA";
+        assert!(matches!(
+            PartiallySignedTransaction::from_base64(keyed_utxo),
+            Err(Error::InvalidKey(key)) if key.type_value == input_type::NON_WITNESS_UTXO
+        ));
+
+        // The same key twice in an input map.
+        let mut psbt = bytes(BIP174_SIGHASH);
+        let sighash_at = psbt.len() - 3;
+        let sighash = Pair::new(input_type::SIGHASH_TYPE, vec![], vec![1, 0, 0, 0]);
+        let mut pair = Vec::new();
+        sighash.encode(&mut pair);
+        psbt.splice(sighash_at..sighash_at, pair);
+        assert!(matches!(
+            PartiallySignedTransaction::deserialize(&psbt),
+            Err(Error::DuplicateKey(key)) if key.type_value == input_type::SIGHASH_TYPE
+        ));
+    }
+
+    #[test]
+    fn bip370_valid_vector_round_trips() {
+        let psbt = PartiallySignedTransaction::from_base64(BIP370_MINIMAL).unwrap();
+        assert_eq!(psbt.version, 2);
+        assert_eq!(psbt.unsigned_tx.version, 2);
+        assert_eq!(psbt.unsigned_tx.input.len(), 1);
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+        assert_eq!(psbt.to_base64(), BIP370_MINIMAL);
+    }
+
+    #[test]
+    fn bip370_invalid_fields() {
+        assert!(PartiallySignedTransaction::deserialize(&v2(vec![], vec![])).is_ok());
+
+        // A version 2 PSBT must not carry the global unsigned transaction.
+        let tx = PartiallySignedTransaction::from_base64(BIP174_P2PKH)
+            .unwrap()
+            .unsigned_tx
+            .serialize_no_witness();
+        let unsigned_tx = Pair::new(key_type::UNSIGNED_TX, vec![], tx);
+        assert!(matches!(
+            PartiallySignedTransaction::deserialize(&v2(vec![unsigned_tx], vec![])),
+            Err(Error::KeyNotAllowed { version: 2, .. })
+        ));
+
+        // Nor a transaction version below 2. The version is the first value,
+        // after its key length, key type and value length.
+        let mut psbt = v2(vec![], vec![]);
+        psbt[MAGIC.len() + 3] = 1;
+        assert_eq!(
+            PartiallySignedTransaction::deserialize(&psbt),
+            Err(Error::InvalidTxVersion(1))
+        );
+
+        // A required time locktime must be a time, not a height.
+        let height_as_time = Pair::new(
+            input_type::REQUIRED_TIME_LOCKTIME,
+            vec![],
+            499_999_999u32.to_le_bytes().to_vec(),
+        );
+        assert!(matches!(
+            PartiallySignedTransaction::deserialize(&v2(vec![], vec![height_as_time])),
+            Err(Error::InvalidValue { .. })
+        ));
+
+        // Version 2 input fields are not allowed in version 0. The input map
+        // follows the 0x75-byte unsigned transaction and the global separator.
+        let mut psbt = bytes(BIP174_P2PKH);
+        let input_start = MAGIC.len() + 3 + 0x75 + 1;
+        let output_index = Pair::new(input_type::OUTPUT_INDEX, vec![], vec![0; 4]);
+        let mut pair = Vec::new();
+        output_index.encode(&mut pair);
+        psbt.splice(input_start..input_start, pair);
+        assert!(matches!(
+            PartiallySignedTransaction::deserialize(&psbt),
+            Err(Error::KeyNotAllowed { version: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn bip370_locktime_determination() {
+        let height = |height: u32| {
+            Pair::new(
+                input_type::REQUIRED_HEIGHT_LOCKTIME,
+                vec![],
+                height.to_le_bytes().to_vec(),
+            )
+        };
+        let time = |time: u32| {
+            Pair::new(
+                input_type::REQUIRED_TIME_LOCKTIME,
+                vec![],
+                time.to_le_bytes().to_vec(),
+            )
+        };
+        let fallback = Pair::new(
+            key_type::FALLBACK_LOCKTIME,
+            vec![],
+            10_000u32.to_le_bytes().to_vec(),
+        );
+        let lock_time = |global, input| {
+            PartiallySignedTransaction::deserialize(&v2(global, input))
+                .map(|psbt| psbt.unsigned_tx.lock_time)
+        };
+
+        assert_eq!(lock_time(vec![], vec![]), Ok(0));
+        assert_eq!(lock_time(vec![fallback.clone()], vec![]), Ok(10_000));
+        assert_eq!(lock_time(vec![fallback], vec![height(20_000)]), Ok(20_000));
+        assert_eq!(
+            lock_time(vec![], vec![time(1_657_048_460)]),
+            Ok(1_657_048_460)
+        );
+        // An input allowing either is satisfied by a height.
+        assert_eq!(
+            lock_time(vec![], vec![height(20_000), time(1_657_048_460)]),
+            Ok(20_000)
+        );
+    }
+
+    #[test]
+    fn locktime_conflict_between_inputs() {
+        let mut psbt = PartiallySignedTransaction::deserialize(&v2(vec![], vec![])).unwrap();
+        psbt.tx_modifiable = Some(modifiable::INPUTS);
+        psbt.inputs[0].required_height_locktime = Some(20_000);
+        let txin = psbt.unsigned_tx.input[0].clone();
+        let input = Input {
+            required_time_locktime: Some(1_657_048_460),
+            ..Input::default()
+        };
+        assert_eq!(psbt.add_input(txin, input), Err(Error::LockTimeConflict));
+        assert_eq!(psbt.inputs.len(), 1);
+    }
+
+    #[test]
+    fn v0_to_v2_and_back_round_trips() {
+        for vector in [BIP174_P2PKH, BIP174_FINALIZED, BIP174_OUTPUTS] {
+            let v0 = PartiallySignedTransaction::from_base64(vector).unwrap();
+            let v2 = v0.clone().into_v2().unwrap();
+            assert_eq!(v2.version, 2);
+            assert_eq!(v2.fallback_locktime, Some(v0.unsigned_tx.lock_time));
+
+            let reparsed = PartiallySignedTransaction::deserialize(&v2.serialize()).unwrap();
+            assert_eq!(reparsed.serialize(), v2.serialize());
+            assert_eq!(reparsed.unsigned_tx, v0.unsigned_tx);
+            assert_eq!(reparsed.inputs, v0.inputs);
+            assert_eq!(reparsed.outputs, v0.outputs);
+
+            assert_eq!(reparsed.into_v0().unwrap().to_base64(), vector);
+        }
+    }
+
+    #[test]
+    fn v2_to_v0_fixes_the_locktime() {
+        let psbt = PartiallySignedTransaction::deserialize(&v2(
+            vec![],
+            vec![Pair::new(
+                input_type::REQUIRED_HEIGHT_LOCKTIME,
+                vec![],
+                20_000u32.to_le_bytes().to_vec(),
+            )],
+        ))
+        .unwrap();
+        let v0 = psbt.into_v0().unwrap();
+        assert_eq!(v0.version, 0);
+        assert_eq!(v0.unsigned_tx.lock_time, 20_000);
+        assert_eq!(v0.inputs[0].required_height_locktime, None);
+
+        let reparsed = PartiallySignedTransaction::deserialize(&v0.serialize()).unwrap();
+        assert_eq!(reparsed.unsigned_tx, v0.unsigned_tx);
+    }
+
+    #[test]
+    fn v2_conversion_derives_modifiable_flags() {
+        let unsigned = PartiallySignedTransaction::from_base64(BIP174_P2PKH)
+            .unwrap()
+            .into_v2()
+            .unwrap();
+        assert_eq!(
+            unsigned.tx_modifiable,
+            Some(modifiable::INPUTS | modifiable::OUTPUTS)
+        );
+
+        let finalized = PartiallySignedTransaction::from_base64(BIP174_FINALIZED)
+            .unwrap()
+            .into_v2()
+            .unwrap();
+        assert_eq!(finalized.tx_modifiable, Some(0));
+        assert!(!finalized.inputs_modifiable());
+        assert!(!finalized.outputs_modifiable());
+
+        let mut single = PartiallySignedTransaction::from_base64(BIP174_P2PKH).unwrap();
+        let mut sig = vec![1; 65];
+        sig[64] = SIGHASH_SINGLE | SIGHASH_ANYONECANPAY;
+        single.inputs[0].tap_key_sig = Some(sig);
+        assert_eq!(
+            single.into_v2().unwrap().tx_modifiable,
+            Some(modifiable::INPUTS | modifiable::OUTPUTS | modifiable::HAS_SIGHASH_SINGLE)
+        );
+    }
+
+    #[test]
+    fn converted_v2_psbts_accept_inputs() {
+        let mut psbt = PartiallySignedTransaction::from_base64(BIP174_P2PKH)
+            .unwrap()
+            .into_v2()
+            .unwrap();
+        let other = PartiallySignedTransaction::from_base64(BIP174_OUTPUTS)
+            .unwrap()
+            .into_v2()
+            .unwrap();
+
+        let joined = join(&[psbt.clone(), other.clone()], JoinOrder::Preserve).unwrap();
+        assert_eq!(joined.version, 2);
+        assert_eq!(joined.inputs.len(), 3);
+        assert_eq!(joined.outputs.len(), 4);
+
+        psbt.add_input(other.unsigned_tx.input[0].clone(), other.inputs[0].clone())
+            .unwrap();
+        assert_eq!(psbt.inputs.len(), 2);
+        assert_eq!(psbt.unsigned_tx.input.len(), 2);
+    }
+
+    #[test]
+    fn v1_transactions_cannot_become_v2() {
+        let mut psbt = PartiallySignedTransaction::from_base64(BIP174_P2PKH).unwrap();
+        psbt.unsigned_tx.version = 1;
+        assert_eq!(psbt.into_v2(), Err(Error::InvalidTxVersion(1)));
+    }
+}
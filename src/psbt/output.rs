@@ -0,0 +1,210 @@
+use crate::amount::Amount;
+use crate::encode::{write_var_bytes, Reader};
+use crate::psbt::input::{decode_tap_key_origin, encode_tap_key_origin};
+use crate::psbt::raw::{
+    self, decode_value, expect_empty_key, insert_unique, key_array, key_pubkey, set_unique,
+    value_array, KeyOrder, KeySource, Pair, ProprietaryKey, RawKey,
+};
+use crate::psbt::Error;
+use crate::transaction::{Script, TxOut};
+use std::collections::BTreeMap;
+
+use raw::output as key_type;
+
+/// A leaf of a taproot script tree, in depth-first order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapLeaf {
+    pub depth: u8,
+    pub leaf_version: u8,
+    pub script: Script,
+}
+
+/// A per-output map of a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Output {
+    pub redeem_script: Option<Script>,
+    pub witness_script: Option<Script>,
+    /// Key sources, keyed by public key.
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    pub tap_internal_key: Option<[u8; 32]>,
+    pub tap_tree: Option<Vec<TapLeaf>>,
+    /// Leaf hashes and key sources, keyed by x-only key.
+    pub tap_key_origins: BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeySource)>,
+    pub proprietary: BTreeMap<ProprietaryKey, Vec<u8>>,
+    /// Pairs with key types this crate does not know, kept as they were read.
+    pub unknown: BTreeMap<RawKey, Vec<u8>>,
+    pub(crate) key_order: KeyOrder,
+}
+
+impl Output {
+    /// Decodes an output map. For version 2 PSBTs the transaction output the
+    /// map describes is returned as well.
+    pub(crate) fn decode(
+        reader: &mut Reader,
+        version: u32,
+    ) -> Result<(Self, Option<TxOut>), Error> {
+        let mut output = Output::default();
+        let mut amount = None;
+        let mut script = None;
+        while let Some(pair) = Pair::decode(reader)? {
+            output.key_order.push(pair.key.clone());
+            let raw = &pair.key;
+            match raw.type_value {
+                key_type::AMOUNT if version >= 2 => {
+                    expect_empty_key(&pair)?;
+                    let value = i64::from_le_bytes(value_array(&pair)?);
+                    let value = u64::try_from(value)
+                        .ok()
+                        .map(Amount::from_sat)
+                        .filter(|&value| value <= Amount::MAX_MONEY)
+                        .ok_or_else(|| Error::invalid_value(raw, "amount out of range"))?;
+                    set_unique(&mut amount, value, raw)?;
+                }
+                key_type::SCRIPT if version >= 2 => {
+                    expect_empty_key(&pair)?;
+                    set_unique(&mut script, Script(pair.value.clone()), raw)?;
+                }
+                key_type::AMOUNT | key_type::SCRIPT => {
+                    return Err(Error::KeyNotAllowed {
+                        version,
+                        key: raw.clone(),
+                    })
+                }
+                _ => output.insert_pair(pair)?,
+            }
+        }
+
+        if version < 2 {
+            return Ok((output, None));
+        }
+        let missing = |type_value| Error::MissingKey {
+            map: "output",
+            type_value,
+        };
+        let txout = TxOut {
+            value: amount.ok_or_else(|| missing(key_type::AMOUNT))?,
+            script_pubkey: script.ok_or_else(|| missing(key_type::SCRIPT))?,
+        };
+        Ok((output, Some(txout)))
+    }
+
+    fn insert_pair(&mut self, pair: Pair) -> Result<(), Error> {
+        let raw = &pair.key;
+        match raw.type_value {
+            key_type::REDEEM_SCRIPT => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.redeem_script, Script(pair.value.clone()), raw)
+            }
+            key_type::WITNESS_SCRIPT => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.witness_script, Script(pair.value.clone()), raw)
+            }
+            key_type::BIP32_DERIVATION => {
+                let pubkey = key_pubkey(&pair)?;
+                let source = decode_value(&pair, KeySource::decode)?;
+                insert_unique(&mut self.bip32_derivation, pubkey, source, raw)
+            }
+            key_type::TAP_INTERNAL_KEY => {
+                expect_empty_key(&pair)?;
+                set_unique(&mut self.tap_internal_key, value_array(&pair)?, raw)
+            }
+            key_type::TAP_TREE => {
+                expect_empty_key(&pair)?;
+                let tree = decode_value(&pair, decode_tap_tree)?;
+                set_unique(&mut self.tap_tree, tree, raw)
+            }
+            key_type::TAP_BIP32_DERIVATION => {
+                let xonly = key_array(&pair)?;
+                let origin = decode_value(&pair, decode_tap_key_origin)?;
+                insert_unique(&mut self.tap_key_origins, xonly, origin, raw)
+            }
+            key_type::PROPRIETARY => {
+                let key = ProprietaryKey::decode(raw)?;
+                insert_unique(&mut self.proprietary, key, pair.value.clone(), raw)
+            }
+            _ => insert_unique(&mut self.unknown, raw.clone(), pair.value.clone(), raw),
+        }
+    }
+
+    /// The map's key-value pairs, in the order they are serialized. Version 2
+    /// PSBTs also carry the fields of `txout`, the output the map describes.
+    pub(crate) fn pairs(&self, version: u32, txout: &TxOut) -> Vec<Pair> {
+        let mut pairs = Vec::new();
+        if version >= 2 {
+            let amount = txout.value.to_sat().to_le_bytes().to_vec();
+            pairs.push(Pair::new(key_type::AMOUNT, vec![], amount));
+            pairs.push(Pair::new(
+                key_type::SCRIPT,
+                vec![],
+                txout.script_pubkey.0.clone(),
+            ));
+        }
+        if let Some(script) = &self.redeem_script {
+            pairs.push(Pair::new(key_type::REDEEM_SCRIPT, vec![], script.0.clone()));
+        }
+        if let Some(script) = &self.witness_script {
+            pairs.push(Pair::new(
+                key_type::WITNESS_SCRIPT,
+                vec![],
+                script.0.clone(),
+            ));
+        }
+        for (pubkey, source) in &self.bip32_derivation {
+            pairs.push(Pair::new(
+                key_type::BIP32_DERIVATION,
+                pubkey.clone(),
+                source.serialize(),
+            ));
+        }
+        if let Some(key) = &self.tap_internal_key {
+            pairs.push(Pair::new(key_type::TAP_INTERNAL_KEY, vec![], key.to_vec()));
+        }
+        if let Some(tree) = &self.tap_tree {
+            let mut value = Vec::new();
+            for leaf in tree {
+                value.push(leaf.depth);
+                value.push(leaf.leaf_version);
+                write_var_bytes(&mut value, leaf.script.as_bytes());
+            }
+            pairs.push(Pair::new(key_type::TAP_TREE, vec![], value));
+        }
+        for (xonly, origin) in &self.tap_key_origins {
+            let value = encode_tap_key_origin(origin);
+            pairs.push(Pair::new(
+                key_type::TAP_BIP32_DERIVATION,
+                xonly.to_vec(),
+                value,
+            ));
+        }
+        for (key, value) in &self.proprietary {
+            pairs.push(Pair {
+                key: key.to_raw(key_type::PROPRIETARY),
+                value: value.clone(),
+            });
+        }
+        for (key, value) in &self.unknown {
+            pairs.push(Pair {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+
+        self.key_order.apply(&mut pairs);
+        pairs
+    }
+}
+
+fn decode_tap_tree(reader: &mut Reader) -> Result<Vec<TapLeaf>, crate::encode::Error> {
+    let mut tree = Vec::new();
+    while !reader.is_empty() {
+        let depth = reader.read_u8()?;
+        let leaf_version = reader.read_u8()?;
+        let script = Script(reader.read_var_bytes()?.to_vec());
+        tree.push(TapLeaf {
+            depth,
+            leaf_version,
+            script,
+        });
+    }
+    Ok(tree)
+}
@@ -0,0 +1,343 @@
+use crate::encode::{self, write_compact_size, write_var_bytes, Reader};
+use crate::psbt::Error;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Key types of the global map.
+pub mod global {
+    pub const UNSIGNED_TX: u64 = 0x00;
+    pub const XPUB: u64 = 0x01;
+    pub const TX_VERSION: u64 = 0x02;
+    pub const FALLBACK_LOCKTIME: u64 = 0x03;
+    pub const INPUT_COUNT: u64 = 0x04;
+    pub const OUTPUT_COUNT: u64 = 0x05;
+    pub const TX_MODIFIABLE: u64 = 0x06;
+    pub const VERSION: u64 = 0xfb;
+    pub const PROPRIETARY: u64 = 0xfc;
+}
+
+/// Key types of the per-input maps.
+pub mod input {
+    pub const NON_WITNESS_UTXO: u64 = 0x00;
+    pub const WITNESS_UTXO: u64 = 0x01;
+    pub const PARTIAL_SIG: u64 = 0x02;
+    pub const SIGHASH_TYPE: u64 = 0x03;
+    pub const REDEEM_SCRIPT: u64 = 0x04;
+    pub const WITNESS_SCRIPT: u64 = 0x05;
+    pub const BIP32_DERIVATION: u64 = 0x06;
+    pub const FINAL_SCRIPTSIG: u64 = 0x07;
+    pub const FINAL_SCRIPTWITNESS: u64 = 0x08;
+    pub const RIPEMD160: u64 = 0x0a;
+    pub const SHA256: u64 = 0x0b;
+    pub const HASH160: u64 = 0x0c;
+    pub const HASH256: u64 = 0x0d;
+    pub const PREVIOUS_TXID: u64 = 0x0e;
+    pub const OUTPUT_INDEX: u64 = 0x0f;
+    pub const SEQUENCE: u64 = 0x10;
+    pub const REQUIRED_TIME_LOCKTIME: u64 = 0x11;
+    pub const REQUIRED_HEIGHT_LOCKTIME: u64 = 0x12;
+    pub const TAP_KEY_SIG: u64 = 0x13;
+    pub const TAP_SCRIPT_SIG: u64 = 0x14;
+    pub const TAP_LEAF_SCRIPT: u64 = 0x15;
+    pub const TAP_BIP32_DERIVATION: u64 = 0x16;
+    pub const TAP_INTERNAL_KEY: u64 = 0x17;
+    pub const TAP_MERKLE_ROOT: u64 = 0x18;
+    pub const PROPRIETARY: u64 = 0xfc;
+}
+
+/// Key types of the per-output maps.
+pub mod output {
+    pub const REDEEM_SCRIPT: u64 = 0x00;
+    pub const WITNESS_SCRIPT: u64 = 0x01;
+    pub const BIP32_DERIVATION: u64 = 0x02;
+    pub const AMOUNT: u64 = 0x03;
+    pub const SCRIPT: u64 = 0x04;
+    pub const TAP_INTERNAL_KEY: u64 = 0x05;
+    pub const TAP_TREE: u64 = 0x06;
+    pub const TAP_BIP32_DERIVATION: u64 = 0x07;
+    pub const PROPRIETARY: u64 = 0xfc;
+}
+
+/// The key of a PSBT key-value pair: its type and the key data following it.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RawKey {
+    pub type_value: u64,
+    pub key: Vec<u8>,
+}
+
+impl RawKey {
+    pub fn new(type_value: u64, key: Vec<u8>) -> Self {
+        RawKey { type_value, key }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut key = Vec::with_capacity(self.key.len() + 1);
+        write_compact_size(&mut key, self.type_value);
+        key.extend_from_slice(&self.key);
+        write_var_bytes(out, &key);
+    }
+}
+
+impl fmt::Debug for RawKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "RawKey({:#04x}, {})",
+            self.type_value,
+            hex::encode(&self.key)
+        )
+    }
+}
+
+impl fmt::Display for RawKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04x}", self.type_value)?;
+        if !self.key.is_empty() {
+            write!(f, ":{}", hex::encode(&self.key))?;
+        }
+        Ok(())
+    }
+}
+
+/// A key-value pair as it appears in a PSBT map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pair {
+    pub key: RawKey,
+    pub value: Vec<u8>,
+}
+
+impl Pair {
+    pub fn new(type_value: u64, key: Vec<u8>, value: Vec<u8>) -> Self {
+        Pair {
+            key: RawKey::new(type_value, key),
+            value,
+        }
+    }
+
+    /// Reads the next pair of a map, or `None` at the map's separator.
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Option<Pair>, Error> {
+        let key = reader.read_var_bytes()?;
+        if key.is_empty() {
+            return Ok(None);
+        }
+
+        let mut key_reader = Reader::new(key);
+        let type_value = key_reader.read_compact_size()?;
+        let key = key_reader.read_bytes(key_reader.remaining())?.to_vec();
+        let value = reader.read_var_bytes()?.to_vec();
+
+        Ok(Some(Pair::new(type_value, key, value)))
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        self.key.encode(out);
+        write_var_bytes(out, &self.value);
+    }
+}
+
+/// A proprietary key (type `0xfc`): an identifier prefix, a subtype and key data.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProprietaryKey {
+    pub prefix: Vec<u8>,
+    pub subtype: u64,
+    pub key: Vec<u8>,
+}
+
+impl ProprietaryKey {
+    pub(crate) fn decode(key: &RawKey) -> Result<Self, Error> {
+        let mut reader = Reader::new(&key.key);
+        let prefix = reader.read_var_bytes()?.to_vec();
+        let subtype = reader.read_compact_size()?;
+        let data = reader.read_bytes(reader.remaining())?.to_vec();
+        Ok(ProprietaryKey {
+            prefix,
+            subtype,
+            key: data,
+        })
+    }
+
+    pub(crate) fn to_raw(&self, type_value: u64) -> RawKey {
+        let mut key = Vec::new();
+        write_var_bytes(&mut key, &self.prefix);
+        write_compact_size(&mut key, self.subtype);
+        key.extend_from_slice(&self.key);
+        RawKey::new(type_value, key)
+    }
+}
+
+/// The master key fingerprint and derivation path of a public key.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct KeySource {
+    pub fingerprint: [u8; 4],
+    pub path: Vec<u32>,
+}
+
+impl KeySource {
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Self, encode::Error> {
+        let fingerprint = reader.read_array()?;
+        if !reader.remaining().is_multiple_of(4) {
+            return Err(encode::Error::InvalidData(
+                "derivation path is not a multiple of 4 bytes".to_string(),
+            ));
+        }
+        let mut path = Vec::with_capacity(reader.remaining() / 4);
+        while !reader.is_empty() {
+            path.push(reader.read_u32_le()?);
+        }
+        Ok(KeySource { fingerprint, path })
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.fingerprint);
+        for index in &self.path {
+            out.extend_from_slice(&index.to_le_bytes());
+        }
+    }
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+impl fmt::Display for KeySource {
+    /// Formats as `[fingerprint/path]`, e.g. `[d34db33f/84'/1'/0']`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}", hex::encode(self.fingerprint))?;
+        for index in &self.path {
+            if index & 0x8000_0000 != 0 {
+                write!(f, "/{}'", index & 0x7fff_ffff)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// The order in which a map's keys were read.
+///
+/// Serializing a parsed PSBT writes its keys back in this order so the output
+/// matches the input byte for byte. Keys added afterwards follow in key order.
+/// It does not take part in equality, as it does not change a PSBT's meaning.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KeyOrder(Vec<RawKey>);
+
+impl KeyOrder {
+    pub(crate) fn push(&mut self, key: RawKey) {
+        self.0.push(key);
+    }
+
+    /// Whether a key of `type_value` with no key data was read.
+    pub(crate) fn contains(&self, type_value: u64) -> bool {
+        self.0
+            .iter()
+            .any(|key| key.type_value == type_value && key.key.is_empty())
+    }
+
+    /// Forgets a key of `type_value` with no key data.
+    pub(crate) fn remove(&mut self, type_value: u64) {
+        self.0
+            .retain(|key| key.type_value != type_value || !key.key.is_empty());
+    }
+
+    /// Sorts `pairs` by the recorded order, with new keys at the end.
+    pub(crate) fn apply(&self, pairs: &mut [Pair]) {
+        let positions: HashMap<&RawKey, usize> =
+            self.0.iter().enumerate().map(|(i, key)| (key, i)).collect();
+        pairs.sort_by(|a, b| {
+            let a_pos = positions.get(&a.key).copied().unwrap_or(usize::MAX);
+            let b_pos = positions.get(&b.key).copied().unwrap_or(usize::MAX);
+            a_pos.cmp(&b_pos).then_with(|| a.key.cmp(&b.key))
+        });
+    }
+}
+
+impl PartialEq for KeyOrder {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for KeyOrder {}
+
+impl PartialOrd for KeyOrder {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for KeyOrder {
+    fn cmp(&self, _: &Self) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+/// Fails unless the pair's key has no key data.
+pub(crate) fn expect_empty_key(pair: &Pair) -> Result<(), Error> {
+    if pair.key.key.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidKey(pair.key.clone()))
+    }
+}
+
+/// Fails unless the pair's key data is exactly `N` bytes, returning them.
+pub(crate) fn key_array<const N: usize>(pair: &Pair) -> Result<[u8; N], Error> {
+    pair.key
+        .key
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::InvalidKey(pair.key.clone()))
+}
+
+/// Fails unless the pair's key data is a compressed or uncompressed public key.
+pub(crate) fn key_pubkey(pair: &Pair) -> Result<Vec<u8>, Error> {
+    match (pair.key.key.len(), pair.key.key.first()) {
+        (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => Ok(pair.key.key.clone()),
+        _ => Err(Error::InvalidKey(pair.key.clone())),
+    }
+}
+
+/// Fails unless the pair's value is exactly `N` bytes, returning them.
+pub(crate) fn value_array<const N: usize>(pair: &Pair) -> Result<[u8; N], Error> {
+    pair.value
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::invalid_value(&pair.key, format!("expected {} bytes", N)))
+}
+
+/// Decodes the pair's value with `decode`, requiring it to consume every byte.
+pub(crate) fn decode_value<T>(
+    pair: &Pair,
+    decode: impl FnOnce(&mut Reader) -> Result<T, encode::Error>,
+) -> Result<T, Error> {
+    let mut reader = Reader::new(&pair.value);
+    decode(&mut reader)
+        .and_then(|value| reader.finish().map(|_| value))
+        .map_err(|e| Error::invalid_value(&pair.key, e.to_string()))
+}
+
+/// Inserts into a map of a PSBT, failing if the key was already present.
+pub(crate) fn insert_unique<K: Ord, V>(
+    map: &mut std::collections::BTreeMap<K, V>,
+    key: K,
+    value: V,
+    raw: &RawKey,
+) -> Result<(), Error> {
+    if map.insert(key, value).is_some() {
+        return Err(Error::DuplicateKey(raw.clone()));
+    }
+    Ok(())
+}
+
+/// Sets a single-valued field of a PSBT, failing if it was already set.
+pub(crate) fn set_unique<T>(field: &mut Option<T>, value: T, raw: &RawKey) -> Result<(), Error> {
+    if field.is_some() {
+        return Err(Error::DuplicateKey(raw.clone()));
+    }
+    *field = Some(value);
+    Ok(())
+}
@@ -0,0 +1,339 @@
+use crate::error::{Error, RpcCode};
+use crate::network::{default_datadir, Network};
+use reqwest::blocking::Client as ReqClient;
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Credentials used to authenticate against the Bitcoin Core RPC server.
+#[derive(Debug, Clone)]
+pub enum Auth {
+    /// No `Authorization` header is sent.
+    None,
+    /// Basic authentication with `rpcuser`/`rpcpassword` or with the user and
+    /// password of an `rpcauth` entry.
+    UserPass(String, String),
+    /// The `.cookie` file the node writes to its datadir on startup. The file is
+    /// re-read whenever the node rejects the current cookie.
+    CookieFile(PathBuf),
+}
+
+impl Auth {
+    /// Cookie authentication for a node using `datadir` on `network`.
+    pub fn cookie(datadir: &Path, network: Network) -> Auth {
+        Auth::CookieFile(network.cookie_path(datadir))
+    }
+
+    /// Cookie authentication for a node using the platform's default datadir.
+    pub fn default_cookie(network: Network) -> Result<Auth, Error> {
+        let datadir = default_datadir()
+            .ok_or_else(|| Error::Config("could not determine the default datadir".to_string()))?;
+        Ok(Auth::cookie(&datadir, network))
+    }
+
+    fn header(&self) -> Result<Option<HeaderValue>, Error> {
+        let credentials = match self {
+            Auth::None => return Ok(None),
+            Auth::UserPass(user, password) => format!("{}:{}", user, password),
+            Auth::CookieFile(path) => fs::read_to_string(path)
+                .map_err(|e| {
+                    Error::Config(format!(
+                        "could not read cookie file {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?
+                .trim()
+                .to_string(),
+        };
+
+        let encoded = format!("Basic {}", base64::encode(credentials));
+        HeaderValue::from_str(&encoded)
+            .map(Some)
+            .map_err(|_| Error::Config("RPC credentials contain invalid characters".to_string()))
+    }
+}
+
+/// Connection settings for an [`RpcClient`].
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub auth: Auth,
+    pub wallet: Option<String>,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+}
+
+impl RpcConfig {
+    /// Creates a config for the node at `host`, e.g. `http://127.0.0.1:18443`.
+    pub fn new(host: impl Into<String>) -> Self {
+        RpcConfig {
+            host: host.into(),
+            port: None,
+            auth: Auth::None,
+            wallet: None,
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Reads the config from the environment.
+    ///
+    /// `RPC_NETWORK` selects the network (default `main`) and `RPC_HOST`
+    /// overrides the node URL, which otherwise defaults to localhost on the
+    /// network's RPC port. Credentials come from `RPC_USER`/`RPC_PASSWORD`
+    /// when both are set, otherwise from `RPC_COOKIE_FILE`, otherwise from the
+    /// `.cookie` file in `RPC_DATADIR` or the default datadir. `RPC_WALLET`
+    /// optionally sets the default wallet.
+    pub fn from_env() -> Result<Self, Error> {
+        let network = match env_var("RPC_NETWORK") {
+            Some(network) => network.parse()?,
+            None => Network::default(),
+        };
+
+        let host = env_var("RPC_HOST")
+            .unwrap_or_else(|| format!("http://127.0.0.1:{}", network.default_rpc_port()));
+
+        let auth = match (env_var("RPC_USER"), env_var("RPC_PASSWORD")) {
+            (Some(user), Some(password)) => Auth::UserPass(user, password),
+            _ => match (env_var("RPC_COOKIE_FILE"), env_var("RPC_DATADIR")) {
+                (Some(path), _) => Auth::CookieFile(PathBuf::from(path)),
+                (_, Some(datadir)) => Auth::cookie(Path::new(&datadir), network),
+                _ => Auth::default_cookie(network)?,
+            },
+        };
+
+        let mut config = RpcConfig::new(host).auth(auth);
+        if let Some(wallet) = env_var("RPC_WALLET") {
+            config = config.wallet(wallet);
+        }
+
+        Ok(config)
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Sets the wallet used by [`RpcClient::call_wallet`].
+    pub fn wallet(mut self, wallet: impl Into<String>) -> Self {
+        self.wallet = Some(wallet.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    fn base_url(&self) -> String {
+        let host = self.host.trim_end_matches('/');
+        match self.port {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        }
+    }
+}
+
+/// A JSON-RPC client for Bitcoin Core.
+///
+/// The underlying HTTP client and the encoded credentials are created once
+/// and reused for every call, so connections are pooled across requests.
+#[derive(Debug)]
+pub struct RpcClient {
+    config: RpcConfig,
+    url: String,
+    auth: Arc<RwLock<Option<HeaderValue>>>,
+    http: ReqClient,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    pub fn new(config: RpcConfig) -> Result<Self, Error> {
+        let http = ReqClient::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+
+        Ok(RpcClient {
+            url: config.base_url(),
+            auth: Arc::new(RwLock::new(config.auth.header()?)),
+            config,
+            http,
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Creates a client configured from the environment, see [`RpcConfig::from_env`].
+    pub fn from_env() -> Result<Self, Error> {
+        RpcClient::new(RpcConfig::from_env()?)
+    }
+
+    pub fn config(&self) -> &RpcConfig {
+        &self.config
+    }
+
+    /// Returns a client that shares this client's connection pool but uses
+    /// `wallet` as its default wallet.
+    pub fn with_wallet(&self, wallet: impl Into<String>) -> RpcClient {
+        RpcClient {
+            config: self.config.clone().wallet(wallet),
+            url: self.url.clone(),
+            auth: Arc::clone(&self.auth),
+            http: self.http.clone(),
+            next_id: AtomicU64::new(self.next_id.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Calls a node-level RPC method and deserializes its `result`.
+    pub fn call<T: DeserializeOwned>(&self, method: &str, params: &Value) -> Result<T, Error> {
+        self.send(&self.url, method, params)
+    }
+
+    /// Calls an RPC method against the configured default wallet.
+    pub fn call_wallet<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &Value,
+    ) -> Result<T, Error> {
+        let wallet = self
+            .config
+            .wallet
+            .as_deref()
+            .ok_or_else(|| Error::Config("no default wallet configured".to_string()))?;
+        self.call_in_wallet(wallet, method, params)
+    }
+
+    /// Calls an RPC method against the named wallet, regardless of the default.
+    pub fn call_in_wallet<T: DeserializeOwned>(
+        &self,
+        wallet: &str,
+        method: &str,
+        params: &Value,
+    ) -> Result<T, Error> {
+        let url = format!("{}/wallet/{}", self.url, encode_wallet_name(wallet));
+        self.send(&url, method, params)
+    }
+
+    /// Asks the node which network it runs on.
+    pub fn network(&self) -> Result<Network, Error> {
+        let info: Value = self.call("getblockchaininfo", &json!([]))?;
+        info["chain"]
+            .as_str()
+            .ok_or_else(|| Error::Config("getblockchaininfo returned no chain".to_string()))?
+            .parse()
+    }
+
+    fn post(&self, url: &str, body: &Value) -> Result<reqwest::blocking::Response, Error> {
+        let mut request = self
+            .http
+            .post(url)
+            .header(CONTENT_TYPE, "text/plain")
+            .body(body.to_string());
+        if let Some(auth) = self.auth.read().unwrap().as_ref() {
+            request = request.header(AUTHORIZATION, auth.clone());
+        }
+
+        Ok(request.send()?)
+    }
+
+    fn send<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        method: &str,
+        params: &Value,
+    ) -> Result<T, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request_body = json!({
+            "jsonrpc": "1.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let mut response = self.post(url, &request_body)?;
+
+        // The node writes a new cookie every time it restarts, so a rejected
+        // cookie is re-read once before giving up.
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let Auth::CookieFile(_) = self.config.auth {
+                *self.auth.write().unwrap() = self.config.auth.header()?;
+                response = self.post(url, &request_body)?;
+            }
+        }
+
+        let status = response.status();
+        let body = response.text()?;
+
+        // Bitcoin Core answers RPC errors with HTTP 500 and a JSON-RPC error
+        // object, so the body is inspected before the status code.
+        match serde_json::from_str::<Value>(&body) {
+            Ok(response) if !response["error"].is_null() || status.is_success() => {
+                deserialize_response(response)
+            }
+            Err(e) if status.is_success() => Err(e.into()),
+            _ => Err(match status {
+                StatusCode::UNAUTHORIZED => Error::Unauthorized,
+                StatusCode::FORBIDDEN => Error::Forbidden,
+                status => Error::Http {
+                    status: status.as_u16(),
+                    body,
+                },
+            }),
+        }
+    }
+}
+
+/// Reads an environment variable, treating an empty value as unset.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Percent-encodes a wallet name for use in the `/wallet/<name>` endpoint.
+fn encode_wallet_name(wallet: &str) -> String {
+    let mut encoded = String::with_capacity(wallet.len());
+    for byte in wallet.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// This function is used to deserialize the result value response
+/// from Bitcoin Core.
+fn deserialize_response<T: DeserializeOwned>(mut response: Value) -> Result<T, Error> {
+    let error = response["error"].take();
+    if !error.is_null() {
+        let code = error["code"].as_i64().unwrap_or_default();
+        let message = error["message"].as_str().unwrap_or_default().to_string();
+        return Err(Error::Rpc {
+            code: RpcCode::from(code),
+            message,
+        });
+    }
+
+    Ok(serde_json::from_value(response["result"].take())?)
+}
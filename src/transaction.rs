@@ -0,0 +1,552 @@
+use crate::amount::Amount;
+use crate::encode::{self, write_compact_size, write_var_bytes, Reader};
+use crate::hash::sha256d;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A transaction id, stored in internal byte order and displayed reversed as
+/// Bitcoin Core does.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Txid(pub [u8; 32]);
+
+impl fmt::Display for Txid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = self.0;
+        bytes.reverse();
+        f.write_str(&hex::encode(bytes))
+    }
+}
+
+impl fmt::Debug for Txid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Txid({})", self)
+    }
+}
+
+impl Serialize for Txid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for Txid {
+    type Err = encode::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(s, &mut bytes)
+            .map_err(|_| encode::Error::InvalidData(format!("invalid txid: {}", s)))?;
+        bytes.reverse();
+        Ok(Txid(bytes))
+    }
+}
+
+/// A reference to an output of a previous transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl OutPoint {
+    pub fn new(txid: Txid, vout: u32) -> Self {
+        OutPoint { txid, vout }
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+/// Parses `txid:vout`.
+impl FromStr for OutPoint {
+    type Err = encode::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || encode::Error::InvalidData(format!("invalid outpoint: {}", s));
+        let (txid, vout) = s.split_once(':').ok_or_else(invalid)?;
+        Ok(OutPoint::new(
+            txid.parse()?,
+            vout.parse().map_err(|_| invalid())?,
+        ))
+    }
+}
+
+impl Serialize for OutPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OutPoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let outpoint = String::deserialize(deserializer)?;
+        outpoint.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A serialized script, displayed as hex.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Script(pub Vec<u8>);
+
+impl Script {
+    pub fn new() -> Self {
+        Script(Vec::new())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG`
+    pub fn is_p2pkh(&self) -> bool {
+        let s = &self.0;
+        s.len() == 25 && s[..3] == [0x76, 0xa9, 0x14] && s[23..] == [0x88, 0xac]
+    }
+
+    /// `OP_HASH160 <20 bytes> OP_EQUAL`
+    pub fn is_p2sh(&self) -> bool {
+        let s = &self.0;
+        s.len() == 23 && s[..2] == [0xa9, 0x14] && s[22] == 0x87
+    }
+
+    /// `OP_0 <20 bytes>`
+    pub fn is_p2wpkh(&self) -> bool {
+        self.0.len() == 22 && self.0[..2] == [0x00, 0x14]
+    }
+
+    /// `OP_0 <32 bytes>`
+    pub fn is_p2wsh(&self) -> bool {
+        self.0.len() == 34 && self.0[..2] == [0x00, 0x20]
+    }
+
+    /// `OP_1 <32 bytes>`
+    pub fn is_p2tr(&self) -> bool {
+        self.0.len() == 34 && self.0[..2] == [0x51, 0x20]
+    }
+
+    /// An unspendable `OP_RETURN` data output.
+    pub fn is_op_return(&self) -> bool {
+        self.0.first() == Some(&0x6a)
+    }
+
+    pub fn script_type(&self) -> ScriptType {
+        if self.is_p2pkh() {
+            ScriptType::P2pkh
+        } else if self.is_p2sh() {
+            ScriptType::P2sh
+        } else if self.is_p2wpkh() {
+            ScriptType::P2wpkh
+        } else if self.is_p2wsh() {
+            ScriptType::P2wsh
+        } else if self.is_p2tr() {
+            ScriptType::P2tr
+        } else if self.is_op_return() {
+            ScriptType::OpReturn
+        } else {
+            ScriptType::Nonstandard
+        }
+    }
+
+    /// The hash or key a standard output script commits to, e.g. the 20-byte
+    /// key hash of a P2PKH script.
+    pub fn payload(&self) -> Option<&[u8]> {
+        if self.is_p2pkh() {
+            Some(&self.0[3..23])
+        } else if self.is_p2sh() {
+            Some(&self.0[2..22])
+        } else if self.is_p2wpkh() || self.is_p2wsh() || self.is_p2tr() {
+            Some(&self.0[2..])
+        } else {
+            None
+        }
+    }
+
+    /// Appends a minimal push of `data`.
+    pub(crate) fn push_slice(&mut self, data: &[u8]) {
+        match data.len() {
+            0 => self.0.push(0x00),
+            len @ 1..=75 => self.0.push(len as u8),
+            len @ 76..=0xff => self.0.extend_from_slice(&[0x4c, len as u8]),
+            len @ 0x100..=0xffff => {
+                self.0.push(0x4d);
+                self.0.extend_from_slice(&(len as u16).to_le_bytes());
+            }
+            len => {
+                self.0.push(0x4e);
+                self.0.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+        self.0.extend_from_slice(data);
+    }
+}
+
+impl From<Vec<u8>> for Script {
+    fn from(bytes: Vec<u8>) -> Self {
+        Script(bytes)
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Script({})", self)
+    }
+}
+
+impl Serialize for Script {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Script {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let script = String::deserialize(deserializer)?;
+        hex::decode(&script)
+            .map(Script)
+            .map_err(|_| serde::de::Error::custom(format!("invalid script: {}", script)))
+    }
+}
+
+/// The standard type of an output script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    OpReturn,
+    Nonstandard,
+}
+
+impl fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScriptType::P2pkh => "p2pkh",
+            ScriptType::P2sh => "p2sh",
+            ScriptType::P2wpkh => "p2wpkh",
+            ScriptType::P2wsh => "p2wsh",
+            ScriptType::P2tr => "p2tr",
+            ScriptType::OpReturn => "op_return",
+            ScriptType::Nonstandard => "nonstandard",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TxIn {
+    pub previous_output: OutPoint,
+    pub script_sig: Script,
+    pub sequence: u32,
+    /// The witness stack, empty for non-segwit inputs.
+    pub witness: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TxOut {
+    pub value: Amount,
+    pub script_pubkey: Script,
+}
+
+impl TxOut {
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Self, encode::Error> {
+        let value = Amount::from_sat(reader.read_u64_le()?);
+        let script_pubkey = Script(reader.read_var_bytes()?.to_vec());
+        Ok(TxOut {
+            value,
+            script_pubkey,
+        })
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.value.to_sat().to_le_bytes());
+        write_var_bytes(out, self.script_pubkey.as_bytes());
+    }
+
+    /// Decodes a single consensus-encoded output.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let mut reader = Reader::new(bytes);
+        let txout = TxOut::decode(&mut reader)?;
+        reader.finish()?;
+        Ok(txout)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+/// A Bitcoin transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Transaction {
+    pub version: i32,
+    pub lock_time: u32,
+    pub input: Vec<TxIn>,
+    pub output: Vec<TxOut>,
+}
+
+impl Transaction {
+    /// Whether any input carries witness data.
+    pub fn has_witness(&self) -> bool {
+        self.input.iter().any(|input| !input.witness.is_empty())
+    }
+
+    /// Decodes a transaction, with or without witness data.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let mut reader = Reader::new(bytes);
+        let tx = Transaction::decode(&mut reader, true)?;
+        reader.finish()?;
+        Ok(tx)
+    }
+
+    /// Decodes a transaction that must use the legacy, witness-less format,
+    /// such as the unsigned transaction of a PSBT.
+    pub fn deserialize_no_witness(bytes: &[u8]) -> Result<Self, encode::Error> {
+        let mut reader = Reader::new(bytes);
+        let tx = Transaction::decode(&mut reader, false)?;
+        reader.finish()?;
+        Ok(tx)
+    }
+
+    /// Encodes the transaction, using the segwit format if it has witnesses.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out, self.has_witness());
+        out
+    }
+
+    /// Encodes the transaction without witness data.
+    pub fn serialize_no_witness(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out, false);
+        out
+    }
+
+    /// The transaction id, which does not commit to witness data.
+    pub fn txid(&self) -> Txid {
+        Txid(sha256d(&self.serialize_no_witness()))
+    }
+
+    /// The witness transaction id (BIP141), equal to the txid when the
+    /// transaction has no witnesses.
+    pub fn wtxid(&self) -> Txid {
+        Txid(sha256d(&self.serialize()))
+    }
+
+    /// The weight in weight units: three times the size without witnesses
+    /// plus the full size.
+    pub fn weight(&self) -> u64 {
+        let base = self.serialize_no_witness().len() as u64;
+        let total = self.serialize().len() as u64;
+        base * 3 + total
+    }
+
+    /// The virtual size in vbytes, the weight divided by four and rounded up.
+    pub fn vsize(&self) -> u64 {
+        self.weight().div_ceil(4)
+    }
+
+    pub(crate) fn decode(reader: &mut Reader, allow_witness: bool) -> Result<Self, encode::Error> {
+        let version = reader.read_i32_le()?;
+
+        let mut segwit = false;
+        if allow_witness && reader.peek_u8()? == 0 {
+            reader.read_u8()?;
+            match reader.read_u8()? {
+                1 => segwit = true,
+                flag => {
+                    return Err(encode::Error::InvalidData(format!(
+                        "unsupported segwit flag {}",
+                        flag
+                    )))
+                }
+            }
+        }
+
+        let input_count = reader.read_length()?;
+        let mut input = Vec::with_capacity(input_count);
+        for _ in 0..input_count {
+            let mut txid = [0u8; 32];
+            txid.copy_from_slice(reader.read_bytes(32)?);
+            let vout = reader.read_u32_le()?;
+            let script_sig = Script(reader.read_var_bytes()?.to_vec());
+            let sequence = reader.read_u32_le()?;
+            input.push(TxIn {
+                previous_output: OutPoint::new(Txid(txid), vout),
+                script_sig,
+                sequence,
+                witness: Vec::new(),
+            });
+        }
+
+        let output_count = reader.read_length()?;
+        let mut output = Vec::with_capacity(output_count);
+        for _ in 0..output_count {
+            output.push(TxOut::decode(reader)?);
+        }
+
+        if segwit {
+            for txin in &mut input {
+                txin.witness = decode_witness(reader)?;
+            }
+            if input.iter().all(|txin| txin.witness.is_empty()) {
+                return Err(encode::Error::InvalidData(
+                    "segwit transaction without witness data".to_string(),
+                ));
+            }
+        }
+
+        let lock_time = reader.read_u32_le()?;
+
+        Ok(Transaction {
+            version,
+            lock_time,
+            input,
+            output,
+        })
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>, with_witness: bool) {
+        out.extend_from_slice(&self.version.to_le_bytes());
+        if with_witness {
+            out.extend_from_slice(&[0, 1]);
+        }
+
+        write_compact_size(out, self.input.len() as u64);
+        for txin in &self.input {
+            out.extend_from_slice(&txin.previous_output.txid.0);
+            out.extend_from_slice(&txin.previous_output.vout.to_le_bytes());
+            write_var_bytes(out, txin.script_sig.as_bytes());
+            out.extend_from_slice(&txin.sequence.to_le_bytes());
+        }
+
+        write_compact_size(out, self.output.len() as u64);
+        for txout in &self.output {
+            txout.encode(out);
+        }
+
+        if with_witness {
+            for txin in &self.input {
+                encode_witness(out, &txin.witness);
+            }
+        }
+
+        out.extend_from_slice(&self.lock_time.to_le_bytes());
+    }
+}
+
+/// Decodes a witness stack: a count followed by that many byte strings.
+pub(crate) fn decode_witness(reader: &mut Reader) -> Result<Vec<Vec<u8>>, encode::Error> {
+    let count = reader.read_length()?;
+    let mut witness = Vec::with_capacity(count);
+    for _ in 0..count {
+        witness.push(reader.read_var_bytes()?.to_vec());
+    }
+    Ok(witness)
+}
+
+pub(crate) fn encode_witness(out: &mut Vec<u8>, witness: &[Vec<u8>]) {
+    write_compact_size(out, witness.len() as u64);
+    for item in witness {
+        write_var_bytes(out, item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The coinbase transaction of the genesis block.
+    const GENESIS_COINBASE: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+    /// The signed transaction of the native P2WPKH example in BIP143, with a
+    /// P2PK input and a P2WPKH input.
+    const BIP143_P2WPKH: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+    #[test]
+    fn legacy_transaction_ids_and_weight() {
+        let bytes = hex::decode(GENESIS_COINBASE).unwrap();
+        let tx = Transaction::deserialize(&bytes).unwrap();
+        assert!(!tx.has_witness());
+        assert_eq!(
+            tx.txid().to_string(),
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+        assert_eq!(tx.wtxid(), tx.txid());
+        assert_eq!(tx.weight(), 816);
+        assert_eq!(tx.vsize(), 204);
+        assert_eq!(tx.serialize(), bytes);
+        assert_eq!(tx.serialize_no_witness(), bytes);
+    }
+
+    #[test]
+    fn segwit_transaction_ids_and_weight() {
+        let bytes = hex::decode(BIP143_P2WPKH).unwrap();
+        let tx = Transaction::deserialize(&bytes).unwrap();
+        assert!(tx.has_witness());
+        assert!(tx.input[0].witness.is_empty());
+        assert_eq!(tx.input[1].witness.len(), 2);
+        assert_eq!(
+            tx.txid().to_string(),
+            "e8151a2af31c368a35053ddd4bdb285a8595c769a3ad83e0fa02314a602d4609"
+        );
+        assert_eq!(
+            tx.wtxid().to_string(),
+            "c36c38370907df2324d9ce9d149d191192f338b37665a82e78e76a12c909b762"
+        );
+        // 233 bytes without witnesses, 343 with them.
+        assert_eq!(tx.weight(), 233 * 3 + 343);
+        assert_eq!(tx.vsize(), 261);
+        assert_eq!(tx.serialize(), bytes);
+    }
+
+    #[test]
+    fn non_witness_serialization_round_trips() {
+        let tx = Transaction::deserialize(&hex::decode(BIP143_P2WPKH).unwrap()).unwrap();
+        let stripped = tx.serialize_no_witness();
+        assert_eq!(stripped.len(), 233);
+
+        let decoded = Transaction::deserialize_no_witness(&stripped).unwrap();
+        assert!(!decoded.has_witness());
+        assert_eq!(decoded.txid(), tx.txid());
+        assert_eq!(decoded.wtxid(), tx.txid());
+        assert_eq!(decoded.serialize(), stripped);
+        assert_eq!(Transaction::deserialize(&stripped).unwrap(), decoded);
+
+        let mut without_witnesses = tx.clone();
+        for txin in &mut without_witnesses.input {
+            txin.witness.clear();
+        }
+        assert_eq!(decoded, without_witnesses);
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut bytes = hex::decode(GENESIS_COINBASE).unwrap();
+        bytes.push(0);
+        assert!(Transaction::deserialize(&bytes).is_err());
+        assert!(Transaction::deserialize_no_witness(&bytes).is_err());
+    }
+}
@@ -0,0 +1,760 @@
+use crate::amount::{Amount, FeeRate};
+use crate::error::Error;
+use crate::psbt::Incomplete;
+use crate::transaction::{Script, ScriptType, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// An unspent output of the wallet, as returned by `listunspent`.
+///
+/// Fields Bitcoin Core leaves out for some outputs or versions are optional.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnspentTxOutputs {
+    pub txid: String,
+    pub vout: u32,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: String,
+    pub amount: Amount,
+    pub confirmations: u32,
+    pub spendable: bool,
+    pub solvable: bool,
+    #[serde(default)]
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub parent_descs: Vec<String>,
+    pub safe: bool,
+}
+
+impl UnspentTxOutputs {
+    /// The type of the output script, if it is valid hex.
+    pub fn script_type(&self) -> Option<ScriptType> {
+        hex::decode(&self.script_pub_key)
+            .ok()
+            .map(|script| Script::from(script).script_type())
+    }
+}
+
+/// Query options for `listunspent`, and filters applied to its result.
+///
+/// The node applies the confirmation, address and amount options, and
+/// [`matches`](Self::matches) checks them again along with the label,
+/// descriptor, script type and flag filters, after any `maximum_count` or
+/// `minimum_sum_amount` cut-off. Options left unset use the node's defaults or
+/// do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct ListUnspentOptions {
+    pub min_conf: Option<u32>,
+    pub max_conf: Option<u32>,
+    pub addresses: Vec<String>,
+    pub include_unsafe: Option<bool>,
+    pub minimum_amount: Option<Amount>,
+    pub maximum_amount: Option<Amount>,
+    pub maximum_count: Option<u32>,
+    /// Stop listing once the outputs add up to this amount.
+    pub minimum_sum_amount: Option<Amount>,
+    pub label: Option<String>,
+    /// Keep outputs with this descriptor or parent descriptor, compared
+    /// without checksums.
+    pub descriptor: Option<String>,
+    /// Keep outputs with any of these script types.
+    pub script_types: Vec<ScriptType>,
+    pub safe: Option<bool>,
+    pub spendable: Option<bool>,
+}
+
+impl ListUnspentOptions {
+    pub fn new() -> Self {
+        ListUnspentOptions::default()
+    }
+
+    pub fn min_conf(mut self, min_conf: u32) -> Self {
+        self.min_conf = Some(min_conf);
+        self
+    }
+
+    pub fn max_conf(mut self, max_conf: u32) -> Self {
+        self.max_conf = Some(max_conf);
+        self
+    }
+
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.addresses.push(address.into());
+        self
+    }
+
+    /// Whether to list unconfirmed outputs from others and replaced
+    /// transactions, which the node includes by default.
+    pub fn include_unsafe(mut self, include_unsafe: bool) -> Self {
+        self.include_unsafe = Some(include_unsafe);
+        self
+    }
+
+    pub fn minimum_amount(mut self, amount: Amount) -> Self {
+        self.minimum_amount = Some(amount);
+        self
+    }
+
+    pub fn maximum_amount(mut self, amount: Amount) -> Self {
+        self.maximum_amount = Some(amount);
+        self
+    }
+
+    pub fn maximum_count(mut self, count: u32) -> Self {
+        self.maximum_count = Some(count);
+        self
+    }
+
+    pub fn minimum_sum_amount(mut self, amount: Amount) -> Self {
+        self.minimum_sum_amount = Some(amount);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn descriptor(mut self, descriptor: impl Into<String>) -> Self {
+        self.descriptor = Some(descriptor.into());
+        self
+    }
+
+    pub fn script_type(mut self, script_type: ScriptType) -> Self {
+        self.script_types.push(script_type);
+        self
+    }
+
+    pub fn safe(mut self, safe: bool) -> Self {
+        self.safe = Some(safe);
+        self
+    }
+
+    pub fn spendable(mut self, spendable: bool) -> Self {
+        self.spendable = Some(spendable);
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if let (Some(min), Some(max)) = (self.min_conf, self.max_conf) {
+            if min > max {
+                return Err(Error::InvalidRequest(format!(
+                    "minimum confirmations {} exceed maximum {}",
+                    min, max
+                )));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.minimum_amount, self.maximum_amount) {
+            if min > max {
+                return Err(Error::InvalidRequest(format!(
+                    "minimum amount {} exceeds maximum {}",
+                    min, max
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The positional parameters of `listunspent`, filling the node's
+    /// defaults in before any option that is set.
+    pub(crate) fn to_json(&self) -> Value {
+        let mut query = json!({});
+        if let Some(amount) = self.minimum_amount {
+            query["minimumAmount"] = json!(amount);
+        }
+        if let Some(amount) = self.maximum_amount {
+            query["maximumAmount"] = json!(amount);
+        }
+        if let Some(count) = self.maximum_count {
+            query["maximumCount"] = json!(count);
+        }
+        if let Some(amount) = self.minimum_sum_amount {
+            query["minimumSumAmount"] = json!(amount);
+        }
+
+        let mut params = vec![
+            json!(self.min_conf.unwrap_or(1)),
+            json!(self.max_conf.unwrap_or(9_999_999)),
+            json!(self.addresses),
+            json!(self.include_unsafe.unwrap_or(true)),
+            query,
+        ];
+        // Trailing defaults are dropped.
+        let set = [
+            self.min_conf.is_some(),
+            self.max_conf.is_some(),
+            !self.addresses.is_empty(),
+            self.include_unsafe.is_some(),
+            params[4].as_object().is_some_and(|query| !query.is_empty()),
+        ];
+        params.truncate(set.iter().rposition(|&set| set).map_or(0, |last| last + 1));
+        Value::Array(params)
+    }
+
+    /// Whether `utxo` passes every filter except the node's default
+    /// confirmations and the count and sum cut-offs.
+    pub fn matches(&self, utxo: &UnspentTxOutputs) -> bool {
+        if self.min_conf.is_some_and(|min| utxo.confirmations < min)
+            || self.max_conf.is_some_and(|max| utxo.confirmations > max)
+            || self.minimum_amount.is_some_and(|min| utxo.amount < min)
+            || self.maximum_amount.is_some_and(|max| utxo.amount > max)
+        {
+            return false;
+        }
+        if !self.addresses.is_empty()
+            && !utxo.address.as_ref().is_some_and(|address| {
+                // Bech32 addresses may be written in upper case.
+                self.addresses
+                    .iter()
+                    .any(|wanted| wanted == address || wanted.to_ascii_lowercase() == *address)
+            })
+        {
+            return false;
+        }
+        if self
+            .label
+            .as_ref()
+            .is_some_and(|label| utxo.label.as_ref() != Some(label))
+        {
+            return false;
+        }
+        if let Some(descriptor) = &self.descriptor {
+            let descriptor = strip_checksum(descriptor);
+            let found = utxo
+                .desc
+                .iter()
+                .chain(&utxo.parent_descs)
+                .any(|desc| strip_checksum(desc) == descriptor);
+            if !found {
+                return false;
+            }
+        }
+        if !self.script_types.is_empty()
+            && !utxo
+                .script_type()
+                .is_some_and(|script_type| self.script_types.contains(&script_type))
+        {
+            return false;
+        }
+        self.safe.is_none_or(|safe| utxo.safe == safe)
+            && self
+                .spendable
+                .is_none_or(|spendable| utxo.spendable == spendable)
+    }
+}
+
+fn strip_checksum(descriptor: &str) -> &str {
+    descriptor
+        .split_once('#')
+        .map_or(descriptor, |(descriptor, _)| descriptor)
+}
+
+/// An outpoint to spend in a new PSBT.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Input {
+    pub txid: String,
+    pub vout: u32,
+}
+
+impl Input {
+    pub fn new(txid: impl Into<String>, vout: u32) -> Self {
+        Input {
+            txid: txid.into(),
+            vout,
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.txid.len() != 64 || !self.txid.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::InvalidRequest(format!(
+                "invalid txid: {}",
+                self.txid
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "txid": self.txid,
+            "vout": self.vout,
+        })
+    }
+}
+
+/// An output of a new PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recipient {
+    /// Pays `amount` to `address`.
+    Address { address: String, amount: Amount },
+    /// An `OP_RETURN` output carrying `data`.
+    Data(Vec<u8>),
+}
+
+impl Recipient {
+    pub fn address(address: impl Into<String>, amount: Amount) -> Self {
+        Recipient::Address {
+            address: address.into(),
+            amount,
+        }
+    }
+
+    pub fn data(data: impl Into<Vec<u8>>) -> Self {
+        Recipient::Data(data.into())
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            Recipient::Address { address, amount } => json!({ address.as_str(): amount }),
+            Recipient::Data(data) => json!({ "data": hex::encode(data) }),
+        }
+    }
+}
+
+/// A funded PSBT, as returned by `walletcreatefundedpsbt`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Psbt {
+    /// The base64-encoded PSBT.
+    pub psbt: String,
+    /// The fee the wallet added to the transaction.
+    pub fee: Amount,
+    /// The position of the change output, or -1 if there is none.
+    pub changepos: i32,
+}
+
+/// The result of `walletprocesspsbt`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalletProcessPsbt {
+    /// The base64-encoded PSBT with the wallet's signatures added.
+    pub psbt: String,
+    /// Whether the transaction has a complete set of signatures.
+    pub complete: bool,
+}
+
+/// The result of `finalizepsbt`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FinalizedPsbtResponse {
+    /// The hex-encoded network transaction, empty unless `complete`.
+    #[serde(default)]
+    pub hex: String,
+    /// The partially finalized PSBT, returned when it could not be completed.
+    pub psbt: Option<String>,
+    pub complete: bool,
+    /// Why inputs could not be finalized, only reported by the offline
+    /// finalizer.
+    #[serde(skip)]
+    pub incomplete: Vec<Incomplete>,
+}
+
+/// The result of `analyzepsbt`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnalyzedPsbt {
+    #[serde(default)]
+    pub inputs: Vec<AnalyzedInput>,
+    pub estimated_vsize: Option<u64>,
+    /// The estimated fee rate in BTC/kvB.
+    pub estimated_feerate: Option<Amount>,
+    pub fee: Option<Amount>,
+    /// The role that should act next: creator, updater, signer, finalizer or
+    /// extractor.
+    pub next: String,
+    pub error: Option<String>,
+}
+
+/// One input of an `analyzepsbt` result.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnalyzedInput {
+    pub has_utxo: bool,
+    pub is_final: bool,
+    pub missing: Option<MissingData>,
+    pub next: Option<String>,
+}
+
+/// What `analyzepsbt` reports an input is missing. Keys and scripts are
+/// identified by their hashes.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MissingData {
+    #[serde(default)]
+    pub pubkeys: Vec<String>,
+    #[serde(default)]
+    pub signatures: Vec<String>,
+    pub redeemscript: Option<String>,
+    pub witnessscript: Option<String>,
+}
+
+impl MissingData {
+    /// One line per missing item, e.g. `missing a signature for key hash ...`.
+    pub fn descriptions(&self) -> Vec<String> {
+        let mut descriptions = Vec::new();
+        for key_hash in &self.pubkeys {
+            descriptions.push(format!("missing the public key with hash {}", key_hash));
+        }
+        for key_hash in &self.signatures {
+            descriptions.push(format!("missing a signature for key hash {}", key_hash));
+        }
+        if let Some(hash) = &self.redeemscript {
+            descriptions.push(format!("missing the redeem script with hash {}", hash));
+        }
+        if let Some(hash) = &self.witnessscript {
+            descriptions.push(format!("missing the witness script with hash {}", hash));
+        }
+        descriptions
+    }
+}
+
+/// The result of `gettxout`, for an output that is still unspent.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TxOutResponse {
+    /// Zero for an output created by a transaction in the mempool.
+    pub confirmations: u32,
+    pub value: Amount,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: ScriptPubKey,
+    pub coinbase: bool,
+}
+
+/// An output script as the node describes it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptPubKey {
+    pub hex: Script,
+    /// Missing for scripts without an address, such as `OP_RETURN` outputs.
+    pub address: Option<String>,
+}
+
+/// A network transaction extracted from a finalized PSBT, with the values an
+/// operator records before broadcasting it.
+#[derive(Debug, Clone)]
+pub struct ExtractedTransaction {
+    pub tx: Transaction,
+    /// The hex-encoded transaction, as taken by `sendrawtransaction`.
+    pub hex: String,
+    pub txid: Txid,
+    pub wtxid: Txid,
+    pub weight: u64,
+    pub vsize: u64,
+    pub fee: Amount,
+    pub fee_rate: FeeRate,
+}
+
+/// How the node estimates a fee when only a confirmation target is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstimateMode {
+    Unset,
+    Economical,
+    Conservative,
+}
+
+impl EstimateMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EstimateMode::Unset => "unset",
+            EstimateMode::Economical => "economical",
+            EstimateMode::Conservative => "conservative",
+        }
+    }
+}
+
+/// The output type used for a change output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    Legacy,
+    P2shSegwit,
+    Bech32,
+    Bech32m,
+}
+
+impl ChangeType {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ChangeType::Legacy => "legacy",
+            ChangeType::P2shSegwit => "p2sh-segwit",
+            ChangeType::Bech32 => "bech32",
+            ChangeType::Bech32m => "bech32m",
+        }
+    }
+}
+
+/// Funding options for `walletcreatefundedpsbt`. Options left unset use the
+/// wallet's defaults.
+#[derive(Debug, Clone, Default)]
+pub struct FundingOptions {
+    pub add_inputs: Option<bool>,
+    pub change_address: Option<String>,
+    pub change_position: Option<u32>,
+    pub change_type: Option<ChangeType>,
+    pub include_watching: Option<bool>,
+    pub lock_unspents: Option<bool>,
+    pub fee_rate: Option<FeeRate>,
+    pub subtract_fee_from_outputs: Vec<usize>,
+    pub replaceable: Option<bool>,
+    pub conf_target: Option<u32>,
+    pub estimate_mode: Option<EstimateMode>,
+    pub locktime: Option<u32>,
+}
+
+impl FundingOptions {
+    pub fn new() -> Self {
+        FundingOptions::default()
+    }
+
+    /// Whether the wallet may add inputs beyond the ones given. Coinjoin
+    /// contributions should set this to `false`.
+    pub fn add_inputs(mut self, add_inputs: bool) -> Self {
+        self.add_inputs = Some(add_inputs);
+        self
+    }
+
+    pub fn change_address(mut self, address: impl Into<String>) -> Self {
+        self.change_address = Some(address.into());
+        self
+    }
+
+    pub fn change_position(mut self, position: u32) -> Self {
+        self.change_position = Some(position);
+        self
+    }
+
+    pub fn change_type(mut self, change_type: ChangeType) -> Self {
+        self.change_type = Some(change_type);
+        self
+    }
+
+    pub fn include_watching(mut self, include_watching: bool) -> Self {
+        self.include_watching = Some(include_watching);
+        self
+    }
+
+    pub fn lock_unspents(mut self, lock_unspents: bool) -> Self {
+        self.lock_unspents = Some(lock_unspents);
+        self
+    }
+
+    /// Sets an exact fee rate. Cannot be combined with a confirmation target.
+    pub fn fee_rate(mut self, fee_rate: FeeRate) -> Self {
+        self.fee_rate = Some(fee_rate);
+        self
+    }
+
+    /// Deducts the fee from the recipients at the given positions.
+    pub fn subtract_fee_from_outputs(mut self, outputs: Vec<usize>) -> Self {
+        self.subtract_fee_from_outputs = outputs;
+        self
+    }
+
+    /// Whether the transaction signals BIP125 replaceability.
+    pub fn replaceable(mut self, replaceable: bool) -> Self {
+        self.replaceable = Some(replaceable);
+        self
+    }
+
+    pub fn conf_target(mut self, blocks: u32) -> Self {
+        self.conf_target = Some(blocks);
+        self
+    }
+
+    pub fn estimate_mode(mut self, mode: EstimateMode) -> Self {
+        self.estimate_mode = Some(mode);
+        self
+    }
+
+    pub fn locktime(mut self, locktime: u32) -> Self {
+        self.locktime = Some(locktime);
+        self
+    }
+
+    pub(crate) fn validate(&self, inputs: &[Input], recipients: &[Recipient]) -> Result<(), Error> {
+        if self.add_inputs == Some(false) && inputs.is_empty() {
+            return Err(Error::InvalidRequest(
+                "inputs are required when the wallet may not add any".to_string(),
+            ));
+        }
+        if self.fee_rate.is_some() && (self.conf_target.is_some() || self.estimate_mode.is_some()) {
+            return Err(Error::InvalidRequest(
+                "a fee rate cannot be combined with a confirmation target or estimate mode"
+                    .to_string(),
+            ));
+        }
+        if self.change_address.is_some() && self.change_type.is_some() {
+            return Err(Error::InvalidRequest(
+                "a change address cannot be combined with a change type".to_string(),
+            ));
+        }
+        if let Some(position) = self.change_position {
+            if position as usize > recipients.len() {
+                return Err(Error::InvalidRequest(format!(
+                    "change position {} is out of bounds",
+                    position
+                )));
+            }
+        }
+        if let Some(&index) = self
+            .subtract_fee_from_outputs
+            .iter()
+            .find(|&&i| i >= recipients.len())
+        {
+            return Err(Error::InvalidRequest(format!(
+                "cannot subtract fee from missing output {}",
+                index
+            )));
+        }
+        Ok(())
+    }
+
+    /// The `options` object passed to `walletcreatefundedpsbt`.
+    pub(crate) fn to_json(&self) -> Value {
+        let mut options = json!({});
+        if let Some(add_inputs) = self.add_inputs {
+            options["add_inputs"] = json!(add_inputs);
+        }
+        if let Some(address) = &self.change_address {
+            options["changeAddress"] = json!(address);
+        }
+        if let Some(position) = self.change_position {
+            options["changePosition"] = json!(position);
+        }
+        if let Some(change_type) = self.change_type {
+            options["change_type"] = json!(change_type.as_str());
+        }
+        if let Some(include_watching) = self.include_watching {
+            options["includeWatching"] = json!(include_watching);
+        }
+        if let Some(lock_unspents) = self.lock_unspents {
+            options["lockUnspents"] = json!(lock_unspents);
+        }
+        if let Some(fee_rate) = self.fee_rate {
+            options["fee_rate"] = json!(fee_rate.to_sat_per_vb());
+        }
+        if !self.subtract_fee_from_outputs.is_empty() {
+            options["subtractFeeFromOutputs"] = json!(self.subtract_fee_from_outputs);
+        }
+        if let Some(replaceable) = self.replaceable {
+            options["replaceable"] = json!(replaceable);
+        }
+        if let Some(conf_target) = self.conf_target {
+            options["conf_target"] = json!(conf_target);
+        }
+        if let Some(mode) = self.estimate_mode {
+            options["estimate_mode"] = json!(mode.as_str());
+        }
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+
+    fn utxo(amount: u64, confirmations: u32) -> UnspentTxOutputs {
+        UnspentTxOutputs {
+            txid: "7b1eabe0209b1fe794124575ef807057c77ada2138ae4fa8d6c4de0398a14f3f".to_string(),
+            vout: 0,
+            address: Some(ADDRESS.to_string()),
+            label: Some("savings".to_string()),
+            script_pub_key: "0014751e76e8199196d454941c45d1b3a323f1433bd6".to_string(),
+            amount: Amount::from_sat(amount),
+            confirmations,
+            spendable: true,
+            solvable: true,
+            desc: Some("wpkh([d34db33f/84h/1h/0h/0/0]03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)#0s7ukjtd".to_string()),
+            parent_descs: vec![],
+            safe: true,
+        }
+    }
+
+    #[test]
+    fn listunspent_params_drop_trailing_defaults() {
+        let params = |options: ListUnspentOptions| options.to_json();
+        assert_eq!(params(ListUnspentOptions::new()), json!([]));
+        assert_eq!(params(ListUnspentOptions::new().min_conf(6)), json!([6]));
+        assert_eq!(
+            params(ListUnspentOptions::new().max_conf(100)),
+            json!([1, 100])
+        );
+        assert_eq!(
+            params(ListUnspentOptions::new().address(ADDRESS)),
+            json!([1, 9_999_999, [ADDRESS]])
+        );
+        assert_eq!(
+            params(ListUnspentOptions::new().include_unsafe(false)),
+            json!([1, 9_999_999, [], false])
+        );
+        assert_eq!(
+            params(
+                ListUnspentOptions::new()
+                    .min_conf(0)
+                    .minimum_amount(Amount::from_sat(100_000))
+                    .maximum_count(5)
+            ),
+            json!([0, 9_999_999, [], true, {"minimumAmount": 0.001, "maximumCount": 5}])
+        );
+        // Filters applied here are not sent to the node.
+        assert_eq!(
+            params(ListUnspentOptions::new().label("savings").safe(true)),
+            json!([])
+        );
+    }
+
+    #[test]
+    fn matches_amounts_and_confirmations() {
+        let options = ListUnspentOptions::new()
+            .min_conf(1)
+            .max_conf(10)
+            .minimum_amount(Amount::from_sat(1_000))
+            .maximum_amount(Amount::from_sat(2_000));
+        assert!(options.matches(&utxo(1_000, 1)));
+        assert!(options.matches(&utxo(2_000, 10)));
+        assert!(!options.matches(&utxo(999, 5)));
+        assert!(!options.matches(&utxo(2_001, 5)));
+        assert!(!options.matches(&utxo(1_500, 0)));
+        assert!(!options.matches(&utxo(1_500, 11)));
+
+        // Unset options do not filter, not even by the node's default
+        // confirmations.
+        assert!(ListUnspentOptions::new().matches(&utxo(1, 0)));
+    }
+
+    #[test]
+    fn matches_addresses() {
+        let utxo = utxo(1_000, 1);
+        assert!(ListUnspentOptions::new().address(ADDRESS).matches(&utxo));
+        assert!(ListUnspentOptions::new()
+            .address(ADDRESS.to_ascii_uppercase())
+            .matches(&utxo));
+        assert!(ListUnspentOptions::new()
+            .address("bcrt1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qzf4jry")
+            .address(ADDRESS)
+            .matches(&utxo));
+        assert!(!ListUnspentOptions::new()
+            .address("bcrt1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qzf4jry")
+            .matches(&utxo));
+
+        let mut without_address = utxo.clone();
+        without_address.address = None;
+        assert!(!ListUnspentOptions::new()
+            .address(ADDRESS)
+            .matches(&without_address));
+    }
+
+    #[test]
+    fn matches_labels_descriptors_and_flags() {
+        let utxo = utxo(1_000, 1);
+        assert!(ListUnspentOptions::new().label("savings").matches(&utxo));
+        assert!(!ListUnspentOptions::new().label("spending").matches(&utxo));
+        assert!(ListUnspentOptions::new()
+            .descriptor("wpkh([d34db33f/84h/1h/0h/0/0]03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)")
+            .matches(&utxo));
+        assert!(ListUnspentOptions::new()
+            .script_type(ScriptType::P2wpkh)
+            .matches(&utxo));
+        assert!(!ListUnspentOptions::new()
+            .script_type(ScriptType::P2tr)
+            .matches(&utxo));
+        assert!(ListUnspentOptions::new().safe(true).matches(&utxo));
+        assert!(!ListUnspentOptions::new().spendable(false).matches(&utxo));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::address;
+use crate::amount::Amount;
+use crate::error::Error;
+use crate::network::Network;
+use crate::psbt::PartiallySignedTransaction;
+use crate::transaction::{OutPoint, Script};
+use crate::types::Recipient;
+use std::fmt;
+
+/// What a participant agreed to before signing a joined PSBT.
+///
+/// The PSBT may only spend the listed inputs of the wallet, must contain every
+/// listed output unchanged, and may cost the wallet no more than the listed
+/// payments plus `max_fee`.
+#[derive(Debug, Clone, Default)]
+pub struct SigningPolicy {
+    pub inputs: Vec<OutPoint>,
+    pub outputs: Vec<Recipient>,
+    /// The most the wallet contributes to the fee.
+    pub max_fee: Amount,
+}
+
+impl SigningPolicy {
+    pub fn new(max_fee: Amount) -> Self {
+        SigningPolicy {
+            max_fee,
+            ..SigningPolicy::default()
+        }
+    }
+
+    /// Allows spending `outpoint`, one of the wallet's outputs.
+    pub fn input(mut self, outpoint: OutPoint) -> Self {
+        self.inputs.push(outpoint);
+        self
+    }
+
+    /// Requires an output matching `recipient`.
+    pub fn output(mut self, recipient: Recipient) -> Self {
+        self.outputs.push(recipient);
+        self
+    }
+}
+
+/// A reason a PSBT does not match its [`SigningPolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// An input's UTXO is missing, so its owner and value are unknown.
+    UnknownInput(OutPoint),
+    /// The PSBT spends an output of the wallet that was not agreed on.
+    UnexpectedInput(OutPoint),
+    /// A requested output is missing or was changed.
+    MissingOutput(Recipient),
+    /// The wallet would lose more than the payments plus the maximum fee.
+    FeeTooHigh { spent: Amount, allowed: Amount },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::UnknownInput(outpoint) => {
+                write!(f, "input {} has no UTXO to check", outpoint)
+            }
+            Violation::UnexpectedInput(outpoint) => {
+                write!(f, "input {} of this wallet was not agreed on", outpoint)
+            }
+            Violation::MissingOutput(Recipient::Address { address, amount }) => {
+                write!(f, "output paying {} to {} is missing", amount, address)
+            }
+            Violation::MissingOutput(Recipient::Data(data)) => {
+                write!(f, "data output {} is missing", hex::encode(data))
+            }
+            Violation::FeeTooHigh { spent, allowed } => write!(
+                f,
+                "wallet would lose {} but at most {} was agreed",
+                spent, allowed
+            ),
+        }
+    }
+}
+
+/// Checks `psbt` against `policy`, returning every violation found.
+///
+/// `is_mine` tells whether an output script belongs to the wallet, and
+/// `network` is used to compare the requested addresses.
+pub fn verify(
+    psbt: &PartiallySignedTransaction,
+    policy: &SigningPolicy,
+    network: Network,
+    mut is_mine: impl FnMut(&Script) -> Result<bool, Error>,
+) -> Result<Vec<Violation>, Error> {
+    let tx = &psbt.unsigned_tx;
+    let mut violations = Vec::new();
+
+    let mut ours_in = Amount::ZERO;
+    for (txin, input) in tx.input.iter().zip(&psbt.inputs) {
+        let Some(spent) = input.spent_output(&txin.previous_output) else {
+            violations.push(Violation::UnknownInput(txin.previous_output));
+            continue;
+        };
+        if is_mine(&spent.script_pubkey)? {
+            if !policy.inputs.contains(&txin.previous_output) {
+                violations.push(Violation::UnexpectedInput(txin.previous_output));
+            }
+            ours_in = saturating_add(ours_in, spent.value);
+        }
+    }
+
+    let mut ours_out = Amount::ZERO;
+    let mut owned = Vec::with_capacity(tx.output.len());
+    for txout in &tx.output {
+        let mine = is_mine(&txout.script_pubkey)?;
+        if mine {
+            ours_out = saturating_add(ours_out, txout.value);
+        }
+        owned.push(mine);
+    }
+
+    // Each requested output claims a distinct matching output, so a request
+    // made twice needs two outputs.
+    let mut matched = vec![false; tx.output.len()];
+    let mut payments = Amount::ZERO;
+    for recipient in &policy.outputs {
+        let found = tx.output.iter().enumerate().position(|(index, txout)| {
+            !matched[index]
+                && matches_recipient(&txout.script_pubkey, txout.value, recipient, network)
+        });
+        match found {
+            Some(index) => {
+                matched[index] = true;
+                if !owned[index] {
+                    payments = saturating_add(payments, tx.output[index].value);
+                }
+            }
+            None => violations.push(Violation::MissingOutput(recipient.clone())),
+        }
+    }
+
+    let allowed = saturating_add(payments, policy.max_fee);
+    if let Some(spent) = ours_in.checked_sub(ours_out) {
+        if spent > allowed {
+            violations.push(Violation::FeeTooHigh { spent, allowed });
+        }
+    }
+
+    Ok(violations)
+}
+
+fn matches_recipient(
+    script_pubkey: &Script,
+    value: Amount,
+    recipient: &Recipient,
+    network: Network,
+) -> bool {
+    match recipient {
+        Recipient::Address { address, amount } => {
+            value == *amount
+                && address::from_script(script_pubkey, network).is_some_and(|encoded| {
+                    // Bech32 addresses may be written in upper case.
+                    encoded == *address || encoded == address.to_ascii_lowercase()
+                })
+        }
+        Recipient::Data(data) => {
+            let mut expected = Script(vec![0x6a]);
+            expected.push_slice(data);
+            value == Amount::ZERO && *script_pubkey == expected
+        }
+    }
+}
+
+fn saturating_add(a: Amount, b: Amount) -> Amount {
+    a.checked_add(b).unwrap_or(Amount::MAX_MONEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{Transaction, TxIn, TxOut, Txid};
+
+    fn script(byte: u8) -> Script {
+        Script([[0x00, 0x14].as_slice(), &[byte; 20]].concat())
+    }
+
+    fn outpoint(byte: u8) -> OutPoint {
+        OutPoint::new(Txid([byte; 32]), 0)
+    }
+
+    fn txout(byte: u8, sat: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(sat),
+            script_pubkey: script(byte),
+        }
+    }
+
+    const OURS: u8 = 1;
+    const CHANGE: u8 = 2;
+    const UNLISTED: u8 = 3;
+    const RECIPIENT: u8 = 4;
+    const OTHER: u8 = 5;
+
+    /// A PSBT spending `(script, value)` inputs into `(script, value)` outputs.
+    fn joined(inputs: &[(u8, u64)], outputs: &[(u8, u64)]) -> PartiallySignedTransaction {
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: inputs
+                .iter()
+                .map(|&(byte, _)| TxIn {
+                    previous_output: outpoint(byte),
+                    script_sig: Script::new(),
+                    sequence: 0xffff_fffd,
+                    witness: Vec::new(),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|&(byte, sat)| txout(byte, sat))
+                .collect(),
+        })
+        .unwrap();
+        for (input, &(byte, sat)) in psbt.inputs.iter_mut().zip(inputs) {
+            input.witness_utxo = Some(txout(byte, sat));
+        }
+        psbt
+    }
+
+    /// The wallet spends 60,000 sat, pays 30,000 sat to the recipient and gets
+    /// 29,000 sat change, while another participant spends 50,000 sat into
+    /// 49,000 sat.
+    fn coinjoin() -> PartiallySignedTransaction {
+        joined(
+            &[(OURS, 60_000), (OTHER, 50_000)],
+            &[(RECIPIENT, 30_000), (CHANGE, 29_000), (OTHER, 49_000)],
+        )
+    }
+
+    fn recipient(sat: u64) -> Recipient {
+        let address = address::from_script(&script(RECIPIENT), Network::Regtest).unwrap();
+        Recipient::address(address, Amount::from_sat(sat))
+    }
+
+    fn policy(max_fee: u64) -> SigningPolicy {
+        SigningPolicy::new(Amount::from_sat(max_fee))
+            .input(outpoint(OURS))
+            .output(recipient(30_000))
+    }
+
+    fn check(psbt: &PartiallySignedTransaction, policy: &SigningPolicy) -> Vec<Violation> {
+        verify(psbt, policy, Network::Regtest, |script_pubkey| {
+            Ok([OURS, CHANGE, UNLISTED]
+                .iter()
+                .any(|&byte| *script_pubkey == script(byte)))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn agreed_psbt_passes() {
+        assert_eq!(check(&coinjoin(), &policy(1_000)), vec![]);
+
+        // The recipient's address may be written in upper case.
+        let mut policy = policy(1_000);
+        if let Recipient::Address { address, .. } = &mut policy.outputs[0] {
+            *address = address.to_ascii_uppercase();
+        }
+        assert_eq!(check(&coinjoin(), &policy), vec![]);
+    }
+
+    #[test]
+    fn unlisted_wallet_input_is_unexpected() {
+        // The extra input comes back as change, so only the input itself is
+        // a violation.
+        let psbt = joined(
+            &[(OURS, 60_000), (UNLISTED, 10_000), (OTHER, 50_000)],
+            &[(RECIPIENT, 30_000), (CHANGE, 39_000), (OTHER, 49_000)],
+        );
+        assert_eq!(
+            check(&psbt, &policy(1_000)),
+            vec![Violation::UnexpectedInput(outpoint(UNLISTED))]
+        );
+    }
+
+    #[test]
+    fn input_without_utxo_is_unknown() {
+        let mut psbt = coinjoin();
+        psbt.inputs[1].witness_utxo = None;
+        assert_eq!(
+            check(&psbt, &policy(1_000)),
+            vec![Violation::UnknownInput(outpoint(OTHER))]
+        );
+    }
+
+    #[test]
+    fn missing_or_underpaid_outputs() {
+        let missing = joined(
+            &[(OURS, 60_000), (OTHER, 50_000)],
+            &[(CHANGE, 59_000), (OTHER, 49_000)],
+        );
+        assert_eq!(
+            check(&missing, &policy(1_000)),
+            vec![Violation::MissingOutput(recipient(30_000))]
+        );
+
+        // The underpaid output is not counted as a payment, so the wallet
+        // also loses more than it agreed to.
+        let underpaid = joined(
+            &[(OURS, 60_000), (OTHER, 50_000)],
+            &[(RECIPIENT, 29_999), (CHANGE, 29_001), (OTHER, 49_000)],
+        );
+        assert_eq!(
+            check(&underpaid, &policy(1_000)),
+            vec![
+                Violation::MissingOutput(recipient(30_000)),
+                Violation::FeeTooHigh {
+                    spent: Amount::from_sat(30_999),
+                    allowed: Amount::from_sat(1_000),
+                },
+            ]
+        );
+
+        let data = Recipient::data(b"hello".to_vec());
+        assert_eq!(
+            check(&coinjoin(), &policy(1_000).output(data.clone())),
+            vec![Violation::MissingOutput(data)]
+        );
+    }
+
+    #[test]
+    fn requested_twice_needs_two_outputs() {
+        let policy = policy(31_000).output(recipient(30_000));
+        assert_eq!(
+            check(&coinjoin(), &policy),
+            vec![Violation::MissingOutput(recipient(30_000))]
+        );
+    }
+
+    #[test]
+    fn fee_over_the_limit() {
+        assert_eq!(
+            check(&coinjoin(), &policy(999)),
+            vec![Violation::FeeTooHigh {
+                spent: Amount::from_sat(31_000),
+                allowed: Amount::from_sat(30_999),
+            }]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::error::Error;
+use crate::rpc::RpcClient;
+use crate::types::ChangeType;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Response of `loadwallet` and `createwallet`.
+#[derive(Debug, Deserialize)]
+pub struct LoadWalletResponse {
+    pub name: String,
+    #[serde(default, deserialize_with = "deserialize_warnings", alias = "warning")]
+    pub warnings: Vec<String>,
+}
+
+/// Options for `createwallet`. The defaults create a descriptor wallet with
+/// private keys enabled, matching Bitcoin Core's defaults.
+#[derive(Debug, Clone)]
+pub struct CreateWalletOptions {
+    pub disable_private_keys: bool,
+    pub blank: bool,
+    pub passphrase: Option<String>,
+    pub avoid_reuse: bool,
+    pub descriptors: bool,
+    pub load_on_startup: Option<bool>,
+}
+
+impl Default for CreateWalletOptions {
+    fn default() -> Self {
+        CreateWalletOptions {
+            disable_private_keys: false,
+            blank: false,
+            passphrase: None,
+            avoid_reuse: false,
+            descriptors: true,
+            load_on_startup: None,
+        }
+    }
+}
+
+impl CreateWalletOptions {
+    /// A wallet without private keys, e.g. to import watch-only descriptors.
+    pub fn watch_only() -> Self {
+        CreateWalletOptions {
+            disable_private_keys: true,
+            blank: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn disable_private_keys(mut self, disable: bool) -> Self {
+        self.disable_private_keys = disable;
+        self
+    }
+
+    /// A blank wallet has no keys or HD seed until some are imported.
+    pub fn blank(mut self, blank: bool) -> Self {
+        self.blank = blank;
+        self
+    }
+
+    pub fn passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    pub fn avoid_reuse(mut self, avoid_reuse: bool) -> Self {
+        self.avoid_reuse = avoid_reuse;
+        self
+    }
+
+    /// Creates a legacy wallet when `false`.
+    pub fn descriptors(mut self, descriptors: bool) -> Self {
+        self.descriptors = descriptors;
+        self
+    }
+
+    pub fn load_on_startup(mut self, load_on_startup: bool) -> Self {
+        self.load_on_startup = Some(load_on_startup);
+        self
+    }
+}
+
+/// What the wallet knows about an address, as returned by `getaddressinfo`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressInfo {
+    pub address: String,
+    /// Whether the wallet can spend outputs paying to the address.
+    pub ismine: bool,
+    #[serde(default)]
+    pub iswatchonly: bool,
+    /// Whether the address was handed out as a change address.
+    #[serde(default)]
+    pub ischange: bool,
+    pub desc: Option<String>,
+}
+
+/// Lists the wallets currently loaded by the node.
+pub fn list_wallets(client: &RpcClient) -> Result<Vec<String>, Error> {
+    client.call("listwallets", &json!([]))
+}
+
+/// Loads a wallet from the node's wallet directory.
+pub fn load_wallet(client: &RpcClient, name: &str) -> Result<LoadWalletResponse, Error> {
+    client.call("loadwallet", &json!([name]))
+}
+
+/// Unloads the named wallet.
+pub fn unload_wallet(client: &RpcClient, name: &str) -> Result<(), Error> {
+    let _: Value = client.call("unloadwallet", &json!([name]))?;
+    Ok(())
+}
+
+/// Creates and loads a new wallet.
+pub fn create_wallet(
+    client: &RpcClient,
+    name: &str,
+    options: &CreateWalletOptions,
+) -> Result<LoadWalletResponse, Error> {
+    let mut params = json!({
+        "wallet_name": name,
+        "disable_private_keys": options.disable_private_keys,
+        "blank": options.blank,
+        "passphrase": options.passphrase.as_deref().unwrap_or_default(),
+        "avoid_reuse": options.avoid_reuse,
+        "descriptors": options.descriptors,
+    });
+    if let Some(load_on_startup) = options.load_on_startup {
+        params["load_on_startup"] = json!(load_on_startup);
+    }
+
+    client.call("createwallet", &params)
+}
+
+/// Looks up what the wallet knows about `address`.
+pub fn get_address_info(client: &RpcClient, address: &str) -> Result<AddressInfo, Error> {
+    client.call_wallet("getaddressinfo", &json!([address]))
+}
+
+/// Returns a new receiving address, of the wallet's default type unless
+/// `address_type` is given.
+pub fn get_new_address(
+    client: &RpcClient,
+    address_type: Option<ChangeType>,
+) -> Result<String, Error> {
+    let params = match address_type {
+        Some(address_type) => json!(["", address_type.as_str()]),
+        None => json!([]),
+    };
+    client.call_wallet("getnewaddress", &params)
+}
+
+/// Returns a new change address, of the wallet's default change type unless
+/// `address_type` is given.
+pub fn get_raw_change_address(
+    client: &RpcClient,
+    address_type: Option<ChangeType>,
+) -> Result<String, Error> {
+    let params = match address_type {
+        Some(address_type) => json!([address_type.as_str()]),
+        None => json!([]),
+    };
+    client.call_wallet("getrawchangeaddress", &params)
+}
+
+/// Older nodes return a single `warning` string, newer ones a `warnings` array.
+fn deserialize_warnings<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Warnings {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<Warnings>::deserialize(deserializer)? {
+        Some(Warnings::One(warning)) if !warning.is_empty() => vec![warning],
+        Some(Warnings::Many(warnings)) => warnings,
+        _ => Vec::new(),
+    })
+}
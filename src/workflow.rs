@@ -0,0 +1,556 @@
+use crate::address;
+use crate::amount::{Amount, FeeRate};
+use crate::coin_selection::{self, Algorithm, CoinSelectionParams, Selection};
+use crate::coinjoin::Participant;
+use crate::error::{Error, RpcCode};
+use crate::inspect::PsbtSummary;
+use crate::psbt::{self, JoinOrder, PartiallySignedTransaction};
+use crate::rpc::RpcClient;
+use crate::transaction::{OutPoint, Txid};
+use crate::types::{
+    AnalyzedPsbt, ChangeType, ExtractedTransaction, FinalizedPsbtResponse, FundingOptions, Input,
+    ListUnspentOptions, Psbt, Recipient, TxOutResponse, UnspentTxOutputs, WalletProcessPsbt,
+};
+use crate::verify::{self, SigningPolicy};
+use crate::wallet::{get_address_info, get_new_address, get_raw_change_address};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Lists the unspent outputs of the client's default wallet.
+pub fn list_unspent(client: &RpcClient) -> Result<Vec<UnspentTxOutputs>, Error> {
+    list_unspent_with_options(client, &ListUnspentOptions::default())
+}
+
+/// Lists the unspent outputs like [`list_unspent`], with query options for
+/// the node and filters applied to its result.
+pub fn list_unspent_with_options(
+    client: &RpcClient,
+    options: &ListUnspentOptions,
+) -> Result<Vec<UnspentTxOutputs>, Error> {
+    options.validate()?;
+    let mut utxos: Vec<UnspentTxOutputs> = client.call_wallet("listunspent", &options.to_json())?;
+    utxos.retain(|utxo| options.matches(utxo));
+    Ok(utxos)
+}
+
+/// Selects which of the wallet's unspent outputs fund `params` with
+/// `algorithm`, for passing to [`create_psbt`] with `add_inputs` disabled.
+pub fn select_inputs(
+    client: &RpcClient,
+    params: &CoinSelectionParams,
+    algorithm: Algorithm,
+) -> Result<Selection, Error> {
+    let options = ListUnspentOptions::new().min_conf(params.min_confirmations);
+    let utxos = list_unspent_with_options(client, &options)?;
+    coin_selection::select_coins(&utxos, params, algorithm)
+}
+
+/// Describes what the wallet brings to a coinjoin: its spendable outputs
+/// matching `options`, and fresh mix and change addresses of `address_type`.
+pub fn coinjoin_participant(
+    client: &RpcClient,
+    options: &ListUnspentOptions,
+    address_type: Option<ChangeType>,
+) -> Result<Participant, Error> {
+    let utxos = list_unspent_with_options(client, &options.clone().spendable(true))?;
+    if utxos.is_empty() {
+        return Err(Error::InvalidRequest(
+            "the wallet has no spendable outputs to mix".to_string(),
+        ));
+    }
+
+    Ok(Participant {
+        utxos,
+        mix_address: get_new_address(client, address_type)?,
+        change_address: get_raw_change_address(client, address_type)?,
+    })
+}
+
+/// Creates a PSBT and returns the newly created PSBT.
+///
+/// The wallet adds inputs as needed to fund the recipients, so `inputs` may be
+/// empty to let Bitcoin Core select them all.
+pub fn create_psbt(
+    client: &RpcClient,
+    inputs: &[Input],
+    recipients: &[Recipient],
+) -> Result<Psbt, Error> {
+    create_psbt_with_options(client, inputs, recipients, &FundingOptions::default())
+}
+
+/// Creates a PSBT like [`create_psbt`], with control over how the wallet
+/// funds it.
+pub fn create_psbt_with_options(
+    client: &RpcClient,
+    inputs: &[Input],
+    recipients: &[Recipient],
+    options: &FundingOptions,
+) -> Result<Psbt, Error> {
+    validate_psbt_request(inputs, recipients)?;
+    options.validate(inputs, recipients)?;
+
+    let utxos: Vec<Value> = inputs.iter().map(Input::to_json).collect();
+    let outputs: Vec<Value> = recipients.iter().map(Recipient::to_json).collect();
+
+    let body = json!([
+        utxos,
+        outputs,
+        options.locktime.unwrap_or(0),
+        options.to_json()
+    ]);
+
+    client.call_wallet("walletcreatefundedpsbt", &body)
+}
+
+/// Checks the inputs and recipients for mistakes the node would reject.
+fn validate_psbt_request(inputs: &[Input], recipients: &[Recipient]) -> Result<(), Error> {
+    let mut outpoints = HashSet::new();
+    for input in inputs {
+        input.validate()?;
+        let txid: Txid = input
+            .txid
+            .parse()
+            .map_err(|_| Error::InvalidRequest(format!("invalid txid: {}", input.txid)))?;
+        let outpoint = OutPoint::new(txid, input.vout);
+        if !outpoints.insert(outpoint) {
+            return Err(Error::InvalidRequest(format!(
+                "input {} is spent twice",
+                outpoint
+            )));
+        }
+    }
+
+    if recipients.is_empty() {
+        return Err(Error::InvalidRequest(
+            "at least one recipient is required".to_string(),
+        ));
+    }
+
+    let mut addresses = HashSet::new();
+    let mut has_data = false;
+    for recipient in recipients {
+        match recipient {
+            Recipient::Address { address, amount } => {
+                if address.is_empty() {
+                    return Err(Error::InvalidRequest(
+                        "recipient address is empty".to_string(),
+                    ));
+                }
+                if *amount == Amount::ZERO || *amount > Amount::MAX_MONEY {
+                    return Err(Error::InvalidRequest(format!(
+                        "invalid amount for {}: {}",
+                        address, amount
+                    )));
+                }
+                if !addresses.insert(address.as_str()) {
+                    return Err(Error::InvalidRequest(format!(
+                        "duplicate recipient address: {}",
+                        address
+                    )));
+                }
+            }
+            Recipient::Data(_) => {
+                if has_data {
+                    return Err(Error::InvalidRequest(
+                        "only one data output is allowed".to_string(),
+                    ));
+                }
+                has_data = true;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Joins multiple PSBTs into a single large PSBT.
+///
+/// Each PSBT is parsed first, so a malformed contribution, one whose signatures
+/// or modifiable flags forbid adding inputs and outputs, or an input spent by
+/// more than one participant is reported before joining.
+pub fn join_psbt(client: &RpcClient, psbts: Vec<String>) -> Result<String, Error> {
+    validate_contributions(&psbts)?;
+
+    let body = json!([psbts]);
+
+    client.call("joinpsbts", &body)
+}
+
+/// Joins multiple PSBTs without a node, ordering the inputs and outputs as
+/// `order` says instead of the node's shuffle.
+pub fn join_psbt_offline(psbts: &[String], order: JoinOrder) -> Result<String, Error> {
+    let psbts = validate_contributions(psbts)?;
+
+    Ok(psbt::join(&psbts, order)?.to_base64())
+}
+
+/// Parses every contribution, checking that it can take more inputs and
+/// outputs and that no input is shared between them.
+fn validate_contributions(psbts: &[String]) -> Result<Vec<PartiallySignedTransaction>, Error> {
+    if psbts.len() < 2 {
+        return Err(Error::InvalidRequest(
+            "at least two PSBTs are required to join".to_string(),
+        ));
+    }
+
+    let mut parsed = Vec::with_capacity(psbts.len());
+    let mut spent_by: HashMap<OutPoint, usize> = HashMap::new();
+    for (index, psbt) in psbts.iter().enumerate() {
+        let psbt = PartiallySignedTransaction::from_base64(psbt)
+            .map_err(|e| Error::InvalidRequest(format!("PSBT {} is malformed: {}", index, e)))?;
+
+        if !psbt.inputs_modifiable() || !psbt.outputs_modifiable() {
+            return Err(Error::InvalidRequest(format!(
+                "PSBT {} does not allow adding inputs and outputs",
+                index
+            )));
+        }
+        for input in &psbt.unsigned_tx.input {
+            if let Some(other) = spent_by.insert(input.previous_output, index) {
+                return Err(Error::InvalidRequest(format!(
+                    "input {} is spent by both PSBT {} and PSBT {}",
+                    input.previous_output, other, index
+                )));
+            }
+        }
+        parsed.push(psbt);
+    }
+
+    Ok(parsed)
+}
+
+/// This function is used to sign the Joined PSBT.
+pub fn wallet_process_psbt(client: &RpcClient, psbt: String) -> Result<WalletProcessPsbt, Error> {
+    let body = json!([psbt]);
+
+    client.call_wallet("walletprocesspsbt", &body)
+}
+
+/// Signs the wallet's inputs like [`wallet_process_psbt`], but only after
+/// checking the PSBT against `policy`. A PSBT that spends other inputs of the
+/// wallet, drops or changes a requested output, or costs the wallet more than
+/// agreed is refused with every problem listed.
+///
+/// Without `finalize` the signatures stay partial signatures, which a node
+/// can still verify when finalizing.
+pub fn wallet_process_psbt_verified(
+    client: &RpcClient,
+    psbt: String,
+    policy: &SigningPolicy,
+    finalize: bool,
+) -> Result<WalletProcessPsbt, Error> {
+    let parsed = PartiallySignedTransaction::from_base64(&psbt)?;
+    let network = client.network()?;
+    let violations = verify::verify(
+        &parsed,
+        policy,
+        network,
+        |script| match address::from_script(script, network) {
+            Some(address) => Ok(get_address_info(client, &address)?.ismine),
+            None => Ok(false),
+        },
+    )?;
+    if !violations.is_empty() {
+        return Err(Error::UnsafeToSign(violations));
+    }
+
+    if finalize {
+        return wallet_process_psbt(client, psbt);
+    }
+    // Signing with the default sighash type and BIP32 derivations.
+    let body = json!([psbt, true, null, true, false]);
+    client.call_wallet("walletprocesspsbt", &body)
+}
+
+/// Combines all signatures and input information into the same PSBT
+pub fn combine_psbt(client: &RpcClient, psbts: Vec<String>) -> Result<String, Error> {
+    let body = json!([psbts]);
+
+    client.call("combinepsbt", &body)
+}
+
+/// Combines copies of the same PSBT without a node, following the BIP174
+/// combiner rules. Fails listing every key whose values differ between copies.
+pub fn combine_psbt_offline(psbts: &[String]) -> Result<String, Error> {
+    let psbts = psbts
+        .iter()
+        .map(|psbt| PartiallySignedTransaction::from_base64(psbt))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(psbt::combine(&psbts)?.to_base64())
+}
+
+/// Finalizes the PSBT and creates a raw network transaction ready to be broadcasted.
+pub fn finalize_psbt(client: &RpcClient, psbt: String) -> Result<FinalizedPsbtResponse, Error> {
+    let body = json!([psbt]);
+
+    client.call("finalizepsbt", &body)
+}
+
+/// Finalizes the PSBT without a node, for P2PKH, P2WPKH, P2SH-P2WPKH,
+/// multisig and taproot key path inputs.
+///
+/// Like `finalizepsbt`, the result holds the network transaction when every
+/// input could be finalized and the partially finalized PSBT otherwise, with
+/// `incomplete` listing what each remaining input is missing.
+pub fn finalize_psbt_offline(psbt: String) -> Result<FinalizedPsbtResponse, Error> {
+    let mut psbt = PartiallySignedTransaction::from_base64(&psbt)?;
+    let incomplete = psbt.finalize();
+
+    if incomplete.is_empty() {
+        Ok(FinalizedPsbtResponse {
+            hex: hex::encode(psbt.extract_tx()?.serialize()),
+            psbt: None,
+            complete: true,
+            incomplete,
+        })
+    } else {
+        Ok(FinalizedPsbtResponse {
+            hex: String::new(),
+            psbt: Some(psbt.to_base64()),
+            complete: false,
+            incomplete,
+        })
+    }
+}
+
+/// Extracts the network transaction from a finalized PSBT without a node,
+/// computing its txid, wtxid, size and fee.
+pub fn extract_transaction(psbt: String) -> Result<ExtractedTransaction, Error> {
+    let psbt = PartiallySignedTransaction::from_base64(&psbt)?;
+    let tx = psbt.extract_tx()?;
+    let fee = psbt.fee()?;
+    let vsize = tx.vsize();
+
+    Ok(ExtractedTransaction {
+        hex: hex::encode(tx.serialize()),
+        txid: tx.txid(),
+        wtxid: tx.wtxid(),
+        weight: tx.weight(),
+        vsize,
+        fee,
+        fee_rate: FeeRate::from_fee(fee, vsize).unwrap_or_default(),
+        tx,
+    })
+}
+
+/// Broadcasts the transaction to the network.
+pub fn broadcast_transaction(client: &RpcClient, hex: String) -> Result<String, Error> {
+    let body = json!([hex]);
+
+    client.call("sendrawtransaction", &body)
+}
+
+/// How many confirmations the transaction `txid` has, with the verbose
+/// getrawtransaction, or with gettransaction from the default wallet once
+/// the node only finds it in blocks with `-txindex`.
+///
+/// Returns `None` if neither knows the transaction, and `Some(0)` while it
+/// waits in the mempool.
+pub fn transaction_confirmations(client: &RpcClient, txid: &str) -> Result<Option<u32>, Error> {
+    let body = json!([txid, true]);
+
+    let transaction: Value = match client.call("getrawtransaction", &body) {
+        Ok(transaction) => transaction,
+        Err(e) if e.rpc_code() == Some(RpcCode::InvalidAddressOrKey) => {
+            match client.call_wallet("gettransaction", &json!([txid])) {
+                Ok(transaction) => transaction,
+                Err(Error::Config(_)) => return Ok(None),
+                Err(e) if e.rpc_code() == Some(RpcCode::InvalidAddressOrKey) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    // Conflicted wallet transactions have negative confirmations.
+    Ok(Some(
+        transaction["confirmations"].as_i64().unwrap_or(0).max(0) as u32,
+    ))
+}
+
+/// The output `outpoint` if it is unspent, looked up in the node's UTXO set
+/// and the mempool, so outputs spent by a mempool transaction are `None`.
+pub fn get_tx_out(client: &RpcClient, outpoint: OutPoint) -> Result<Option<TxOutResponse>, Error> {
+    let body = json!([outpoint.txid.to_string(), outpoint.vout, true]);
+
+    client.call("gettxout", &body)
+}
+
+/// Decodes a PSBT into the node's JSON representation.
+pub fn decode_psbt(client: &RpcClient, psbt: String) -> Result<Value, Error> {
+    let body = json!([psbt]);
+
+    client.call("decodepsbt", &body)
+}
+
+/// Analyzes the PSBT with the node's analyzepsbt, reporting what each input
+/// is missing and the estimated size and fee rate.
+pub fn analyze_psbt(client: &RpcClient, psbt: String) -> Result<AnalyzedPsbt, Error> {
+    let body = json!([psbt]);
+
+    client.call("analyzepsbt", &body)
+}
+
+/// Summarizes the PSBT with the local parser, completed by the node: its
+/// network for addresses, `analyzepsbt` for missing data and size estimates,
+/// and, when a wallet is configured, which outputs belong to it.
+pub fn inspect_psbt(client: &RpcClient, psbt: String) -> Result<PsbtSummary, Error> {
+    let parsed = PartiallySignedTransaction::from_base64(&psbt)?;
+    let mut summary = PsbtSummary::new(&parsed, client.network()?);
+    let analysis = analyze_psbt(client, psbt)?;
+
+    for (input, analyzed) in summary.inputs.iter_mut().zip(&analysis.inputs) {
+        input.finalized = analyzed.is_final;
+        if !analyzed.has_utxo {
+            input.missing = vec!["missing the spent output".to_string()];
+        } else if let Some(missing) = &analyzed.missing {
+            input.missing = missing.descriptions();
+        } else if analyzed.is_final {
+            input.missing.clear();
+        }
+    }
+    summary.fee = summary.fee.or(analysis.fee);
+    if summary.vsize.is_none() {
+        summary.vsize = analysis.estimated_vsize;
+        summary.fee_rate = analysis
+            .estimated_feerate
+            .map(|rate| FeeRate::from_sat_per_kvb(rate.to_sat()));
+    }
+    summary.next = Some(analysis.next);
+
+    if client.config().wallet.is_some() {
+        for output in &mut summary.outputs {
+            if let Some(address) = &output.address {
+                let info = get_address_info(client, address)?;
+                output.is_mine = Some(info.ismine);
+                output.is_change = Some(info.ischange);
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::psbt::{Incomplete, Missing};
+    use crate::transaction::{Script, Transaction, TxIn, TxOut};
+
+    const TXID: &str = "7b1eabe0209b1fe794124575ef807057c77ada2138ae4fa8d6c4de0398a14f3f";
+
+    fn recipients() -> Vec<Recipient> {
+        vec![Recipient::address(
+            "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080",
+            Amount::from_sat(10_000),
+        )]
+    }
+
+    #[test]
+    fn distinct_inputs_are_accepted() {
+        let inputs = vec![Input::new(TXID, 0), Input::new(TXID, 1)];
+        assert!(validate_psbt_request(&inputs, &recipients()).is_ok());
+    }
+
+    #[test]
+    fn duplicate_inputs_are_rejected_regardless_of_txid_case() {
+        let inputs = vec![Input::new(TXID, 0), Input::new(TXID.to_uppercase(), 0)];
+        match validate_psbt_request(&inputs, &recipients()) {
+            Err(Error::InvalidRequest(message)) => {
+                assert_eq!(message, format!("input {}:0 is spent twice", TXID))
+            }
+            other => panic!("expected a duplicate input error, got {:?}", other),
+        }
+    }
+
+    fn taproot_psbt(signed: &[bool]) -> PartiallySignedTransaction {
+        let script_pubkey = Script([[0x51, 0x20].as_slice(), &[7; 32]].concat());
+        let mut psbt = PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: (0..signed.len() as u32)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(TXID.parse().unwrap(), vout),
+                    script_sig: Script::new(),
+                    sequence: 0xffff_fffd,
+                    witness: Vec::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: script_pubkey.clone(),
+            }],
+        })
+        .unwrap();
+        for (input, &signed) in psbt.inputs.iter_mut().zip(signed) {
+            input.witness_utxo = Some(TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: script_pubkey.clone(),
+            });
+            if signed {
+                input.tap_key_sig = Some(vec![1; 64]);
+            }
+        }
+        psbt
+    }
+
+    #[test]
+    fn offline_finalizing_a_complete_psbt_returns_the_transaction() {
+        let psbt = taproot_psbt(&[true, true]);
+        let finalized = finalize_psbt_offline(psbt.to_base64()).unwrap();
+        assert!(finalized.complete);
+        assert_eq!(finalized.psbt, None);
+        assert!(finalized.incomplete.is_empty());
+
+        let tx = Transaction::deserialize(&hex::decode(&finalized.hex).unwrap()).unwrap();
+        assert_eq!(tx.input[0].witness, vec![vec![1; 64]]);
+        assert_eq!(tx.input[1].witness, vec![vec![1; 64]]);
+    }
+
+    #[test]
+    fn offline_finalizing_an_incomplete_psbt_returns_the_psbt() {
+        let psbt = taproot_psbt(&[true, false]);
+        let finalized = finalize_psbt_offline(psbt.to_base64()).unwrap();
+        assert!(!finalized.complete);
+        assert_eq!(finalized.hex, "");
+        assert_eq!(
+            finalized.incomplete,
+            vec![Incomplete {
+                index: 1,
+                missing: vec![Missing::TapKeySig],
+            }]
+        );
+
+        // The inputs that could be finalized are, the others are unchanged.
+        let partial = PartiallySignedTransaction::from_base64(&finalized.psbt.unwrap()).unwrap();
+        assert!(partial.inputs[0].is_finalized());
+        assert_eq!(partial.inputs[1], psbt.inputs[1]);
+    }
+
+    #[test]
+    fn extracting_a_finalized_psbt() {
+        let mut psbt = taproot_psbt(&[true, true]);
+        assert!(psbt.finalize().is_empty());
+        let extracted = extract_transaction(psbt.to_base64()).unwrap();
+
+        let bytes = hex::decode(&extracted.hex).unwrap();
+        assert_eq!(Transaction::deserialize(&bytes).unwrap(), extracted.tx);
+        assert_eq!(extracted.txid, extracted.tx.txid());
+        assert_eq!(extracted.wtxid, extracted.tx.wtxid());
+        assert_ne!(extracted.txid, extracted.wtxid);
+        assert_eq!(extracted.txid, psbt.unsigned_tx.txid());
+        let base = extracted.tx.serialize_no_witness().len() as u64;
+        assert_eq!(extracted.weight, base * 3 + bytes.len() as u64);
+        assert_eq!(extracted.vsize, extracted.weight.div_ceil(4));
+        assert_eq!(extracted.fee, Amount::from_sat(10_000));
+    }
+
+    #[test]
+    fn extracting_needs_every_input_finalized() {
+        let psbt = taproot_psbt(&[true, true]);
+        assert!(matches!(
+            extract_transaction(psbt.to_base64()),
+            Err(Error::Psbt(psbt::Error::NotFinalized(0)))
+        ));
+    }
+}